@@ -3,34 +3,139 @@ use directories::{BaseDirs, ProjectDirs};
 use glib::{ControlFlow, LogLevels, MainContext, Propagation};
 use gtk::gdk;
 use gtk::gdk_pixbuf::{InterpType, Pixbuf};
+use gtk::gio;
 use gtk::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::env;
 use std::fs;
 use std::io::{self, BufRead, BufReader, Write};
 #[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+#[cfg(unix)]
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
+#[cfg(unix)]
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{self, Child, Command, Stdio};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::{Duration, Instant};
 use tray_icon::menu::{Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem};
-use tray_icon::{Icon, TrayIconBuilder};
+use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
 use zeroize::Zeroizing;
 
 const APP_NAME: &str = "givetray";
 const DEFAULT_PROFILE: &str = "default";
 const DEFAULT_COMMAND: &str = "echo configure command";
+const CONFIG_VERSION: u32 = 1;
+const CONFIG_FILE_MODE: u32 = 0o600;
+const GLOBAL_CONFIG_FILE_NAME: &str = "config.toml";
+const MAX_EXTENDS_DEPTH: usize = 8;
+const DEFAULT_RESTART_DELAY_SECS: u64 = 3;
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_HISTORY_RUNS: usize = 20;
+const HISTORY_DIR_NAME: &str = "history";
+const HISTORY_FILE_NAME: &str = "runs.toml";
+const DEPENDENCY_START_TIMEOUT: Duration = Duration::from_secs(30);
+const READY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// `CONFIG_MIGRATIONS[n]` upgrades a version `n` profile table to version `n + 1`.
+const CONFIG_MIGRATIONS: &[fn(&mut toml::Table)] = &[migrate_config_v0_to_v1];
 const MAX_LOG_LINES: usize = 5000;
+const MAX_LOG_LINE_BYTES: usize = 16 * 1024;
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SINK_RETRY_MIN_DELAY: Duration = Duration::from_secs(5);
+const SINK_RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+const SYSLOG_FACILITY_USER: u8 = 1;
 const MAX_UNDO: usize = 200;
 const MAX_COMMAND_LENGTH: usize = 8192;
 const MAX_PROFILE_LENGTH: usize = 128;
 const ICON_FILE_NAME: &str = "icon.png";
 const BUNDLED_ICON_FILE_NAME: &str = "default-icon.png";
+const BUNDLE_FORMAT: u32 = 1;
+const BUNDLE_MANIFEST_NAME: &str = "manifest.toml";
+const BUNDLE_PROFILE_NAME: &str = "profile.toml";
+const BUNDLE_ICON_NAME: &str = "icon.png";
+const MAX_BUNDLE_SIZE: u64 = 16 * 1024 * 1024;
+const TAR_BLOCK_SIZE: usize = 512;
 const BG_CHILD_ENV: &str = "GIVETRAY_BG_CHILD";
+const CONFIG_RELOAD_DELAY: Duration = Duration::from_millis(250);
+const INITIAL_ACTION_ENV: &str = "GIVETRAY_INITIAL_ACTION";
+/// Set on `sudo -A` children so that givetray, run by sudo as `SUDO_ASKPASS`,
+/// acts as a password prompt instead of starting the tray.
+const ASKPASS_ARG: &str = "--askpass";
+const ASKPASS_WRAPPER_NAME: &str = "askpass";
+const SUDO_PROMPT: &str = "Enter sudo password to start this command:";
+/// Passed to `sudo -p` so sudo's own prompts can be told apart from command output.
+const SUDO_PROMPT_MARKER: &str = "[givetray-sudo]";
+/// Matches sudo's default `passwd_tries`.
+const SUDO_PASSWORD_TRIES: u32 = 3;
+/// How long sudo gets to reject a password before stdin is closed for the command.
+const SUDO_AUTH_GRACE: Duration = Duration::from_secs(5);
+const PTY_COLUMNS: u16 = 120;
+const PTY_ROWS: u16 = 40;
+const PTY_TERM: &str = "xterm-256color";
+const ANSI_PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+const SECRET_SERVICE_NAME: &str = "org.freedesktop.secrets";
+const SECRET_SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const SECRET_SERVICE_IFACE: &str = "org.freedesktop.Secret.Service";
+const SECRET_COLLECTION_IFACE: &str = "org.freedesktop.Secret.Collection";
+const SECRET_ITEM_IFACE: &str = "org.freedesktop.Secret.Item";
+const SECRET_SESSION_IFACE: &str = "org.freedesktop.Secret.Session";
+const SECRET_PROMPT_IFACE: &str = "org.freedesktop.Secret.Prompt";
+/// D-Bus address of an alternative secret service, e.g. a stand-in used for testing.
+const SECRET_SERVICE_BUS_ENV: &str = "GIVETRAY_SECRET_SERVICE_BUS";
+const SECRET_PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+/// Secret name under which the sudo password of a profile is stored.
+const SUDO_SECRET_NAME: &str = "sudo";
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+const DESKTOP_EXEC_RESERVED: &str = "\"'\\><~|&;$*?#()`";
+const DESKTOP_EXEC_FIELD_CODES: &str = "fFuUickdDnNvm%";
+const DESKTOP_BOOLEAN_KEYS: &[&str] = &[
+    "NoDisplay",
+    "Hidden",
+    "DBusActivatable",
+    "Terminal",
+    "StartupNotify",
+    "PrefersNonDefaultGPU",
+    "SingleMainWindow",
+];
+const SYSTEMD_UNIT_PREFIX: &str = "givetray-";
+const SYSTEMD_RUN_UNIT_SUFFIX: &str = "-run";
+const SYSTEMD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SYSTEMD_POLL_MAX_INTERVAL: Duration = Duration::from_secs(30);
+const SYSTEMD_PASSTHROUGH_ENV: &[&str] = &[
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+    "XDG_RUNTIME_DIR",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "PATH",
+];
 
 #[derive(Debug, Clone)]
 struct CliOptions {
@@ -47,11 +152,64 @@ enum CliMode {
     DesktopFile {
         output_dir: Option<PathBuf>,
         autostart: bool,
+        check: bool,
+    },
+    SystemdUnit {
+        action: UnitFileAction,
     },
+    Control {
+        action: Option<ControlAction>,
+    },
+    Profile {
+        action: ProfileAction,
+        target: Option<String>,
+    },
+    Secret {
+        action: SecretAction,
+        name: Option<String>,
+    },
+    Check,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SecretAction {
+    Set,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProfileAction {
+    List,
+    Show { effective: bool },
+    Create,
+    Delete,
+    Rename,
+    Copy,
+    Export,
+    Import,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "&'static str")]
+enum ControlAction {
+    Start,
+    Stop,
+    Restart,
+    Logs,
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnitFileAction {
+    Write,
+    Enable,
+    Disable,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 struct Config {
+    #[serde(default)]
+    version: u32,
     command: String,
     #[serde(default)]
     autostart: bool,
@@ -61,12 +219,442 @@ struct Config {
     log_to_file: bool,
     #[serde(default)]
     log_file_path: Option<String>,
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    limits: ResourceLimits,
+    #[serde(default)]
+    backend: BackendKind,
+    #[serde(default, skip_serializing_if = "DesktopSettings::is_empty")]
+    desktop: DesktopSettings,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    hooks: Hooks,
+    #[serde(default)]
+    restart_on_change: bool,
+    #[serde(default)]
+    elevation: ElevationMode,
+    #[serde(default)]
+    secret_service: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extends: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    requires: Vec<String>,
+    #[serde(default)]
+    stop_dependents: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ready_check: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    vars: BTreeMap<String, String>,
+    #[serde(default)]
+    restart: RestartPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    restart_delay_secs: Option<u64>,
+    #[serde(default)]
+    keep_stdin_open: bool,
+    #[serde(default)]
+    pty: bool,
+    #[serde(default)]
+    log_final_progress_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "LogSinks::is_empty")]
+    log_sinks: LogSinks,
+    #[serde(default)]
+    log_format: LogFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history_runs: Option<usize>,
+    #[serde(flatten)]
+    extra: toml::Table,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            command: DEFAULT_COMMAND.to_string(),
+            autostart: false,
+            icon_path: None,
+            log_to_file: false,
+            log_file_path: None,
+            limits: ResourceLimits::default(),
+            backend: BackendKind::default(),
+            desktop: DesktopSettings::default(),
+            hooks: Hooks::default(),
+            restart_on_change: false,
+            elevation: ElevationMode::default(),
+            secret_service: false,
+            extends: None,
+            requires: Vec::new(),
+            stop_dependents: false,
+            ready_check: None,
+            env: BTreeMap::new(),
+            vars: BTreeMap::new(),
+            restart: RestartPolicy::default(),
+            restart_delay_secs: None,
+            keep_stdin_open: false,
+            pty: false,
+            log_final_progress_only: false,
+            log_encoding: None,
+            log_sinks: LogSinks::default(),
+            log_format: LogFormat::default(),
+            history_runs: None,
+            extra: toml::Table::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+struct DesktopSettings {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    comment: Option<String>,
+    #[serde(default)]
+    keywords: Option<Vec<String>>,
+    #[serde(default)]
+    startup_wm_class: Option<String>,
+    #[serde(default)]
+    actions: Option<Vec<ControlAction>>,
+    #[serde(flatten)]
+    extra: toml::Table,
+}
+
+impl DesktopSettings {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// One start of the command, as kept in the profile's run history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct RunRecord {
+    id: String,
+    started: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ended: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    /// Set when requires or pre_start failed and the command never ran.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    not_started: bool,
+    /// File name of the run's output, relative to the history directory.
+    log: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct RunHistory {
+    #[serde(default)]
+    runs: Vec<RunRecord>,
+}
+
+/// Line format of the profile's log file.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, see `json_line_record` and `json_event_record`.
+    Jsonl,
+}
+
+/// Destinations besides the Logs window and log file that receive every log line.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+struct LogSinks {
+    #[serde(default)]
+    journald: bool,
+    #[serde(default)]
+    syslog: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    syslog_socket: Option<String>,
+    #[serde(flatten)]
+    extra: toml::Table,
+}
+
+impl LogSinks {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Commands run around the lifetime of the main command.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pre_start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pre_stop: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_exit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+    #[serde(flatten)]
+    extra: toml::Table,
+}
+
+impl Hooks {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn command(&self, kind: HookKind) -> Option<&str> {
+        match kind {
+            HookKind::PreStart => self.pre_start.as_deref(),
+            HookKind::PostStart => self.post_start.as_deref(),
+            HookKind::PreStop => self.pre_stop.as_deref(),
+            HookKind::PostExit => self.post_exit.as_deref(),
+        }
+        .filter(|command| !command.trim().is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HookKind {
+    PreStart,
+    PostStart,
+    PreStop,
+    PostExit,
+}
+
+impl HookKind {
+    const ALL: [HookKind; 4] = [
+        HookKind::PreStart,
+        HookKind::PostStart,
+        HookKind::PreStop,
+        HookKind::PostExit,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            HookKind::PreStart => "pre_start",
+            HookKind::PostStart => "post_start",
+            HookKind::PreStop => "pre_stop",
+            HookKind::PostExit => "post_exit",
+        }
+    }
+}
+
+/// A hook command resolved on the GTK thread so it can run on a worker thread.
+struct PreparedHook {
+    kind: HookKind,
+    args: Zeroizing<Vec<String>>,
+    env: BTreeMap<String, String>,
+    timeout: Duration,
+    log_encoding: Option<String>,
+}
+
+/// How a command starting with `sudo` gets its privileges.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum ElevationMode {
+    /// Prompt in givetray and pipe the password to `sudo -S`.
+    #[default]
+    SudoStdin,
+    /// Replace `sudo` with `pkexec` and let the desktop's polkit agent ask.
+    Pkexec,
+    /// Run `sudo -A` with givetray itself as the `SUDO_ASKPASS` helper.
+    Askpass,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+/// What to do once a stop requested by the tray has finished, including the
+/// post_exit hook.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum AfterStop {
+    #[default]
+    Nothing,
+    Restart,
+    Quit,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum BackendKind {
+    #[default]
+    Direct,
+    Systemd,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+struct ResourceLimits {
+    #[serde(default)]
+    address_space_mb: Option<u64>,
+    #[serde(default)]
+    open_files: Option<u64>,
+    #[serde(default)]
+    cpu_seconds: Option<u64>,
+    #[serde(default)]
+    nice: Option<i32>,
+    #[serde(default)]
+    ionice_class: Option<String>,
+    #[serde(default)]
+    ionice_level: Option<u8>,
+    #[serde(flatten)]
+    extra: toml::Table,
+}
+
+impl ResourceLimits {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ProcessLimits {
+    address_space: Option<libc::rlim_t>,
+    open_files: Option<libc::rlim_t>,
+    cpu_seconds: Option<libc::rlim_t>,
+    nice: Option<libc::c_int>,
+    ioprio: Option<libc::c_int>,
+}
+
+struct LaunchSpec {
+    unit_name: String,
+    args: Zeroizing<Vec<String>>,
+    limits: ProcessLimits,
+    env: BTreeMap<String, String>,
+    sudo_password: Option<Zeroizing<String>>,
+    /// Set while sudo may still ask for the password again; the stderr
+    /// reader only treats a repeated prompt as a rejection while it is set.
+    sudo_armed: Arc<AtomicBool>,
+    keep_stdin_open: bool,
+    pty: bool,
+    log_encoding: Option<String>,
+}
+
+/// Launches a profile command and hands back a handle to the running instance.
+trait CommandBackend {
+    fn spawn(
+        &self,
+        spec: LaunchSpec,
+        ui_tx: &Sender<UiEvent>,
+    ) -> Result<Box<dyn RunningCommand>, String>;
+}
+
+/// A command started by a [`CommandBackend`].
+///
+/// Exit codes are `None` when the command was killed by a signal or the backend
+/// could not determine how it ended.
+trait RunningCommand: Send {
+    fn try_wait(&mut self) -> io::Result<Option<Option<i32>>>;
+    fn terminate(&mut self, timeout: Duration) -> Option<i32>;
+
+    fn write_stdin(&mut self, _data: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "command has no stdin pipe",
+        ))
+    }
+
+    fn close_stdin(&mut self) {}
+
+    fn has_stdin(&self) -> bool {
+        false
+    }
+
+    fn is_terminal(&self) -> bool {
+        false
+    }
+
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    fn signal(&mut self, _signal: libc::c_int) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "command cannot be signalled",
+        ))
+    }
+}
+
+/// Control input the Logs window can send to a running command.
+#[derive(Debug, Clone, Copy)]
+enum StdinControl {
+    Interrupt,
+    Quit,
+    Eof,
+    Escape,
+    Tab,
+}
+
+impl StdinControl {
+    /// The byte a terminal line discipline turns into this control action.
+    fn terminal_byte(self) -> u8 {
+        match self {
+            StdinControl::Interrupt => 0x03,
+            StdinControl::Quit => 0x1c,
+            StdinControl::Eof => 0x04,
+            StdinControl::Escape => 0x1b,
+            StdinControl::Tab => b'\t',
+        }
+    }
+
+    const ALL: [StdinControl; 5] = [
+        StdinControl::Interrupt,
+        StdinControl::Quit,
+        StdinControl::Eof,
+        StdinControl::Escape,
+        StdinControl::Tab,
+    ];
+
+    /// Whether this is sent over stdin even without a terminal. Interrupt and
+    /// quit become signals instead.
+    fn needs_input(self) -> bool {
+        !matches!(self, StdinControl::Interrupt | StdinControl::Quit)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            StdinControl::Interrupt => "Ctrl+C (interrupt)",
+            StdinControl::Quit => "Ctrl+\\ (quit)",
+            StdinControl::Eof => "Ctrl+D (end of input)",
+            StdinControl::Escape => "Escape",
+            StdinControl::Tab => "Tab",
+        }
+    }
+}
+
+struct DirectBackend;
+
+struct SystemdBackend;
+
+struct SystemdUnit {
+    unit: String,
+    journal: Option<Child>,
+    /// Exit code from the watcher thread once the unit is no longer active.
+    status: mpsc::Receiver<io::Result<Option<i32>>>,
+    /// Tells the watcher thread to stop once the unit is no longer tracked.
+    released: Arc<AtomicBool>,
+}
+
+struct ControlRequest {
+    action: ControlAction,
+    reply: mpsc::Sender<String>,
 }
 
 enum UiEvent {
     AppendLog(String),
+    Output {
+        stream: LogStream,
+        segment: LogSegment,
+    },
     ProcessExited(Option<i32>),
     SetRunning(bool),
+    SudoPrompted,
+    SudoPasswordRejected,
+    StdinFailed(io::Error),
 }
 
 struct AppState {
@@ -77,15 +665,63 @@ struct AppState {
     saved_icon_path: Option<String>,
     saved_log_to_file: bool,
     saved_log_file_path: Option<String>,
-    child: Option<Child>,
+    saved_limits: ResourceLimits,
+    saved_backend: BackendKind,
+    saved_desktop: DesktopSettings,
+    saved_hooks: Hooks,
+    saved_restart_on_change: bool,
+    saved_elevation: ElevationMode,
+    saved_secret_service: bool,
+    run_secrets: BTreeMap<String, Zeroizing<String>>,
+    pending_sudo_secret: Option<Zeroizing<String>>,
+    saved_extends: Option<String>,
+    saved_requires: Vec<String>,
+    saved_stop_dependents: bool,
+    saved_ready_check: Option<String>,
+    saved_env: BTreeMap<String, String>,
+    saved_vars: BTreeMap<String, String>,
+    last_prompt_values: BTreeMap<String, String>,
+    saved_restart: RestartPolicy,
+    saved_restart_delay_secs: Option<u64>,
+    saved_keep_stdin_open: bool,
+    saved_pty: bool,
+    saved_log_final_progress_only: bool,
+    saved_log_encoding: Option<String>,
+    saved_log_sinks: LogSinks,
+    saved_log_format: LogFormat,
+    saved_history_runs: Option<usize>,
+    run_id: Option<String>,
+    run_pid: Option<u32>,
+    run_log_path: Option<PathBuf>,
+    log_forwarder: LogForwarder,
+    saved_extra: toml::Table,
+    stop_requested: bool,
+    stopping: bool,
+    after_stop: AfterStop,
+    /// Whether `ready_check` has passed for the running command.
+    ready: bool,
+    starting: bool,
+    sudo_attempts: u32,
+    /// The password for sudo's first prompt, held until sudo asks for it.
+    sudo_password: Option<Zeroizing<String>>,
+    sudo_stdin_generation: u64,
+    sudo_auth_failed: bool,
+    sudo_armed: Arc<AtomicBool>,
+    child: Option<Box<dyn RunningCommand>>,
     log_lines: VecDeque<String>,
+    log_last_line_offset: i32,
+    log_progress: Option<(LogStream, String)>,
     log_file_path: Option<PathBuf>,
     logs_window: gtk::Window,
     logs_view: gtk::TextView,
     logs_buffer: gtk::TextBuffer,
     logs_clear_button: gtk::Button,
     logs_copy_button: gtk::Button,
+    logs_history_button: gtk::Button,
     logs_status_label: gtk::Label,
+    logs_input_entry: gtk::Entry,
+    logs_send_button: gtk::Button,
+    logs_control_button: gtk::MenuButton,
     about_window: gtk::Window,
     config_window: gtk::Window,
     config_view: gtk::TextView,
@@ -103,11 +739,23 @@ struct AppState {
     config_last: String,
     config_ignore: bool,
     start_stop_item: MenuItem,
+    tray: TrayIcon,
     config_path: PathBuf,
+    config_monitors: Vec<(PathBuf, gio::FileMonitor)>,
+    config_reload_pending: bool,
+    /// The control socket this instance bound, removed again on quit.
+    control_socket_path: Option<PathBuf>,
 }
 
 fn main() {
     install_log_filters();
+    if env::args().nth(1).as_deref() == Some(ASKPASS_ARG) {
+        run_askpass_helper();
+    }
+    let initial_action = env::var(INITIAL_ACTION_ENV)
+        .ok()
+        .and_then(|value| ControlAction::parse(&value));
+    env::remove_var(INITIAL_ACTION_ENV);
 
     let cli = parse_cli_args().unwrap_or_else(|err| {
         eprintln!("{err}");
@@ -119,8 +767,53 @@ fn main() {
         CliMode::DesktopFile {
             output_dir,
             autostart,
+            check,
         } => {
-            if let Err(err) = create_desktop_file_from_cli(&cli, output_dir, autostart) {
+            let result = if check {
+                check_desktop_files_from_cli(&cli, output_dir)
+            } else {
+                create_desktop_file_from_cli(&cli, output_dir, autostart)
+            };
+            if let Err(err) = result {
+                eprintln!("{err}");
+                process::exit(1);
+            }
+            return;
+        }
+        CliMode::SystemdUnit { action } => {
+            if let Err(err) = create_systemd_unit_from_cli(&cli, action) {
+                eprintln!("{err}");
+                process::exit(1);
+            }
+            return;
+        }
+        CliMode::Control { action } => {
+            let action = action.unwrap_or(ControlAction::Status);
+            match send_control_action(&cli.profile, action) {
+                Ok(reply) => println!("{reply}"),
+                Err(err) => {
+                    eprintln!("{err}");
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        CliMode::Profile { action, target } => {
+            if let Err(err) = run_profile_command(&cli, action, target.as_deref()) {
+                eprintln!("{err}");
+                process::exit(1);
+            }
+            return;
+        }
+        CliMode::Secret { action, name } => {
+            if let Err(err) = run_secret_command(&cli.profile, action, name.as_deref()) {
+                eprintln!("{err}");
+                process::exit(1);
+            }
+            return;
+        }
+        CliMode::Check => {
+            if let Err(err) = check_profile_from_cli(&cli) {
                 eprintln!("{err}");
                 process::exit(1);
             }
@@ -131,7 +824,20 @@ fn main() {
 
     let config_path =
         config_path_for_profile(&cli.profile).expect("failed to resolve configuration path");
-    let mut config = load_or_create_config(&config_path);
+    let (mut config, config_error) = match load_or_create_config(&config_path) {
+        Ok(config) => (config, None),
+        Err(err) => {
+            if cli.command_override.is_some() || cli.icon_source.is_some() || cli.log_file.is_some()
+            {
+                eprintln!("{err}");
+                eprintln!(
+                    "fix the profile file or run `{APP_NAME} check` before applying overrides"
+                );
+                process::exit(1);
+            }
+            (Config::default(), Some(err))
+        }
+    };
 
     match apply_cli_overrides_to_config(&mut config, &cli) {
         Ok(true) => {
@@ -168,7 +874,11 @@ fn main() {
         logs_buffer,
         logs_clear_button,
         logs_copy_button,
+        logs_history_button,
         logs_status_label,
+        logs_input_entry,
+        logs_send_button,
+        logs_control_button,
     ) = build_logs_window();
     let (
         config_window,
@@ -224,7 +934,7 @@ fn main() {
 
     let tray_icon = load_tray_icon(&config).expect("failed to load tray icon");
     let tooltip = format!("{APP_NAME} ({})", cli.profile);
-    let _tray = TrayIconBuilder::new()
+    let tray = TrayIconBuilder::new()
         .with_menu(Box::new(tray_menu))
         .with_tooltip(&tooltip)
         .with_icon(tray_icon)
@@ -239,15 +949,61 @@ fn main() {
         saved_icon_path: config.icon_path.clone(),
         saved_log_to_file: config.log_to_file,
         saved_log_file_path: config.log_file_path.clone(),
+        saved_limits: config.limits.clone(),
+        saved_backend: config.backend,
+        saved_desktop: config.desktop.clone(),
+        saved_hooks: config.hooks.clone(),
+        saved_restart_on_change: config.restart_on_change,
+        saved_elevation: config.elevation,
+        saved_secret_service: config.secret_service,
+        run_secrets: BTreeMap::new(),
+        pending_sudo_secret: None,
+        saved_extends: config.extends.clone(),
+        saved_requires: config.requires.clone(),
+        saved_stop_dependents: config.stop_dependents,
+        saved_ready_check: config.ready_check.clone(),
+        saved_env: config.env.clone(),
+        saved_vars: config.vars.clone(),
+        last_prompt_values: BTreeMap::new(),
+        saved_restart: config.restart,
+        saved_restart_delay_secs: config.restart_delay_secs,
+        saved_keep_stdin_open: config.keep_stdin_open,
+        saved_pty: config.pty,
+        saved_log_final_progress_only: config.log_final_progress_only,
+        saved_log_encoding: config.log_encoding.clone(),
+        saved_log_sinks: config.log_sinks.clone(),
+        saved_log_format: config.log_format,
+        saved_history_runs: config.history_runs,
+        run_id: None,
+        run_pid: None,
+        run_log_path: None,
+        log_forwarder: LogForwarder::default(),
+        saved_extra: config.extra.clone(),
+        stop_requested: false,
+        stopping: false,
+        after_stop: AfterStop::Nothing,
+        ready: false,
+        starting: false,
+        sudo_attempts: 0,
+        sudo_password: None,
+        sudo_stdin_generation: 0,
+        sudo_auth_failed: false,
+        sudo_armed: Arc::new(AtomicBool::new(false)),
         child: None,
         log_lines: VecDeque::new(),
+        log_last_line_offset: 0,
+        log_progress: None,
         log_file_path,
         logs_window,
         logs_view,
         logs_buffer,
         logs_clear_button,
         logs_copy_button,
+        logs_history_button,
         logs_status_label,
+        logs_input_entry,
+        logs_send_button,
+        logs_control_button,
         about_window,
         config_window,
         config_view,
@@ -262,10 +1018,14 @@ fn main() {
         config_saved_system_autostart: false,
         config_undo: Vec::new(),
         config_redo: Vec::new(),
-        config_last: config.command,
+        config_last: config.command.clone(),
         config_ignore: false,
         start_stop_item,
+        tray,
         config_path,
+        config_monitors: Vec::new(),
+        config_reload_pending: false,
+        control_socket_path: None,
     }));
 
     {
@@ -281,15 +1041,48 @@ fn main() {
 
     setup_config_handlers(state.clone());
     setup_logs_handlers(state.clone());
-    setup_log_receiver(state.clone(), ui_rx);
+    setup_log_receiver(state.clone(), ui_tx.clone(), ui_rx);
     setup_menu_polling(state.clone(), ui_tx.clone());
     setup_process_watcher(state.clone(), ui_tx.clone());
+    setup_control_server(state.clone(), ui_tx.clone());
+    setup_config_monitor(state.clone(), ui_tx.clone());
+
+    for key in unknown_config_keys(&config) {
+        append_log(
+            &mut state.borrow_mut(),
+            format!("Unknown configuration key '{key}' is kept but ignored"),
+        );
+    }
+
+    if let Some(err) = config_error {
+        append_log(&mut state.borrow_mut(), err.clone());
+        append_log(
+            &mut state.borrow_mut(),
+            "Using default settings; the broken file is backed up before it is overwritten"
+                .to_string(),
+        );
+        show_config_error_dialog(&err);
+    }
+
+    if config.backend == BackendKind::Systemd {
+        attach_systemd_unit(state.clone(), &ui_tx);
+    }
+
+    if config.autostart && state.borrow().child.is_none() {
+        start_command(state.clone(), ui_tx.clone());
+    }
 
-    if config.autostart {
-        start_command(state.clone(), ui_tx);
+    if let Some(action) = initial_action {
+        handle_control_action(state.clone(), ui_tx, action);
     }
 
     gtk::main();
+
+    // A stale socket would only be cleaned up by the next instance's bind.
+    let socket_path = state.borrow_mut().control_socket_path.take();
+    if let Some(path) = socket_path {
+        let _ = fs::remove_file(path);
+    }
 }
 
 fn detach_to_background_if_needed(profile: &str) -> Result<(), String> {
@@ -361,8 +1154,49 @@ fn parse_cli_args() -> Result<CliOptions, String> {
         mode = CliMode::DesktopFile {
             output_dir: None,
             autostart: false,
+            check: false,
+        };
+        args.remove(0);
+    } else if args.first().is_some_and(|arg| arg == "systemd-unit") {
+        mode = CliMode::SystemdUnit {
+            action: UnitFileAction::Write,
+        };
+        args.remove(0);
+    } else if args.first().is_some_and(|arg| arg == "ctl") {
+        mode = CliMode::Control { action: None };
+        args.remove(0);
+    } else if args.first().is_some_and(|arg| arg == "check") {
+        mode = CliMode::Check;
+        args.remove(0);
+    } else if args.first().is_some_and(|arg| arg == "secret") {
+        args.remove(0);
+        let action = match args.first().map(String::as_str) {
+            Some("set") => SecretAction::Set,
+            Some("delete") => SecretAction::Delete,
+            Some(other) => return Err(format!("unknown secret action: {other}")),
+            None => return Err("missing secret action".to_string()),
+        };
+        args.remove(0);
+        mode = CliMode::Secret { action, name: None };
+    } else if args.first().is_some_and(|arg| arg == "profile") {
+        args.remove(0);
+        let action = match args.first().map(String::as_str) {
+            Some("list") => ProfileAction::List,
+            Some("show") => ProfileAction::Show { effective: false },
+            Some("create") => ProfileAction::Create,
+            Some("delete") => ProfileAction::Delete,
+            Some("rename") => ProfileAction::Rename,
+            Some("copy") => ProfileAction::Copy,
+            Some("export") => ProfileAction::Export,
+            Some("import") => ProfileAction::Import,
+            Some(other) => return Err(format!("unknown profile action: {other}")),
+            None => return Err("missing profile action".to_string()),
         };
         args.remove(0);
+        mode = CliMode::Profile {
+            action,
+            target: None,
+        };
     }
 
     let mut profile: Option<String> = None;
@@ -404,7 +1238,7 @@ fn parse_cli_args() -> Result<CliOptions, String> {
                 let value = args
                     .get(i + 1)
                     .ok_or_else(|| "missing value for --log-file".to_string())?;
-                if matches!(mode, CliMode::DesktopFile { .. }) {
+                if !matches!(mode, CliMode::Run) {
                     return Err("--log-file is only valid in app mode".to_string());
                 }
                 log_file = Some(PathBuf::from(value));
@@ -415,38 +1249,182 @@ fn parse_cli_args() -> Result<CliOptions, String> {
                     .get(i + 1)
                     .ok_or_else(|| "missing value for --output-dir".to_string())?;
                 match &mut mode {
-                    CliMode::DesktopFile {
-                        output_dir,
-                        autostart: _,
-                    } => {
+                    CliMode::DesktopFile { output_dir, .. } => {
                         *output_dir = Some(PathBuf::from(value));
                         i += 2;
                     }
-                    CliMode::Run => {
+                    _ => {
                         return Err("--output-dir is only valid with desktop-file".to_string());
                     }
                 }
             }
             "--autostart" => match &mut mode {
-                CliMode::DesktopFile {
-                    output_dir: _,
-                    autostart,
-                } => {
+                CliMode::DesktopFile { autostart, .. } => {
                     *autostart = true;
                     i += 1;
                 }
-                CliMode::Run => {
+                _ => {
                     return Err("--autostart is only valid with desktop-file".to_string());
                 }
             },
+            "--check" => match &mut mode {
+                CliMode::DesktopFile { check, .. } => {
+                    *check = true;
+                    i += 1;
+                }
+                _ => {
+                    return Err("--check is only valid with desktop-file".to_string());
+                }
+            },
+            flag @ ("--enable" | "--disable") => match &mut mode {
+                CliMode::SystemdUnit { action } => {
+                    if *action != UnitFileAction::Write {
+                        return Err("--enable and --disable are mutually exclusive".to_string());
+                    }
+                    *action = if flag == "--enable" {
+                        UnitFileAction::Enable
+                    } else {
+                        UnitFileAction::Disable
+                    };
+                    i += 1;
+                }
+                _ => {
+                    return Err(format!("{flag} is only valid with systemd-unit"));
+                }
+            },
+            value if matches!(mode, CliMode::Control { action: None }) => {
+                let parsed = ControlAction::parse(value)
+                    .ok_or_else(|| format!("unknown ctl action: {value}"))?;
+                mode = CliMode::Control {
+                    action: Some(parsed),
+                };
+                i += 1;
+            }
+            value
+                if matches!(
+                    mode,
+                    CliMode::Profile {
+                        action: ProfileAction::Rename | ProfileAction::Copy,
+                        target: None,
+                    }
+                ) =>
+            {
+                if let CliMode::Profile { target, .. } = &mut mode {
+                    *target = Some(validate_profile_name(value)?);
+                }
+                i += 1;
+            }
+            value
+                if matches!(
+                    mode,
+                    CliMode::Profile {
+                        action: ProfileAction::Import,
+                        target: None,
+                    }
+                ) =>
+            {
+                if let CliMode::Profile { target, .. } = &mut mode {
+                    *target = Some(value.to_string());
+                }
+                i += 1;
+            }
+            value if matches!(mode, CliMode::Secret { name: None, .. }) => {
+                if let CliMode::Secret { name, .. } = &mut mode {
+                    *name = Some(validate_secret_name(value)?);
+                }
+                i += 1;
+            }
+            "-o" | "--output" => {
+                let value = args
+                    .get(i + 1)
+                    .ok_or_else(|| "missing value for -o/--output".to_string())?;
+                match &mut mode {
+                    CliMode::Profile {
+                        action: ProfileAction::Export,
+                        target,
+                    } => {
+                        if target.is_some() {
+                            return Err("-o/--output provided more than once".to_string());
+                        }
+                        *target = Some(value.to_string());
+                        i += 2;
+                    }
+                    _ => {
+                        return Err("-o/--output is only valid with profile export".to_string());
+                    }
+                }
+            }
+            "--effective" => match &mut mode {
+                CliMode::Profile {
+                    action: ProfileAction::Show { effective },
+                    ..
+                } => {
+                    *effective = true;
+                    i += 1;
+                }
+                _ => {
+                    return Err("--effective is only valid with profile show".to_string());
+                }
+            },
             unknown => {
                 return Err(format!("unknown argument: {unknown}"));
             }
         }
     }
 
-    let profile =
-        profile.ok_or_else(|| "missing required -c/--config PROFILE argument".to_string())?;
+    // `desktop-file --check` without a profile checks every givetray entry.
+    let profile = match profile {
+        Some(profile) => profile,
+        None if matches!(mode, CliMode::DesktopFile { check: true, .. }) => String::new(),
+        None if matches!(
+            mode,
+            CliMode::Profile {
+                action: ProfileAction::List | ProfileAction::Import,
+                ..
+            }
+        ) =>
+        {
+            String::new()
+        }
+        None => return Err("missing required -c/--config PROFILE argument".to_string()),
+    };
+
+    if matches!(mode, CliMode::Check)
+        && (command_override.is_some() || icon_source.is_some() || log_file.is_some())
+    {
+        return Err("check does not accept overrides".to_string());
+    }
+
+    if let CliMode::Secret { name, .. } = &mode {
+        if name.is_none() {
+            return Err("missing secret NAME argument".to_string());
+        }
+        if command_override.is_some() || icon_source.is_some() || log_file.is_some() {
+            return Err("secret does not accept overrides".to_string());
+        }
+    }
+
+    if let CliMode::Profile { action, target } = &mode {
+        if matches!(action, ProfileAction::Rename | ProfileAction::Copy) && target.is_none() {
+            return Err("missing NEW_PROFILE argument".to_string());
+        }
+        if *action == ProfileAction::Export && target.is_none() {
+            return Err("missing -o/--output FILE argument".to_string());
+        }
+        if *action == ProfileAction::Import && target.is_none() {
+            return Err("missing bundle FILE argument".to_string());
+        }
+        if *action != ProfileAction::Create && (command_override.is_some() || icon_source.is_some())
+        {
+            return Err("-cmd/--command and --icon are only valid with profile create".to_string());
+        }
+    }
+
+    if matches!(mode, CliMode::Control { .. })
+        && (command_override.is_some() || icon_source.is_some())
+    {
+        return Err("ctl does not accept -cmd/--command or --icon".to_string());
+    }
 
     Ok(CliOptions {
         profile,
@@ -459,7 +1437,7 @@ fn parse_cli_args() -> Result<CliOptions, String> {
 
 fn print_help() {
     println!(
-        "{name}\n\nUsage:\n  {name} -c PROFILE [-cmd COMMAND|--command COMMAND] [--icon ICON_PATH] [--log-file LOG_PATH]\n  {name} desktop-file -c PROFILE [-cmd COMMAND|--command COMMAND] [--output-dir DIR] [--autostart] [--icon ICON_PATH]\n\nOptions:\n  -c, --config PROFILE    Required profile name (letters, numbers, '-' or '_')\n  -cmd, --command COMMAND Set or overwrite saved command for the profile\n      --icon ICON_PATH    Copy icon into the selected profile and update config\n      --log-file LOG_PATH Enable log-to-file and set output path (app mode only)\n      --output-dir DIR    Output directory for desktop file (desktop-file mode only)\n      --autostart         Mark desktop file as autostart and default to ~/.config/autostart\n  -h, --help              Show this help\n  -V, --version           Show version\n",
+        "{name}\n\nUsage:\n  {name} -c PROFILE [-cmd COMMAND|--command COMMAND] [--icon ICON_PATH] [--log-file LOG_PATH]\n  {name} desktop-file -c PROFILE [-cmd COMMAND|--command COMMAND] [--output-dir DIR] [--autostart] [--icon ICON_PATH]\n  {name} desktop-file --check [-c PROFILE] [--output-dir DIR]\n  {name} systemd-unit -c PROFILE [-cmd COMMAND|--command COMMAND] [--icon ICON_PATH] [--enable|--disable]\n  {name} ctl -c PROFILE start|stop|restart|logs|status\n  {name} profile list\n  {name} profile show -c PROFILE [--effective]\n  {name} profile delete -c PROFILE\n  {name} profile create -c PROFILE [-cmd COMMAND|--command COMMAND] [--icon ICON_PATH]\n  {name} profile rename|copy -c PROFILE NEW_PROFILE\n  {name} profile export -c PROFILE -o FILE.tar\n  {name} profile import FILE.tar [-c PROFILE]\n  {name} check -c PROFILE\n  {name} secret set|delete -c PROFILE NAME\n\nOptions:\n  -c, --config PROFILE    Required profile name (letters, numbers, '-' or '_')\n  -cmd, --command COMMAND Set or overwrite saved command for the profile\n      --icon ICON_PATH    Copy icon into the selected profile and update config\n      --log-file LOG_PATH Enable log-to-file and set output path (app mode only)\n      --output-dir DIR    Output directory for desktop file (desktop-file mode only)\n      --autostart         Mark desktop file as autostart and default to ~/.config/autostart\n      --check             Validate existing givetray_*.desktop files (desktop-file mode only)\n      --enable            Enable the generated systemd user service (systemd-unit mode only)\n      --disable           Disable and remove the systemd user service (systemd-unit mode only)\n  -o, --output FILE       Bundle path to write (profile export only)\n  -h, --help              Show this help\n  -V, --version           Show version\n",
         name = APP_NAME,
     );
 }
@@ -553,7 +1531,7 @@ fn create_desktop_file_from_cli(
 ) -> Result<(), String> {
     let config_path = config_path_for_profile(&cli.profile)
         .ok_or_else(|| "unable to resolve configuration path".to_string())?;
-    let mut config = load_or_create_config(&config_path);
+    let mut config = load_or_create_config(&config_path)?;
 
     if apply_cli_overrides_to_config(&mut config, cli)? {
         save_config(&config_path, &config)
@@ -575,7 +1553,15 @@ fn create_desktop_file_from_cli(
             .ok_or_else(|| "unable to resolve Applications desktop path".to_string())?
     };
 
-    let contents = desktop_entry(&exec_path, &icon_path, &cli.profile, autostart);
+    let contents = desktop_entry(
+        &exec_path,
+        &icon_path,
+        &cli.profile,
+        autostart,
+        &config.desktop,
+    );
+    ensure_valid_desktop_entry(&contents)
+        .map_err(|err| format!("generated desktop file is invalid: {err}"))?;
     write_desktop_file(&desktop_path, &contents)
         .map_err(|err| format!("failed to write desktop file: {err}"))?;
 
@@ -583,210 +1569,888 @@ fn create_desktop_file_from_cli(
     Ok(())
 }
 
-fn build_logs_window() -> (
-    gtk::Window,
-    gtk::TextView,
-    gtk::TextBuffer,
-    gtk::Button,
-    gtk::Button,
-    gtk::Label,
-) {
-    let window = gtk::Window::new(gtk::WindowType::Toplevel);
-    window.set_title("Logs");
-    window.set_default_size(820, 520);
+fn check_desktop_files_from_cli(
+    cli: &CliOptions,
+    output_dir: Option<PathBuf>,
+) -> Result<(), String> {
+    let dirs = match output_dir {
+        Some(dir) => vec![dir],
+        None => [applications_dir(), autostart_dir()]
+            .into_iter()
+            .flatten()
+            .collect(),
+    };
 
-    let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
-    let text_view = gtk::TextView::with_buffer(&buffer);
-    text_view.set_editable(false);
-    text_view.set_monospace(true);
-    text_view.set_cursor_visible(false);
+    let mut files = Vec::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(format!("failed to read {}: {err}", dir.display())),
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let matches = if cli.profile.is_empty() {
+                name.starts_with(&format!("{APP_NAME}_")) && name.ends_with(".desktop")
+            } else {
+                name == desktop_file_name(&cli.profile)
+            };
+            if matches {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
 
-    text_view.set_left_margin(8);
-    text_view.set_right_margin(8);
-    text_view.set_top_margin(8);
-    text_view.set_bottom_margin(8);
+    if files.is_empty() {
+        println!("No {APP_NAME} desktop files found");
+        return Ok(());
+    }
 
-    let clear_button = gtk::Button::new();
-    let clear_icon = gtk::Image::from_icon_name(Some("edit-clear"), gtk::IconSize::Button);
-    let clear_label = gtk::Label::new(Some("Clear"));
-    let clear_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    clear_box.pack_start(&clear_icon, false, false, 0);
-    clear_box.pack_start(&clear_label, false, false, 0);
-    clear_button.add(&clear_box);
+    let mut problem_count = 0usize;
+    for path in files {
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+        let problems = validate_desktop_entry(&contents);
+        if problems.is_empty() {
+            println!("{}: ok", path.display());
+        }
+        for problem in &problems {
+            println!("{}: {problem}", path.display());
+        }
+        problem_count += problems.len();
+    }
 
-    let copy_button = gtk::Button::new();
-    let copy_icon = gtk::Image::from_icon_name(Some("edit-copy"), gtk::IconSize::Button);
-    let copy_label = gtk::Label::new(Some("Copy All"));
-    let copy_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    copy_box.pack_start(&copy_icon, false, false, 0);
-    copy_box.pack_start(&copy_label, false, false, 0);
-    copy_button.add(&copy_box);
+    if problem_count > 0 {
+        return Err(format!("{problem_count} problem(s) found"));
+    }
+    Ok(())
+}
 
-    let status_label = gtk::Label::new(Some("0 lines"));
-    status_label.set_halign(gtk::Align::Start);
-    status_label.set_xalign(0.0);
+fn run_profile_command(
+    cli: &CliOptions,
+    action: ProfileAction,
+    target: Option<&str>,
+) -> Result<(), String> {
+    match action {
+        ProfileAction::List => list_profiles(),
+        ProfileAction::Show { effective } => show_profile(&cli.profile, effective),
+        ProfileAction::Create => create_profile(cli),
+        ProfileAction::Delete => delete_profile(&cli.profile),
+        ProfileAction::Rename => transfer_profile(&cli.profile, target.unwrap_or_default(), true),
+        ProfileAction::Copy => transfer_profile(&cli.profile, target.unwrap_or_default(), false),
+        ProfileAction::Export => {
+            export_profile(&cli.profile, Path::new(target.unwrap_or_default()))
+        }
+        ProfileAction::Import => import_profile(
+            Path::new(target.unwrap_or_default()),
+            Some(cli.profile.as_str()).filter(|profile| !profile.is_empty()),
+        ),
+    }
+}
 
-    let actions = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    actions.set_halign(gtk::Align::Fill);
-    actions.set_margin_start(8);
-    actions.set_margin_end(8);
-    actions.set_margin_top(8);
-    actions.set_margin_bottom(4);
-    actions.pack_start(&status_label, true, true, 0);
-    actions.pack_start(&copy_button, false, false, 0);
-    actions.pack_start(&clear_button, false, false, 0);
+fn list_profiles() -> Result<(), String> {
+    let profiles = existing_profiles()?;
+    if profiles.is_empty() {
+        println!("No profiles found");
+        return Ok(());
+    }
+    for profile in profiles {
+        if instance_is_running(&profile) {
+            println!("{profile} (running)");
+        } else {
+            println!("{profile}");
+        }
+    }
+    Ok(())
+}
 
-    let scroller = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
-    scroller.set_hexpand(true);
-    scroller.set_vexpand(true);
-    scroller.add(&text_view);
+fn existing_profiles() -> Result<Vec<String>, String> {
+    let dir = configs_dir().ok_or_else(|| "unable to resolve configuration path".to_string())?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("failed to read {}: {err}", dir.display())),
+    };
 
-    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
-    container.set_hexpand(true);
-    container.set_vexpand(true);
-    container.pack_start(&actions, false, false, 0);
-    container.pack_start(&scroller, true, true, 0);
+    let mut profiles = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+                return None;
+            }
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(str::to_string)
+        })
+        .collect::<Vec<String>>();
+    profiles.sort();
+    Ok(profiles)
+}
 
-    window.add(&container);
-    window.connect_delete_event(|window, _| {
-        window.hide();
-        Propagation::Stop
-    });
+fn show_profile(profile: &str, effective: bool) -> Result<(), String> {
+    let config_path = existing_profile_config(profile)?;
+    let config = load_or_create_config(&config_path)?;
+    let content = fs::read_to_string(&config_path)
+        .map_err(|err| format!("failed to read {}: {err}", config_path.display()))?;
 
-    window.show_all();
-    window.hide();
+    println!("Profile: {profile}");
+    println!("Config: {}", config_path.display());
+    println!(
+        "Status: {}",
+        if instance_is_running(profile) {
+            "running"
+        } else {
+            "stopped"
+        }
+    );
+    if let Some(path) = resolve_log_file_path(profile, &config) {
+        println!("Log file: {}", path.display());
+    }
+    if let Some(icon) = config.icon_path.as_ref() {
+        println!("Icon: {icon}");
+    }
+    for path in [
+        applications_desktop_path(profile),
+        autostart_desktop_path(profile),
+        systemd_service_path(profile),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|path| path.exists())
+    {
+        println!("Entry: {}", path.display());
+    }
+    println!();
+    if effective {
+        let merged = toml::to_string_pretty(&config)
+            .map_err(|err| format!("failed to serialize config: {err}"))?;
+        println!("# Effective configuration (global defaults, extends chain and profile)");
+        print!("{merged}");
+    } else {
+        print!("{content}");
+    }
+    Ok(())
+}
 
-    (
-        window,
-        text_view,
-        buffer,
-        clear_button,
-        copy_button,
-        status_label,
-    )
+fn create_profile(cli: &CliOptions) -> Result<(), String> {
+    let config_path = config_path_for_profile(&cli.profile)
+        .ok_or_else(|| "unable to resolve configuration path".to_string())?;
+    if config_path.exists() {
+        return Err(format!("profile already exists: {}", cli.profile));
+    }
+
+    let mut config = Config::default();
+    apply_cli_overrides_to_config(&mut config, cli)?;
+    save_config(&config_path, &config)?;
+    println!("Profile created: {}", config_path.display());
+    Ok(())
 }
 
-fn build_config_window(
-    profile: &str,
-    command: &str,
-    autostart: bool,
-    log_to_file: bool,
-) -> (
-    gtk::Window,
-    gtk::TextView,
-    gtk::TextBuffer,
-    gtk::CheckButton,
-    gtk::CheckButton,
-    gtk::CheckButton,
-    gtk::CheckButton,
-    gtk::Button,
-    gtk::Label,
-) {
-    let window = gtk::Window::new(gtk::WindowType::Toplevel);
-    window.set_title(&format!("Configuration ({profile})"));
-    window.set_default_size(860, 300);
+fn delete_profile(profile: &str) -> Result<(), String> {
+    let config_path = existing_profile_config(profile)?;
+    if instance_is_running(profile) {
+        return Err(format!(
+            "profile {profile} is running; stop its instance first"
+        ));
+    }
+    // A broken profile can still be deleted; only the custom log notice depends on it.
+    let config = load_or_create_config(&config_path).unwrap_or_default();
 
-    let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
-    buffer.set_text(command);
-    let text_view = gtk::TextView::with_buffer(&buffer);
-    text_view.set_monospace(true);
-    text_view.set_wrap_mode(gtk::WrapMode::WordChar);
-    text_view.set_hexpand(true);
-    text_view.set_vexpand(true);
-    text_view.set_left_margin(8);
-    text_view.set_right_margin(8);
-    text_view.set_top_margin(8);
-    text_view.set_bottom_margin(8);
+    remove_profile_desktop_entries(profile)?;
+    remove_profile_systemd_service(profile)?;
 
-    let label = gtk::Label::new(Some("Command or script"));
-    label.set_halign(gtk::Align::Start);
-    label.set_margin_start(8);
-    label.set_margin_end(8);
-    label.set_margin_top(12);
-    label.set_margin_bottom(4);
+    if let Some(dir) = profile_data_dir(profile) {
+        remove_path_if_exists(&dir)?;
+    }
+    if let Some(log_path) = default_log_file_path(profile) {
+        remove_path_if_exists(&log_path)?;
+    }
+    if let Some(log_path) = config.log_file_path.as_ref() {
+        if default_log_file_path(profile).as_deref() != Some(Path::new(log_path)) {
+            println!("Custom log file kept: {log_path}");
+        }
+    }
 
-    let scroller = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
-    scroller.set_hexpand(true);
-    scroller.set_vexpand(true);
-    scroller.add(&text_view);
+    fs::remove_file(&config_path)
+        .map_err(|err| format!("failed to remove {}: {err}", config_path.display()))?;
+    println!("Profile deleted: {profile}");
+    Ok(())
+}
 
-    let hint = gtk::Label::new(Some("Enter the terminal command to run for this profile."));
-    hint.set_halign(gtk::Align::Start);
-    hint.set_xalign(0.0);
-    hint.set_margin_start(8);
-    hint.set_margin_end(8);
-    hint.set_margin_bottom(4);
+/// Copies or renames a profile, carrying its icon and default log path along.
+/// Renames also move desktop entries and the systemd service to the new name.
+fn transfer_profile(source: &str, target: &str, rename: bool) -> Result<(), String> {
+    let source_config_path = existing_profile_config(source)?;
+    let target_config_path = config_path_for_profile(target)
+        .ok_or_else(|| "unable to resolve configuration path".to_string())?;
+    if target_config_path.exists() {
+        return Err(format!("profile already exists: {target}"));
+    }
+    if rename && instance_is_running(source) {
+        return Err(format!(
+            "profile {source} is running; stop its instance first"
+        ));
+    }
 
-    let autostart_toggle =
-        gtk::CheckButton::with_label("Run command automatically when givetray launches");
-    autostart_toggle.set_active(autostart);
-    autostart_toggle.set_halign(gtk::Align::Start);
-    autostart_toggle.set_tooltip_text(Some(
-        "Runs this profile command when the givetray instance starts.",
-    ));
+    let mut config = load_or_create_config(&source_config_path)?;
 
-    let log_to_file_toggle = gtk::CheckButton::with_label("Write logs to file");
-    log_to_file_toggle.set_active(log_to_file);
-    log_to_file_toggle.set_halign(gtk::Align::Start);
-    log_to_file_toggle.set_tooltip_text(Some(
-        "When enabled, command logs are appended to a profile log file.",
-    ));
+    if let (Some(source_dir), Some(target_dir)) =
+        (profile_data_dir(source), profile_data_dir(target))
+    {
+        if source_dir.exists() {
+            copy_dir_recursive(&source_dir, &target_dir)?;
+            if !rename {
+                remove_path_if_exists(&target_dir.join(HISTORY_DIR_NAME))?;
+            }
+            if let Some(icon) = config.icon_path.as_ref() {
+                if let Ok(relative) = Path::new(icon).strip_prefix(&source_dir) {
+                    config.icon_path =
+                        Some(target_dir.join(relative).to_string_lossy().to_string());
+                }
+            }
+        }
+    }
 
-    let apps_toggle = gtk::CheckButton::with_label("Create Applications entry (.desktop)");
-    apps_toggle.set_halign(gtk::Align::Start);
-    apps_toggle.set_tooltip_text(Some(
-        "Creates or removes ~/.local/share/applications desktop entry for this profile.",
-    ));
+    let source_log = default_log_file_path(source);
+    let target_log = default_log_file_path(target);
+    // An unset log_file_path also means the default file, which then has to
+    // move with the profile even though the config does not change.
+    if source_log.is_some() && resolve_log_file_path(source, &config) == source_log {
+        if config.log_file_path.is_some() {
+            config.log_file_path = target_log
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string());
+        }
+        if rename {
+            if let (Some(from), Some(to)) = (source_log.as_ref(), target_log.as_ref()) {
+                if from.exists() {
+                    fs::rename(from, to)
+                        .map_err(|err| format!("failed to move log file: {err}"))?;
+                }
+            }
+        }
+    }
 
-    let autostart_desktop_toggle =
-        gtk::CheckButton::with_label("Enable desktop session autostart (~/.config/autostart)");
-    autostart_desktop_toggle.set_halign(gtk::Align::Start);
-    autostart_desktop_toggle.set_tooltip_text(Some(
-        "Creates or removes ~/.config/autostart desktop entry for this profile.",
-    ));
+    save_config(&target_config_path, &config)?;
 
-    let save_button = gtk::Button::new();
-    let save_icon = gtk::Image::from_icon_name(Some("media-floppy"), gtk::IconSize::Button);
-    let save_label = gtk::Label::new(Some("Save"));
-    let save_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    save_box.pack_start(&save_icon, false, false, 0);
-    save_box.pack_start(&save_label, false, false, 0);
-    save_button.add(&save_box);
+    if rename {
+        let had_applications = applications_desktop_path(source).is_some_and(|path| path.exists());
+        let had_autostart = autostart_desktop_path(source).is_some_and(|path| path.exists());
+        let had_service = systemd_service_path(source).is_some_and(|path| path.exists());
+        let service_enabled = had_service
+            && run_systemctl_user(&["is-enabled", "--quiet", &systemd_service_name(source)])
+                .is_ok();
 
-    let options = gtk::Box::new(gtk::Orientation::Vertical, 4);
-    options.pack_start(&autostart_toggle, false, false, 0);
-    options.pack_start(&log_to_file_toggle, false, false, 0);
-    options.pack_start(&apps_toggle, false, false, 0);
-    options.pack_start(&autostart_desktop_toggle, false, false, 0);
+        remove_profile_desktop_entries(source)?;
+        remove_profile_systemd_service(source)?;
+        if let Some(dir) = profile_data_dir(source) {
+            remove_path_if_exists(&dir)?;
+        }
+        fs::remove_file(&source_config_path)
+            .map_err(|err| format!("failed to remove {}: {err}", source_config_path.display()))?;
+
+        let exec_path = env::current_exe()
+            .map_err(|err| format!("unable to resolve executable path: {err}"))?;
+        let icon_path = resolve_icon_path_for_desktop(&config)
+            .map_err(|err| format!("unable to resolve icon path: {err}"))?;
+        for (enabled, path, autostart) in [
+            (had_applications, applications_desktop_path(target), false),
+            (had_autostart, autostart_desktop_path(target), true),
+        ] {
+            if let (true, Some(path)) = (enabled, path) {
+                let contents =
+                    desktop_entry(&exec_path, &icon_path, target, autostart, &config.desktop);
+                write_desktop_file(&path, &contents)
+                    .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+                println!("Desktop entry moved: {}", path.display());
+            }
+        }
+        if had_service {
+            if let Some(path) = systemd_service_path(target) {
+                write_unit_file(&path, &systemd_service_unit(&exec_path, target))
+                    .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+                run_systemctl_user(&["daemon-reload"])?;
+                if service_enabled {
+                    run_systemctl_user(&["enable", &systemd_service_name(target)])?;
+                }
+                println!("Systemd unit moved: {}", path.display());
+            }
+        }
+        println!("Profile renamed: {source} -> {target}");
+    } else {
+        println!("Profile copied: {source} -> {target}");
+    }
+    Ok(())
+}
 
-    let status_label = gtk::Label::new(Some("Saved"));
-    status_label.set_halign(gtk::Align::End);
-    status_label.set_xalign(1.0);
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    format: u32,
+    profile: String,
+    givetray_version: String,
+    #[serde(default)]
+    applications_entry: bool,
+    #[serde(default)]
+    autostart_entry: bool,
+}
 
-    let footer = gtk::Box::new(gtk::Orientation::Horizontal, 6);
-    footer.set_halign(gtk::Align::Fill);
-    footer.set_valign(gtk::Align::Start);
-    footer.set_margin_start(8);
-    footer.set_margin_end(8);
-    footer.set_margin_top(6);
-    footer.set_margin_bottom(8);
-    footer.pack_start(&options, true, true, 0);
-    footer.pack_start(&status_label, false, false, 0);
-    footer.pack_start(&save_button, false, false, 0);
-    save_button.set_valign(gtk::Align::Center);
-    save_button.set_halign(gtk::Align::End);
-    save_button.set_vexpand(false);
-    save_button.set_hexpand(false);
+/// Writes a profile bundle: a tar archive holding a manifest, the profile with
+/// inheritance resolved and home paths made portable, and the icon.
+fn export_profile(profile: &str, output: &Path) -> Result<(), String> {
+    let config_path = existing_profile_config(profile)?;
+    let mut config = load_or_create_config(&config_path)?;
+    config.extends = None;
 
-    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
-    container.set_hexpand(true);
-    container.set_vexpand(true);
-    container.pack_start(&label, false, false, 0);
-    container.pack_start(&hint, false, false, 0);
+    let icon = match config.icon_path.take() {
+        Some(icon) => {
+            Some(fs::read(&icon).map_err(|err| format!("unable to read icon {icon}: {err}"))?)
+        }
+        None => None,
+    };
+    config.log_file_path = match config.log_file_path.take() {
+        Some(path) if default_log_file_path(profile).as_deref() == Some(Path::new(&path)) => None,
+        other => other.map(|path| portable_path(&path)),
+    };
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        profile: profile.to_string(),
+        givetray_version: env!("CARGO_PKG_VERSION").to_string(),
+        applications_entry: applications_desktop_path(profile).is_some_and(|path| path.exists()),
+        autostart_entry: autostart_desktop_path(profile).is_some_and(|path| path.exists()),
+    };
+    let manifest = toml::to_string_pretty(&manifest)
+        .map_err(|err| format!("failed to serialize bundle manifest: {err}"))?;
+    let config = toml::to_string_pretty(&config)
+        .map_err(|err| format!("failed to serialize config: {err}"))?;
+
+    let mtime = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut archive = Vec::new();
+    tar_append(
+        &mut archive,
+        BUNDLE_MANIFEST_NAME,
+        manifest.as_bytes(),
+        mtime,
+    );
+    tar_append(&mut archive, BUNDLE_PROFILE_NAME, config.as_bytes(), mtime);
+    if let Some(icon) = icon.as_ref() {
+        tar_append(&mut archive, BUNDLE_ICON_NAME, icon, mtime);
+    }
+    archive.resize(archive.len() + 2 * TAR_BLOCK_SIZE, 0);
+
+    write_file_atomically(output, &archive)
+        .map_err(|err| format!("failed to write {}: {err}", output.display()))?;
+    println!("Profile exported: {}", output.display());
+    Ok(())
+}
+
+/// Creates a profile from a bundle written by [`export_profile`]. Everything is
+/// validated before the first file is written.
+fn import_profile(bundle: &Path, name: Option<&str>) -> Result<(), String> {
+    let size = fs::metadata(bundle)
+        .map_err(|err| format!("failed to read {}: {err}", bundle.display()))?
+        .len();
+    if size > MAX_BUNDLE_SIZE {
+        return Err(format!(
+            "{} is too large for a profile bundle",
+            bundle.display()
+        ));
+    }
+    let archive =
+        fs::read(bundle).map_err(|err| format!("failed to read {}: {err}", bundle.display()))?;
+
+    let mut manifest = None;
+    let mut profile_content = None;
+    let mut icon = None;
+    for (entry, data) in read_tar_entries(&archive)? {
+        let slot = match entry.as_str() {
+            BUNDLE_MANIFEST_NAME => &mut manifest,
+            BUNDLE_PROFILE_NAME => &mut profile_content,
+            BUNDLE_ICON_NAME => &mut icon,
+            other => return Err(format!("unexpected file in bundle: {other}")),
+        };
+        if slot.replace(data).is_some() {
+            return Err(format!("duplicate file in bundle: {entry}"));
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| format!("bundle has no {BUNDLE_MANIFEST_NAME}"))?;
+    let manifest = String::from_utf8(manifest)
+        .map_err(|_| format!("{BUNDLE_MANIFEST_NAME} is not valid UTF-8"))?;
+    let manifest: BundleManifest = toml::from_str(&manifest)
+        .map_err(|err| format!("{BUNDLE_MANIFEST_NAME}: {}", err.message().trim()))?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(format!(
+            "unsupported bundle format {} (expected {BUNDLE_FORMAT})",
+            manifest.format
+        ));
+    }
+
+    let profile = match name {
+        Some(name) => name.to_string(),
+        None => validate_profile_name(&manifest.profile)
+            .map_err(|err| format!("bundle profile name: {err}"))?,
+    };
+    let config_path = config_path_for_profile(&profile)
+        .ok_or_else(|| "unable to resolve configuration path".to_string())?;
+    if config_path.exists() {
+        return Err(format!(
+            "profile already exists: {profile}; use -c to import under another name"
+        ));
+    }
+
+    let content = profile_content.ok_or_else(|| format!("bundle has no {BUNDLE_PROFILE_NAME}"))?;
+    let content = String::from_utf8(content)
+        .map_err(|_| format!("{BUNDLE_PROFILE_NAME} is not valid UTF-8"))?;
+    let mut config =
+        parse_config(&content).map_err(|err| format!("{BUNDLE_PROFILE_NAME}: {err}"))?;
+    for warning in imported_path_warnings(&config, &profile) {
+        eprintln!("warning: {warning}");
+    }
+    // Icons only come from the bundle itself, never from a path on the exporting machine.
+    config.icon_path = None;
+    config.log_file_path = config.log_file_path.map(|path| local_path(&path));
+
+    validate_command_override(&config.command)
+        .map_err(|err| err.replace("-cmd/--command value", "command"))?;
+    validate_command_template(&config.command, &profile, &config.vars)
+        .map_err(|err| format!("command: {err}"))?;
+    resolve_process_limits(&config.limits).map_err(|err| format!("limits: {err}"))?;
+    if let Some(icon) = icon.as_ref() {
+        image::load_from_memory(icon).map_err(|err| format!("invalid icon image: {err}"))?;
+    }
+    let exec_path =
+        env::current_exe().map_err(|err| format!("unable to resolve executable path: {err}"))?;
+    let entry_icon = profile_icon_path(&profile)
+        .ok_or_else(|| "unable to resolve icon storage path".to_string())?;
+    for autostart in [false, true] {
+        let entry = desktop_entry(
+            &exec_path,
+            &entry_icon,
+            &profile,
+            autostart,
+            &config.desktop,
+        );
+        ensure_valid_desktop_entry(&entry).map_err(|err| format!("desktop: {err}"))?;
+    }
+
+    if let Some(icon) = icon {
+        let icon_path = store_profile_icon(&icon, &profile)?;
+        config.icon_path = Some(icon_path.to_string_lossy().to_string());
+    }
+    save_config(&config_path, &config)?;
+    println!("Profile imported: {}", config_path.display());
+
+    let icon_path = resolve_icon_path_for_desktop(&config)
+        .map_err(|err| format!("unable to resolve icon path: {err}"))?;
+    for (enabled, path, autostart) in [
+        (
+            manifest.applications_entry,
+            applications_desktop_path(&profile),
+            false,
+        ),
+        (
+            manifest.autostart_entry,
+            autostart_desktop_path(&profile),
+            true,
+        ),
+    ] {
+        if let (true, Some(path)) = (enabled, path) {
+            let contents =
+                desktop_entry(&exec_path, &icon_path, &profile, autostart, &config.desktop);
+            write_desktop_file(&path, &contents)
+                .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+            println!("Desktop entry created: {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Lists paths in an imported profile that point at the exporting machine:
+/// absolute paths in the command and hooks, and an icon outside the profile.
+fn imported_path_warnings(config: &Config, profile: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let hooks = HookKind::ALL.into_iter().filter_map(|kind| {
+        config
+            .hooks
+            .command(kind)
+            .map(|command| (format!("hooks.{}", kind.name()), command))
+    });
+    for (key, command) in [("command".to_string(), config.command.as_str())]
+        .into_iter()
+        .chain(hooks)
+    {
+        for word in shell_words::split(command).unwrap_or_default() {
+            if word.starts_with('/') || word.contains("=/") {
+                warnings.push(format!(
+                    "{key} uses the absolute path {word}, which may not exist on this machine"
+                ));
+            }
+        }
+    }
+    if let Some(icon) = config.icon_path.as_deref() {
+        let profile_dir = profile_data_dir(profile);
+        if !profile_dir.is_some_and(|dir| Path::new(icon).starts_with(dir)) {
+            warnings.push(format!(
+                "icon_path {icon} is outside the profile and is ignored; the bundle's icon is used instead"
+            ));
+        }
+    }
+    warnings
+}
+
+/// Rewrites a path under the home directory as `~/...` so it survives a move
+/// to another machine or user.
+fn portable_path(path: &str) -> String {
+    BaseDirs::new()
+        .and_then(|dirs| {
+            Path::new(path)
+                .strip_prefix(dirs.home_dir())
+                .ok()
+                .map(|relative| format!("~/{}", relative.display()))
+        })
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn local_path(path: &str) -> String {
+    match (path.strip_prefix("~/"), BaseDirs::new()) {
+        (Some(relative), Some(dirs)) => dirs.home_dir().join(relative).display().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Appends a regular file to a ustar archive held in memory.
+fn tar_append(archive: &mut Vec<u8>, name: &str, data: &[u8], mtime: u64) {
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_tar_octal(&mut header[100..108], 0o600);
+    write_tar_octal(&mut header[108..116], 0);
+    write_tar_octal(&mut header[116..124], 0);
+    write_tar_octal(&mut header[124..136], data.len() as u64);
+    write_tar_octal(&mut header[136..148], mtime);
+    header[148..156].fill(b' ');
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    let checksum = header.iter().map(|&byte| u64::from(byte)).sum();
+    write_tar_octal(&mut header[148..155], checksum);
+
+    archive.extend_from_slice(&header);
+    archive.extend_from_slice(data);
+    let padding = (TAR_BLOCK_SIZE - data.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+    archive.resize(archive.len() + padding, 0);
+}
+
+fn write_tar_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+/// Reads the regular files of a ustar archive. Anything else (links, devices,
+/// directories) is rejected, as bundles never contain them.
+fn read_tar_entries(archive: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + TAR_BLOCK_SIZE <= archive.len() {
+        let header = &archive[offset..offset + TAR_BLOCK_SIZE];
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(entries);
+        }
+        if &header[257..262] != b"ustar" {
+            return Err("not a tar archive".to_string());
+        }
+        let checksum = header
+            .iter()
+            .enumerate()
+            .map(|(index, &byte)| {
+                if (148..156).contains(&index) {
+                    u64::from(b' ')
+                } else {
+                    u64::from(byte)
+                }
+            })
+            .sum::<u64>();
+        if parse_tar_octal(&header[148..156])? != checksum {
+            return Err("corrupt archive header".to_string());
+        }
+
+        let name = tar_field_str(&header[0..100])?;
+        let prefix = tar_field_str(&header[345..500])?;
+        let name = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        if !matches!(header[156], b'0' | 0) {
+            return Err(format!("unsupported archive entry: {name}"));
+        }
+        let size = parse_tar_octal(&header[124..136])?;
+        let start = offset + TAR_BLOCK_SIZE;
+        let end = usize::try_from(size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .filter(|end| *end <= archive.len())
+            .ok_or_else(|| format!("archive is truncated at {name}"))?;
+        entries.push((name, archive[start..end].to_vec()));
+        offset = start + (end - start).div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+    }
+    Err("archive is truncated".to_string())
+}
+
+fn tar_field_str(field: &[u8]) -> Result<String, String> {
+    let value = field.split(|&byte| byte == 0).next().unwrap_or_default();
+    String::from_utf8(value.to_vec()).map_err(|_| "invalid file name in archive".to_string())
+}
+
+fn parse_tar_octal(field: &[u8]) -> Result<u64, String> {
+    let value = tar_field_str(field)?;
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(value, 8).map_err(|_| "invalid number in archive header".to_string())
+}
+
+fn existing_profile_config(profile: &str) -> Result<PathBuf, String> {
+    let config_path = config_path_for_profile(profile)
+        .ok_or_else(|| "unable to resolve configuration path".to_string())?;
+    if !config_path.exists() {
+        return Err(format!("profile not found: {profile}"));
+    }
+    Ok(config_path)
+}
+
+fn instance_is_running(profile: &str) -> bool {
+    control_socket_path(profile).is_some_and(|path| UnixStream::connect(path).is_ok())
+}
+
+fn remove_profile_desktop_entries(profile: &str) -> Result<(), String> {
+    for path in [
+        applications_desktop_path(profile),
+        autostart_desktop_path(profile),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if path.exists() {
+            remove_path_if_exists(&path)?;
+            println!("Desktop entry removed: {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn remove_profile_systemd_service(profile: &str) -> Result<(), String> {
+    let Some(path) = systemd_service_path(profile) else {
+        return Ok(());
+    };
+    if !path.exists() {
+        return Ok(());
+    }
+    let _ = run_systemctl_user(&["disable", &systemd_service_name(profile)]);
+    remove_path_if_exists(&path)?;
+    run_systemctl_user(&["daemon-reload"])?;
+    println!("Systemd unit removed: {}", path.display());
+    Ok(())
+}
+
+fn remove_path_if_exists(path: &Path) -> Result<(), String> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(format!("failed to remove {}: {err}", path.display())),
+    }
+}
+
+fn copy_dir_recursive(source: &Path, target: &Path) -> Result<(), String> {
+    fs::create_dir_all(target)
+        .map_err(|err| format!("failed to create {}: {err}", target.display()))?;
+    let entries = fs::read_dir(source)
+        .map_err(|err| format!("failed to read {}: {err}", source.display()))?;
+    for entry in entries.flatten() {
+        let from = entry.path();
+        let to = target.join(entry.file_name());
+        if from.is_dir() {
+            copy_dir_recursive(&from, &to)?;
+        } else {
+            fs::copy(&from, &to)
+                .map_err(|err| format!("failed to copy {}: {err}", from.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn create_systemd_unit_from_cli(cli: &CliOptions, action: UnitFileAction) -> Result<(), String> {
+    let unit_path = systemd_service_path(&cli.profile)
+        .ok_or_else(|| "unable to resolve systemd user unit path".to_string())?;
+    let unit_name = systemd_service_name(&cli.profile);
+
+    if action == UnitFileAction::Disable {
+        if unit_path.exists() {
+            run_systemctl_user(&["disable", &unit_name])?;
+            fs::remove_file(&unit_path)
+                .map_err(|err| format!("failed to remove systemd unit: {err}"))?;
+            run_systemctl_user(&["daemon-reload"])?;
+            println!("Systemd unit disabled and removed: {}", unit_path.display());
+        } else {
+            println!("Systemd unit not found: {}", unit_path.display());
+        }
+        return Ok(());
+    }
+
+    let config_path = config_path_for_profile(&cli.profile)
+        .ok_or_else(|| "unable to resolve configuration path".to_string())?;
+    let mut config = load_or_create_config(&config_path)?;
+
+    if apply_cli_overrides_to_config(&mut config, cli)? {
+        save_config(&config_path, &config)
+            .map_err(|err| format!("failed to save config overrides: {err}"))?;
+    }
+
+    let exec_path = env::current_exe()
+        .map_err(|err| format!("unable to resolve executable path for systemd unit: {err}"))?;
+    let contents = systemd_service_unit(&exec_path, &cli.profile);
+    write_unit_file(&unit_path, &contents)
+        .map_err(|err| format!("failed to write systemd unit: {err}"))?;
+    run_systemctl_user(&["daemon-reload"])?;
+    println!("Systemd unit created: {}", unit_path.display());
+
+    if action == UnitFileAction::Enable {
+        run_systemctl_user(&["enable", &unit_name])?;
+        println!("Systemd unit enabled: {unit_name}");
+    }
+    Ok(())
+}
+
+fn run_systemctl_user(args: &[&str]) -> Result<(), String> {
+    let output = systemctl_user()
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("failed to run systemctl: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn build_logs_window() -> (
+    gtk::Window,
+    gtk::TextView,
+    gtk::TextBuffer,
+    gtk::Button,
+    gtk::Button,
+    gtk::Button,
+    gtk::Label,
+    gtk::Entry,
+    gtk::Button,
+    gtk::MenuButton,
+) {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("Logs");
+    window.set_default_size(820, 520);
+
+    let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
+    let text_view = gtk::TextView::with_buffer(&buffer);
+    text_view.set_editable(false);
+    text_view.set_monospace(true);
+    text_view.set_cursor_visible(false);
+
+    text_view.set_left_margin(8);
+    text_view.set_right_margin(8);
+    text_view.set_top_margin(8);
+    text_view.set_bottom_margin(8);
+
+    let clear_button = gtk::Button::new();
+    let clear_icon = gtk::Image::from_icon_name(Some("edit-clear"), gtk::IconSize::Button);
+    let clear_label = gtk::Label::new(Some("Clear"));
+    let clear_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    clear_box.pack_start(&clear_icon, false, false, 0);
+    clear_box.pack_start(&clear_label, false, false, 0);
+    clear_button.add(&clear_box);
+
+    let copy_button = gtk::Button::new();
+    let copy_icon = gtk::Image::from_icon_name(Some("edit-copy"), gtk::IconSize::Button);
+    let copy_label = gtk::Label::new(Some("Copy All"));
+    let copy_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    copy_box.pack_start(&copy_icon, false, false, 0);
+    copy_box.pack_start(&copy_label, false, false, 0);
+    copy_button.add(&copy_box);
+
+    let history_button = gtk::Button::new();
+    let history_icon =
+        gtk::Image::from_icon_name(Some("document-open-recent"), gtk::IconSize::Button);
+    let history_label = gtk::Label::new(Some("History"));
+    let history_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    history_box.pack_start(&history_icon, false, false, 0);
+    history_box.pack_start(&history_label, false, false, 0);
+    history_button.add(&history_box);
+
+    let status_label = gtk::Label::new(Some("0 lines"));
+    status_label.set_halign(gtk::Align::Start);
+    status_label.set_xalign(0.0);
+
+    let actions = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    actions.set_halign(gtk::Align::Fill);
+    actions.set_margin_start(8);
+    actions.set_margin_end(8);
+    actions.set_margin_top(8);
+    actions.set_margin_bottom(4);
+    actions.pack_start(&status_label, true, true, 0);
+    actions.pack_start(&history_button, false, false, 0);
+    actions.pack_start(&copy_button, false, false, 0);
+    actions.pack_start(&clear_button, false, false, 0);
+
+    let scroller = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scroller.set_hexpand(true);
+    scroller.set_vexpand(true);
+    scroller.add(&text_view);
+
+    let input_entry = gtk::Entry::new();
+    input_entry.set_hexpand(true);
+    input_entry.set_placeholder_text(Some("Input for the running command"));
+
+    let send_button = gtk::Button::with_label("Send");
+    let control_button = gtk::MenuButton::new();
+    control_button.set_label("Control");
+    control_button.set_tooltip_text(Some("Send a control character"));
+
+    let input = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    input.set_margin_start(8);
+    input.set_margin_end(8);
+    input.set_margin_top(4);
+    input.set_margin_bottom(8);
+    input.pack_start(&input_entry, true, true, 0);
+    input.pack_start(&send_button, false, false, 0);
+    input.pack_start(&control_button, false, false, 0);
+
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    container.set_hexpand(true);
+    container.set_vexpand(true);
+    container.pack_start(&actions, false, false, 0);
     container.pack_start(&scroller, true, true, 0);
-    container.pack_start(&footer, false, false, 0);
+    container.pack_start(&input, false, false, 0);
 
     window.add(&container);
+    window.connect_delete_event(|window, _| {
+        window.hide();
+        Propagation::Stop
+    });
 
     window.show_all();
     window.hide();
@@ -795,30 +2459,169 @@ fn build_config_window(
         window,
         text_view,
         buffer,
-        autostart_toggle,
-        log_to_file_toggle,
-        apps_toggle,
-        autostart_desktop_toggle,
-        save_button,
+        clear_button,
+        copy_button,
+        history_button,
         status_label,
+        input_entry,
+        send_button,
+        control_button,
     )
 }
 
-fn build_about_window(window_icon: Option<&Pixbuf>) -> gtk::Window {
+fn build_config_window(
+    profile: &str,
+    command: &str,
+    autostart: bool,
+    log_to_file: bool,
+) -> (
+    gtk::Window,
+    gtk::TextView,
+    gtk::TextBuffer,
+    gtk::CheckButton,
+    gtk::CheckButton,
+    gtk::CheckButton,
+    gtk::CheckButton,
+    gtk::Button,
+    gtk::Label,
+) {
     let window = gtk::Window::new(gtk::WindowType::Toplevel);
-    window.set_title("About");
-    window.set_default_size(460, 300);
-    window.set_resizable(true);
-
-    let title = gtk::Label::new(None);
-    title.set_markup("<b>givetray</b>");
-    title.set_halign(gtk::Align::Start);
-    title.set_xalign(0.0);
-
-    let subtitle = gtk::Label::new(Some("Run terminal commands from the Linux system tray"));
-    subtitle.set_halign(gtk::Align::Start);
-    subtitle.set_xalign(0.0);
-    subtitle.set_margin_bottom(6);
+    window.set_title(&format!("Configuration ({profile})"));
+    window.set_default_size(860, 300);
+
+    let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
+    buffer.set_text(command);
+    let text_view = gtk::TextView::with_buffer(&buffer);
+    text_view.set_monospace(true);
+    text_view.set_wrap_mode(gtk::WrapMode::WordChar);
+    text_view.set_hexpand(true);
+    text_view.set_vexpand(true);
+    text_view.set_left_margin(8);
+    text_view.set_right_margin(8);
+    text_view.set_top_margin(8);
+    text_view.set_bottom_margin(8);
+
+    let label = gtk::Label::new(Some("Command or script"));
+    label.set_halign(gtk::Align::Start);
+    label.set_margin_start(8);
+    label.set_margin_end(8);
+    label.set_margin_top(12);
+    label.set_margin_bottom(4);
+
+    let scroller = gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    scroller.set_hexpand(true);
+    scroller.set_vexpand(true);
+    scroller.add(&text_view);
+
+    let hint = gtk::Label::new(Some("Enter the terminal command to run for this profile."));
+    hint.set_halign(gtk::Align::Start);
+    hint.set_xalign(0.0);
+    hint.set_margin_start(8);
+    hint.set_margin_end(8);
+    hint.set_margin_bottom(4);
+
+    let autostart_toggle =
+        gtk::CheckButton::with_label("Run command automatically when givetray launches");
+    autostart_toggle.set_active(autostart);
+    autostart_toggle.set_halign(gtk::Align::Start);
+    autostart_toggle.set_tooltip_text(Some(
+        "Runs this profile command when the givetray instance starts.",
+    ));
+
+    let log_to_file_toggle = gtk::CheckButton::with_label("Write logs to file");
+    log_to_file_toggle.set_active(log_to_file);
+    log_to_file_toggle.set_halign(gtk::Align::Start);
+    log_to_file_toggle.set_tooltip_text(Some(
+        "When enabled, command logs are appended to a profile log file.",
+    ));
+
+    let apps_toggle = gtk::CheckButton::with_label("Create Applications entry (.desktop)");
+    apps_toggle.set_halign(gtk::Align::Start);
+    apps_toggle.set_tooltip_text(Some(
+        "Creates or removes ~/.local/share/applications desktop entry for this profile.",
+    ));
+
+    let autostart_desktop_toggle =
+        gtk::CheckButton::with_label("Enable desktop session autostart (~/.config/autostart)");
+    autostart_desktop_toggle.set_halign(gtk::Align::Start);
+    autostart_desktop_toggle.set_tooltip_text(Some(
+        "Creates or removes ~/.config/autostart desktop entry for this profile.",
+    ));
+
+    let save_button = gtk::Button::new();
+    let save_icon = gtk::Image::from_icon_name(Some("media-floppy"), gtk::IconSize::Button);
+    let save_label = gtk::Label::new(Some("Save"));
+    let save_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    save_box.pack_start(&save_icon, false, false, 0);
+    save_box.pack_start(&save_label, false, false, 0);
+    save_button.add(&save_box);
+
+    let options = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    options.pack_start(&autostart_toggle, false, false, 0);
+    options.pack_start(&log_to_file_toggle, false, false, 0);
+    options.pack_start(&apps_toggle, false, false, 0);
+    options.pack_start(&autostart_desktop_toggle, false, false, 0);
+
+    let status_label = gtk::Label::new(Some("Saved"));
+    status_label.set_halign(gtk::Align::End);
+    status_label.set_xalign(1.0);
+
+    let footer = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    footer.set_halign(gtk::Align::Fill);
+    footer.set_valign(gtk::Align::Start);
+    footer.set_margin_start(8);
+    footer.set_margin_end(8);
+    footer.set_margin_top(6);
+    footer.set_margin_bottom(8);
+    footer.pack_start(&options, true, true, 0);
+    footer.pack_start(&status_label, false, false, 0);
+    footer.pack_start(&save_button, false, false, 0);
+    save_button.set_valign(gtk::Align::Center);
+    save_button.set_halign(gtk::Align::End);
+    save_button.set_vexpand(false);
+    save_button.set_hexpand(false);
+
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    container.set_hexpand(true);
+    container.set_vexpand(true);
+    container.pack_start(&label, false, false, 0);
+    container.pack_start(&hint, false, false, 0);
+    container.pack_start(&scroller, true, true, 0);
+    container.pack_start(&footer, false, false, 0);
+
+    window.add(&container);
+
+    window.show_all();
+    window.hide();
+
+    (
+        window,
+        text_view,
+        buffer,
+        autostart_toggle,
+        log_to_file_toggle,
+        apps_toggle,
+        autostart_desktop_toggle,
+        save_button,
+        status_label,
+    )
+}
+
+fn build_about_window(window_icon: Option<&Pixbuf>) -> gtk::Window {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("About");
+    window.set_default_size(460, 300);
+    window.set_resizable(true);
+
+    let title = gtk::Label::new(None);
+    title.set_markup("<b>givetray</b>");
+    title.set_halign(gtk::Align::Start);
+    title.set_xalign(0.0);
+
+    let subtitle = gtk::Label::new(Some("Run terminal commands from the Linux system tray"));
+    subtitle.set_halign(gtk::Align::Start);
+    subtitle.set_xalign(0.0);
+    subtitle.set_margin_bottom(6);
 
     let version = gtk::Label::new(Some(&format!("Version: {}", env!("CARGO_PKG_VERSION"))));
     version.set_halign(gtk::Align::Start);
@@ -828,1167 +2631,6269 @@ fn build_about_window(window_icon: Option<&Pixbuf>) -> gtk::Window {
     author.set_halign(gtk::Align::Start);
     author.set_xalign(0.0);
 
-    let github = gtk::LinkButton::with_label("https://github.com/allenguarnes/givetray", "GitHub");
-    github.set_halign(gtk::Align::Start);
-    github.set_margin_top(2);
+    let github = gtk::LinkButton::with_label("https://github.com/allenguarnes/givetray", "GitHub");
+    github.set_halign(gtk::Align::Start);
+    github.set_margin_top(2);
+
+    let coffee =
+        gtk::LinkButton::with_label("https://buymeacoffee.com/allenguarnes", "Buy Me a Coffee");
+    coffee.set_halign(gtk::Align::Start);
+
+    let description = gtk::Label::new(Some(
+        "Run terminal commands from a tray icon with profile-based settings and logs.",
+    ));
+    description.set_halign(gtk::Align::Start);
+    description.set_xalign(0.0);
+    description.set_line_wrap(true);
+
+    let licenses = gtk::Label::new(Some("License: MIT OR Apache-2.0"));
+    licenses.set_halign(gtk::Align::Start);
+    licenses.set_xalign(0.0);
+    licenses.set_line_wrap(true);
+
+    let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+    header.set_halign(gtk::Align::Start);
+
+    if let Some(icon) = window_icon {
+        let about_icon = icon
+            .scale_simple(56, 56, InterpType::Bilinear)
+            .unwrap_or_else(|| icon.clone());
+        let icon_image = gtk::Image::from_pixbuf(Some(&about_icon));
+        icon_image.set_halign(gtk::Align::Start);
+        header.pack_start(&icon_image, false, false, 0);
+    }
+
+    let title_block = gtk::Box::new(gtk::Orientation::Vertical, 2);
+    title_block.pack_start(&title, false, false, 0);
+    title_block.pack_start(&subtitle, false, false, 0);
+    header.pack_start(&title_block, false, false, 0);
+
+    let links = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    links.pack_start(&github, false, false, 0);
+    links.pack_start(&coffee, false, false, 0);
+
+    let divider = gtk::Separator::new(gtk::Orientation::Horizontal);
+
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 6);
+    container.set_margin_start(12);
+    container.set_margin_end(12);
+    container.set_margin_top(12);
+    container.set_margin_bottom(12);
+    container.pack_start(&header, false, false, 0);
+    container.pack_start(&description, false, false, 0);
+    container.pack_start(&divider, false, false, 4);
+    container.pack_start(&version, false, false, 0);
+    container.pack_start(&author, false, false, 0);
+    container.pack_start(&links, false, false, 0);
+    container.pack_start(&licenses, false, false, 0);
+
+    window.add(&container);
+    window.connect_delete_event(|window, _| {
+        window.hide();
+        Propagation::Stop
+    });
+
+    window.show_all();
+    window.hide();
+
+    window
+}
+
+fn setup_config_handlers(state: Rc<RefCell<AppState>>) {
+    let view = state.borrow().config_view.clone();
+    let buffer = state.borrow().config_buffer.clone();
+    let window = state.borrow().config_window.clone();
+    let autostart_toggle = state.borrow().config_autostart.clone();
+    let log_to_file_toggle = state.borrow().config_log_to_file.clone();
+    let save_button = state.borrow().config_save_button.clone();
+    let apps_toggle = state.borrow().config_applications.clone();
+    let system_autostart_toggle = state.borrow().config_system_autostart.clone();
+
+    let state_close = state.clone();
+    let buffer_close = buffer.clone();
+    let autostart_toggle_close = autostart_toggle.clone();
+    let log_to_file_toggle_close = log_to_file_toggle.clone();
+    let apps_toggle_close = apps_toggle.clone();
+    let system_autostart_toggle_close = system_autostart_toggle.clone();
+    window.connect_delete_event(move |window, _| {
+        let current_text = buffer_text(&buffer_close);
+        let has_unsaved = {
+            let app = state_close.borrow();
+            config_has_unsaved_changes(
+                &app,
+                &current_text,
+                autostart_toggle_close.is_active(),
+                log_to_file_toggle_close.is_active(),
+                apps_toggle_close.is_active(),
+                system_autostart_toggle_close.is_active(),
+            )
+        };
+
+        if !has_unsaved {
+            window.hide();
+            return Propagation::Stop;
+        }
+
+        match show_config_close_dialog(window) {
+            ConfigCloseAction::Save => {
+                save_from_config_widgets(
+                    state_close.clone(),
+                    &buffer_close,
+                    &log_to_file_toggle_close,
+                    &apps_toggle_close,
+                    &system_autostart_toggle_close,
+                );
+                window.hide();
+            }
+            ConfigCloseAction::Discard => {
+                refresh_config_dirty_status(state_close.clone());
+                window.hide();
+            }
+            ConfigCloseAction::Cancel => {}
+        }
+
+        Propagation::Stop
+    });
+
+    let state_save = state.clone();
+    let buffer_save = buffer.clone();
+    let log_to_file_toggle_save = log_to_file_toggle.clone();
+    let apps_toggle_save = apps_toggle.clone();
+    let system_autostart_save = system_autostart_toggle.clone();
+    save_button.connect_clicked(move |_| {
+        save_from_config_widgets(
+            state_save.clone(),
+            &buffer_save,
+            &log_to_file_toggle_save,
+            &apps_toggle_save,
+            &system_autostart_save,
+        );
+    });
+
+    let state_changed = state.clone();
+    let buffer_changed = buffer.clone();
+    buffer.connect_changed(move |_| {
+        let text = buffer_text(&buffer_changed);
+        let mut state = state_changed.borrow_mut();
+        if state.config_ignore || text == state.config_last {
+            return;
+        }
+        let last = state.config_last.clone();
+        state.config_undo.push(last);
+        if state.config_undo.len() > MAX_UNDO {
+            state.config_undo.remove(0);
+        }
+        state.config_last = text;
+        state.config_redo.clear();
+        drop(state);
+        refresh_config_dirty_status(state_changed.clone());
+    });
+
+    let state_autostart_toggled = state.clone();
+    autostart_toggle.connect_toggled(move |_| {
+        refresh_config_dirty_status(state_autostart_toggled.clone());
+    });
+
+    let state_logfile_toggled = state.clone();
+    log_to_file_toggle.connect_toggled(move |_| {
+        refresh_config_dirty_status(state_logfile_toggled.clone());
+    });
+
+    let state_apps_toggled = state.clone();
+    apps_toggle.connect_toggled(move |_| {
+        refresh_config_dirty_status(state_apps_toggled.clone());
+    });
+
+    let state_system_toggled = state.clone();
+    system_autostart_toggle.connect_toggled(move |_| {
+        refresh_config_dirty_status(state_system_toggled.clone());
+    });
+
+    let state_keys = state.clone();
+    let buffer_keys = buffer.clone();
+    view.connect_key_press_event(move |_, event| {
+        let key = event.keyval();
+        let modifiers = event.state();
+        let ctrl = modifiers.contains(gdk::ModifierType::CONTROL_MASK);
+        let shift = modifiers.contains(gdk::ModifierType::SHIFT_MASK);
+
+        if ctrl && shift && (key == gdk::keys::constants::Z || key == gdk::keys::constants::z) {
+            if let Some(next) = {
+                let mut state = state_keys.borrow_mut();
+                let next = state.config_redo.pop();
+                if let Some(ref value) = next {
+                    let last = state.config_last.clone();
+                    state.config_undo.push(last);
+                    state.config_last = value.clone();
+                    state.config_ignore = true;
+                }
+                next
+            } {
+                buffer_keys.set_text(&next);
+                state_keys.borrow_mut().config_ignore = false;
+                refresh_config_dirty_status(state_keys.clone());
+                return Propagation::Stop;
+            }
+            return Propagation::Proceed;
+        }
+
+        if ctrl && !shift && (key == gdk::keys::constants::z || key == gdk::keys::constants::Z) {
+            if let Some(prev) = {
+                let mut state = state_keys.borrow_mut();
+                let prev = state.config_undo.pop();
+                if let Some(ref value) = prev {
+                    let last = state.config_last.clone();
+                    state.config_redo.push(last);
+                    state.config_last = value.clone();
+                    state.config_ignore = true;
+                }
+                prev
+            } {
+                buffer_keys.set_text(&prev);
+                state_keys.borrow_mut().config_ignore = false;
+                refresh_config_dirty_status(state_keys.clone());
+                return Propagation::Stop;
+            }
+            return Propagation::Proceed;
+        }
+
+        if ctrl && (key == gdk::keys::constants::y || key == gdk::keys::constants::Y) {
+            if let Some(next) = {
+                let mut state = state_keys.borrow_mut();
+                let next = state.config_redo.pop();
+                if let Some(ref value) = next {
+                    let last = state.config_last.clone();
+                    state.config_undo.push(last);
+                    state.config_last = value.clone();
+                    state.config_ignore = true;
+                }
+                next
+            } {
+                buffer_keys.set_text(&next);
+                state_keys.borrow_mut().config_ignore = false;
+                refresh_config_dirty_status(state_keys.clone());
+                return Propagation::Stop;
+            }
+            return Propagation::Proceed;
+        }
+
+        Propagation::Proceed
+    });
+}
+
+enum ConfigCloseAction {
+    Save,
+    Discard,
+    Cancel,
+}
+
+fn show_config_error_dialog(message: &str) {
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>,
+        gtk::DialogFlags::empty(),
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Close,
+        "The profile configuration could not be loaded.",
+    );
+    dialog.set_secondary_text(Some(&format!(
+        "{message}\n\nDefault settings are in use. A timestamped .bak copy of the file is kept before it is overwritten."
+    )));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show_all();
+}
+
+/// Returns true when the user chose to replace their edits with the file on disk.
+fn show_config_conflict_dialog(parent: &gtk::Window) -> bool {
+    let dialog = gtk::MessageDialog::new(
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::None,
+        "The configuration file changed on disk.",
+    );
+    dialog.set_secondary_text(Some(
+        "You have unsaved edits in this window. Saving them will overwrite the changes on disk.",
+    ));
+    dialog.add_button("Keep My Edits", gtk::ResponseType::No);
+    dialog.add_button("Load From Disk", gtk::ResponseType::Yes);
+    dialog.set_default_response(gtk::ResponseType::No);
+
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Yes
+}
+
+fn show_config_close_dialog(parent: &gtk::Window) -> ConfigCloseAction {
+    let dialog = gtk::MessageDialog::new(
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        "You have unsaved configuration changes.",
+    );
+    dialog.set_secondary_text(Some("Save changes before closing this window?"));
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.add_button("Discard", gtk::ResponseType::No);
+    dialog.add_button("Save", gtk::ResponseType::Yes);
+    dialog.set_default_response(gtk::ResponseType::Yes);
+
+    let response = dialog.run();
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Yes => ConfigCloseAction::Save,
+        gtk::ResponseType::No => ConfigCloseAction::Discard,
+        _ => ConfigCloseAction::Cancel,
+    }
+}
+
+fn save_from_config_widgets(
+    state: Rc<RefCell<AppState>>,
+    buffer: &gtk::TextBuffer,
+    log_to_file_toggle: &gtk::CheckButton,
+    apps_toggle: &gtk::CheckButton,
+    system_autostart_toggle: &gtk::CheckButton,
+) {
+    let text = buffer_text(buffer);
+    let saved = save_configuration(state.clone(), text, log_to_file_toggle.is_active());
+    if saved {
+        apply_desktop_actions(
+            state.clone(),
+            apps_toggle.is_active(),
+            system_autostart_toggle.is_active(),
+        );
+        refresh_desktop_toggles(state.clone(), apps_toggle, system_autostart_toggle);
+    }
+    refresh_config_dirty_status(state);
+}
+
+fn config_has_unsaved_changes(
+    state: &AppState,
+    current_command: &str,
+    current_autostart: bool,
+    current_log_to_file: bool,
+    current_applications: bool,
+    current_system_autostart: bool,
+) -> bool {
+    current_command != state.saved_command
+        || current_autostart != state.saved_autostart
+        || current_log_to_file != state.saved_log_to_file
+        || current_applications != state.config_saved_applications
+        || current_system_autostart != state.config_saved_system_autostart
+}
+
+fn refresh_config_dirty_status(state: Rc<RefCell<AppState>>) {
+    let (status_label, status_text) = {
+        let app = state.borrow();
+        if app.config_ignore {
+            return;
+        }
+
+        let command = buffer_text(&app.config_buffer);
+        let unsaved = config_has_unsaved_changes(
+            &app,
+            &command,
+            app.config_autostart.is_active(),
+            app.config_log_to_file.is_active(),
+            app.config_applications.is_active(),
+            app.config_system_autostart.is_active(),
+        );
+        (
+            app.config_status_label.clone(),
+            if unsaved { "Unsaved changes" } else { "Saved" },
+        )
+    };
+
+    status_label.set_text(status_text);
+}
+
+fn setup_logs_handlers(state: Rc<RefCell<AppState>>) {
+    let clear_button = state.borrow().logs_clear_button.clone();
+    let copy_button = state.borrow().logs_copy_button.clone();
+    let buffer = state.borrow().logs_buffer.clone();
+    let status_label = state.borrow().logs_status_label.clone();
+
+    let state_clear = state.clone();
+    let buffer_clear = buffer.clone();
+    let status_clear = status_label.clone();
+    clear_button.connect_clicked(move |_| {
+        let mut state = state_clear.borrow_mut();
+        finish_progress_log(&mut state);
+        state.log_lines.clear();
+        buffer_clear.set_text("");
+        set_logs_status(&status_clear, 0, Some("cleared"));
+    });
+
+    let buffer_copy = buffer.clone();
+    let state_copy = state.clone();
+    let status_copy = status_label.clone();
+    copy_button.connect_clicked(move |_| {
+        let text = buffer_text(&buffer_copy);
+        let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+        clipboard.set_text(&text);
+        let line_count = state_copy.borrow().log_lines.len();
+        set_logs_status(&status_copy, line_count, Some("copied"));
+    });
+
+    let history_button = state.borrow().logs_history_button.clone();
+    let state_history = state.clone();
+    history_button.connect_clicked(move |_| show_history_window(&state_history.borrow()));
+
+    let input_entry = state.borrow().logs_input_entry.clone();
+    let send_button = state.borrow().logs_send_button.clone();
+    let control_button = state.borrow().logs_control_button.clone();
+
+    let state_send = state.clone();
+    let send_line = move |entry: &gtk::Entry| {
+        let line = entry.text().to_string();
+        entry.set_text("");
+        send_stdin_line(&mut state_send.borrow_mut(), &line);
+    };
+    let send_activate = send_line.clone();
+    input_entry.connect_activate(move |entry| send_activate(entry));
+    let entry_send = input_entry.clone();
+    send_button.connect_clicked(move |_| send_line(&entry_send));
+
+    let state_keys = state.clone();
+    input_entry.connect_key_press_event(move |entry, event| {
+        let ctrl = event.state().contains(gdk::ModifierType::CONTROL_MASK);
+        let is_d = matches!(
+            event.keyval(),
+            gdk::keys::constants::d | gdk::keys::constants::D
+        );
+        if ctrl && is_d && entry.text().is_empty() {
+            send_stdin_control(&mut state_keys.borrow_mut(), StdinControl::Eof);
+            return Propagation::Stop;
+        }
+        Propagation::Proceed
+    });
+
+    let menu = gtk::Menu::new();
+    let mut items = Vec::new();
+    for control in StdinControl::ALL {
+        let item = gtk::MenuItem::with_label(control.label());
+        let state_control = state.clone();
+        item.connect_activate(move |_| {
+            send_stdin_control(&mut state_control.borrow_mut(), control);
+        });
+        menu.append(&item);
+        items.push((control, item));
+    }
+    menu.show_all();
+    control_button.set_popup(Some(&menu));
+    // Controls written as bytes need somewhere to write them.
+    let state_menu = state.clone();
+    control_button.connect_toggled(move |button| {
+        if !button.is_active() {
+            return;
+        }
+        let state = state_menu.borrow();
+        let writable = state
+            .child
+            .as_ref()
+            .is_some_and(|child| child.has_stdin() || child.is_terminal());
+        for (control, item) in &items {
+            item.set_sensitive(writable || !control.needs_input());
+        }
+    });
+
+    update_logs_input(&state.borrow());
+}
+
+/// Enables the Logs window input only while the command can receive it.
+fn update_logs_input(state: &AppState) {
+    let running = state.child.is_some();
+    let has_stdin = state.child.as_ref().is_some_and(|child| child.has_stdin());
+    let placeholder = if !running || has_stdin {
+        "Input for the running command"
+    } else if state.saved_backend == BackendKind::Systemd {
+        "Input is not available with the systemd backend"
+    } else {
+        "Input needs keep_stdin_open = true or pty = true in the profile"
+    };
+    state
+        .logs_input_entry
+        .set_placeholder_text(Some(placeholder));
+    state.logs_input_entry.set_sensitive(has_stdin);
+    state.logs_send_button.set_sensitive(has_stdin);
+    state.logs_control_button.set_sensitive(running);
+}
+
+fn send_stdin_line(state: &mut AppState, line: &str) {
+    let Some(child) = state.child.as_mut() else {
+        return;
+    };
+    let result = child.write_stdin(format!("{line}\n").as_bytes());
+    match result {
+        Ok(()) => echo_stdin(state, format!("> {line}")),
+        Err(err) => stdin_write_failed(state, err),
+    }
+}
+
+/// Shows what was typed in the Logs view only; the log file and sinks keep
+/// just the command's own output, which may include input it echoes back.
+fn echo_stdin(state: &mut AppState, line: String) {
+    finish_progress_log(state);
+    add_log_line(state, line);
+}
+
+fn send_stdin_control(state: &mut AppState, control: StdinControl) {
+    let Some(child) = state.child.as_mut() else {
+        return;
+    };
+    let result = match control {
+        _ if child.is_terminal() => child.write_stdin(&[control.terminal_byte()]),
+        StdinControl::Interrupt => child.signal(libc::SIGINT),
+        StdinControl::Quit => child.signal(libc::SIGQUIT),
+        StdinControl::Eof => {
+            child.close_stdin();
+            Ok(())
+        }
+        StdinControl::Escape | StdinControl::Tab => child.write_stdin(&[control.terminal_byte()]),
+    };
+    match result {
+        Ok(()) => echo_stdin(state, format!("> [{}]", control.label())),
+        Err(err) => stdin_write_failed(state, err),
+    }
+    update_logs_input(state);
+}
+
+fn stdin_write_failed(state: &mut AppState, err: io::Error) {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        // The command closed its end; stop offering input it cannot read.
+        if let Some(child) = state.child.as_mut() {
+            child.close_stdin();
+        }
+        update_logs_input(state);
+    }
+    append_log(state, format!("failed to send input to command: {err}"));
+}
+
+fn set_logs_status(label: &gtk::Label, line_count: usize, detail: Option<&str>) {
+    let text = match detail {
+        Some(detail) => format!("{line_count} lines | {detail}"),
+        None => format!("{line_count} lines"),
+    };
+    label.set_text(&text);
+}
+
+fn setup_log_receiver(
+    state: Rc<RefCell<AppState>>,
+    ui_tx: Sender<UiEvent>,
+    receiver: Receiver<UiEvent>,
+) {
+    MainContext::default().spawn_local(async move {
+        let state_handle = state.clone();
+        while let Ok(event) = receiver.recv().await {
+            let mut state = state.borrow_mut();
+            match event {
+                UiEvent::AppendLog(line) => append_log(&mut state, line),
+                UiEvent::Output { stream, segment } => append_output(&mut state, stream, segment),
+                UiEvent::ProcessExited(code) => {
+                    state.child = None;
+                    state.stopping = false;
+                    // sudo exits after the last rejected attempt without
+                    // prompting again.
+                    if state.sudo_armed.load(Ordering::Relaxed)
+                        && state.sudo_attempts >= SUDO_PASSWORD_TRIES
+                    {
+                        show_sudo_auth_failed(&mut state);
+                    }
+                    disarm_sudo_prompt(&mut state);
+                    state.start_stop_item.set_text("Start");
+                    update_logs_input(&state);
+                    log_run_event(&mut state, RunEvent::Exited(code));
+                    let code = code.map(|code| code.to_string()).unwrap_or_default();
+                    let after = std::mem::take(&mut state.after_stop);
+                    // Dependents only go down with a stop that is meant to
+                    // last; the restart policy never restarts those.
+                    let stop_dependents = state.saved_stop_dependents
+                        && state.stop_requested
+                        && after == AfterStop::Nothing;
+                    let state_after = state_handle.clone();
+                    let ui_tx_after = ui_tx.clone();
+                    run_hook_then(
+                        &state,
+                        HookKind::PostExit,
+                        &ui_tx,
+                        vec![("GIVETRAY_EXIT_CODE", code)],
+                        move || finish_stop(state_after, ui_tx_after, after),
+                    );
+                    // The post_exit hook was the last user of this run's secrets.
+                    state.run_secrets.clear();
+                    if stop_dependents {
+                        let profile = state.profile.clone();
+                        let ui_tx = ui_tx.clone();
+                        thread::spawn(move || stop_dependent_profiles(&profile, &ui_tx));
+                    }
+                }
+                UiEvent::SetRunning(running) => {
+                    state
+                        .start_stop_item
+                        .set_text(if running { "Stop" } else { "Start" });
+                    update_logs_input(&state);
+                }
+                UiEvent::StdinFailed(err) => stdin_write_failed(&mut state, err),
+                UiEvent::SudoPrompted => answer_sudo_prompt(&mut state),
+                UiEvent::SudoPasswordRejected => {
+                    append_log(&mut state, "sudo rejected the password".to_string());
+                    // The prompt runs a nested main loop, so it must not start
+                    // while this handler holds the state borrow.
+                    let state = state_handle.clone();
+                    let ui_tx = ui_tx.clone();
+                    glib::idle_add_local_once(move || retry_sudo_password(state, ui_tx));
+                }
+            }
+        }
+    });
+}
+
+fn show_logs_window(state: &Rc<RefCell<AppState>>) {
+    let window = state.borrow().logs_window.clone();
+    window.show_all();
+    window.resize(820, 520);
+    window.present();
+}
+
+fn reset_config_window(state: Rc<RefCell<AppState>>) {
+    let (
+        buffer,
+        autostart_toggle,
+        log_to_file_toggle,
+        apps_toggle,
+        system_autostart_toggle,
+        command,
+        autostart,
+        log_to_file,
+    ) = {
+        let state = state.borrow();
+        (
+            state.config_buffer.clone(),
+            state.config_autostart.clone(),
+            state.config_log_to_file.clone(),
+            state.config_applications.clone(),
+            state.config_system_autostart.clone(),
+            state.saved_command.clone(),
+            state.saved_autostart,
+            state.saved_log_to_file,
+        )
+    };
+    {
+        let mut state = state.borrow_mut();
+        state.config_ignore = true;
+        state.config_last = command.clone();
+        state.config_undo.clear();
+        state.config_redo.clear();
+    }
+    buffer.set_text(&command);
+    autostart_toggle.set_active(autostart);
+    log_to_file_toggle.set_active(log_to_file);
+    refresh_desktop_toggles(state.clone(), &apps_toggle, &system_autostart_toggle);
+    refresh_config_dirty_status(state);
+}
+
+fn setup_menu_polling(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    glib::timeout_add_local(Duration::from_millis(150), move || {
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            let id = event.id;
+            if id == "start-stop" {
+                let running = state.borrow().child.is_some();
+                if running {
+                    stop_command(state.clone(), ui_tx.clone());
+                } else {
+                    start_command(state.clone(), ui_tx.clone());
+                }
+            } else if id == "logs" {
+                show_logs_window(&state);
+            } else if id == "configure" {
+                let (window, view) = {
+                    let state = state.borrow();
+                    (state.config_window.clone(), state.config_view.clone())
+                };
+                reset_config_window(state.clone());
+                window.show_all();
+                view.grab_focus();
+            } else if id == "about" {
+                let window = state.borrow().about_window.clone();
+                window.show_all();
+            } else if id == "exit" {
+                stop_command_then(state.clone(), ui_tx.clone(), AfterStop::Quit);
+            }
+        }
+
+        ControlFlow::Continue
+    });
+}
+
+impl ControlAction {
+    /// The name used on the control socket, the `ctl` command line and in
+    /// `desktop.actions`.
+    const NAMES: [(ControlAction, &'static str); 5] = [
+        (Self::Start, "start"),
+        (Self::Stop, "stop"),
+        (Self::Restart, "restart"),
+        (Self::Logs, "logs"),
+        (Self::Status, "status"),
+    ];
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| *name == value)
+            .map(|(action, _)| *action)
+    }
+
+    fn as_str(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(action, _)| *action == self)
+            .map_or("", |(_, name)| name)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Start => "Start",
+            Self::Stop => "Stop",
+            Self::Restart => "Restart",
+            Self::Logs => "Show Logs",
+            Self::Status => "Status",
+        }
+    }
+}
+
+impl TryFrom<String> for ControlAction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| {
+            let names = Self::NAMES.map(|(_, name)| name).join(", ");
+            format!("unknown action '{value}', expected one of {names}")
+        })
+    }
+}
+
+impl From<ControlAction> for &'static str {
+    fn from(action: ControlAction) -> Self {
+        action.as_str()
+    }
+}
+
+fn setup_control_server(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    let profile = state.borrow().profile.clone();
+    let Some(socket_path) = control_socket_path(&profile) else {
+        append_log(
+            &mut state.borrow_mut(),
+            "Unable to resolve control socket path".to_string(),
+        );
+        return;
+    };
+
+    let listener = match bind_control_socket(&socket_path) {
+        Ok(listener) => {
+            state.borrow_mut().control_socket_path = Some(socket_path);
+            listener
+        }
+        Err(err) => {
+            append_log(
+                &mut state.borrow_mut(),
+                format!("Control socket unavailable: {err}"),
+            );
+            return;
+        }
+    };
+
+    let (request_tx, request_rx) = async_channel::unbounded::<ControlRequest>();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            serve_control_client(stream, &request_tx);
+        }
+    });
+
+    MainContext::default().spawn_local(async move {
+        while let Ok(request) = request_rx.recv().await {
+            let reply = handle_control_action(state.clone(), ui_tx.clone(), request.action);
+            let _ = request.reply.send(reply);
+        }
+    });
+}
+
+fn bind_control_socket(path: &Path) -> Result<UnixListener, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("failed to create runtime dir: {err}"))?;
+        let _ = fs::set_permissions(parent, fs::Permissions::from_mode(0o700));
+    }
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!(
+                "another instance is already listening on {}",
+                path.display()
+            ));
+        }
+        fs::remove_file(path).map_err(|err| format!("failed to remove stale socket: {err}"))?;
+    }
+
+    UnixListener::bind(path).map_err(|err| format!("failed to bind {}: {err}", path.display()))
+}
+
+fn serve_control_client(stream: UnixStream, request_tx: &Sender<ControlRequest>) {
+    let _ = stream.set_read_timeout(Some(CONTROL_TIMEOUT));
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line).is_err() {
+        return;
+    }
+
+    let reply = match ControlAction::parse(line.trim()) {
+        Some(action) => {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let request = ControlRequest {
+                action,
+                reply: reply_tx,
+            };
+            if request_tx.send_blocking(request).is_err() {
+                "error instance is shutting down".to_string()
+            } else {
+                reply_rx
+                    .recv_timeout(CONTROL_TIMEOUT)
+                    .unwrap_or_else(|_| "error instance did not respond".to_string())
+            }
+        }
+        None => format!("error unknown action: {}", line.trim()),
+    };
+
+    let mut stream = stream;
+    let _ = writeln!(stream, "{reply}");
+}
+
+fn handle_control_action(
+    state: Rc<RefCell<AppState>>,
+    ui_tx: Sender<UiEvent>,
+    action: ControlAction,
+) -> String {
+    match action {
+        ControlAction::Start => {
+            if state.borrow().child.is_none() {
+                start_command(state.clone(), ui_tx);
+            }
+        }
+        ControlAction::Stop => {
+            if state.borrow().child.is_none() {
+                return "ok stopped".to_string();
+            }
+            stop_command(state.clone(), ui_tx);
+            return "ok stopping".to_string();
+        }
+        ControlAction::Restart => {
+            let running = {
+                let state = state.borrow();
+                state.child.is_some() || state.stopping
+            };
+            if running {
+                // Stop hooks can take a while; reply now and start once the
+                // stop has finished.
+                stop_command_then(state.clone(), ui_tx, AfterStop::Restart);
+                return "ok restarting".to_string();
+            }
+            start_command(state.clone(), ui_tx);
+        }
+        ControlAction::Logs => {
+            show_logs_window(&state);
+        }
+        ControlAction::Status => {}
+    }
+
+    if state.borrow().child.is_some() {
+        // Dependents wait for "running", so it waits for ready_check too.
+        if state.borrow().ready {
+            "ok running".to_string()
+        } else {
+            "ok starting".to_string()
+        }
+    } else if state.borrow().starting {
+        "ok starting".to_string()
+    } else if matches!(action, ControlAction::Start | ControlAction::Restart) {
+        "error command failed to start, see Logs".to_string()
+    } else {
+        "ok stopped".to_string()
+    }
+}
+
+fn send_control_action(profile: &str, action: ControlAction) -> Result<String, String> {
+    let socket_path = control_socket_path(profile)
+        .ok_or_else(|| "unable to resolve control socket path".to_string())?;
+
+    let mut stream = match UnixStream::connect(&socket_path) {
+        Ok(stream) => stream,
+        Err(_) => return control_action_without_instance(profile, action),
+    };
+    stream
+        .set_read_timeout(Some(CONTROL_TIMEOUT * 2))
+        .map_err(|err| format!("failed to configure control socket: {err}"))?;
+    writeln!(stream, "{}", action.as_str())
+        .map_err(|err| format!("failed to send control action: {err}"))?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .map_err(|err| format!("failed to read control reply: {err}"))?;
+    let reply = reply.trim();
+    match reply.strip_prefix("ok ") {
+        Some(detail) => Ok(detail.to_string()),
+        None => Err(reply.strip_prefix("error ").unwrap_or(reply).to_string()),
+    }
+}
+
+fn control_action_without_instance(profile: &str, action: ControlAction) -> Result<String, String> {
+    match action {
+        ControlAction::Stop | ControlAction::Status => Ok("stopped".to_string()),
+        ControlAction::Start | ControlAction::Restart | ControlAction::Logs => {
+            let executable = env::current_exe()
+                .map_err(|err| format!("unable to resolve executable path: {err}"))?;
+            let mut command = Command::new(executable);
+            command
+                .arg("--config")
+                .arg(profile)
+                .env(INITIAL_ACTION_ENV, action.as_str())
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+
+            #[cfg(unix)]
+            {
+                unsafe {
+                    command.pre_exec(|| {
+                        if libc::setsid() == -1 {
+                            return Err(io::Error::last_os_error());
+                        }
+                        Ok(())
+                    });
+                }
+            }
+
+            command
+                .spawn()
+                .map_err(|err| format!("unable to launch instance: {err}"))?;
+            Ok("launched new instance".to_string())
+        }
+    }
+}
+
+fn install_log_filters() {
+    glib::log_set_handler(
+        Some("libayatana-appindicator"),
+        LogLevels::LEVEL_WARNING,
+        false,
+        false,
+        |_domain, _level, _message| {},
+    );
+}
+
+fn install_css() {
+    let provider = gtk::CssProvider::new();
+    let css = b"
+        textview,
+        textview text {
+            font-family: monospace;
+            font-size: 11pt;
+        }
+    ";
+    provider.load_from_data(css).expect("failed to load CSS");
+
+    if let Some(screen) = gdk::Screen::default() {
+        gtk::StyleContext::add_provider_for_screen(
+            &screen,
+            &provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+    }
+}
+
+fn setup_process_watcher(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    glib::timeout_add_local(Duration::from_millis(500), move || {
+        let mut should_emit = None;
+        {
+            let mut state = state.borrow_mut();
+            if let Some(child) = state.child.as_mut() {
+                match child.try_wait() {
+                    Ok(Some(code)) => {
+                        should_emit = Some(code);
+                        state.child = None;
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        append_log(&mut state, format!("failed to check command status: {err}"));
+                    }
+                }
+            }
+        }
+
+        if let Some(code) = should_emit {
+            let _ = ui_tx.send_blocking(UiEvent::ProcessExited(code));
+            schedule_restart_if_needed(state.clone(), ui_tx.clone(), code);
+        }
+
+        ControlFlow::Continue
+    });
+}
+
+fn schedule_restart_if_needed(
+    state: Rc<RefCell<AppState>>,
+    ui_tx: Sender<UiEvent>,
+    code: Option<i32>,
+) {
+    let (policy, delay) = {
+        let app = state.borrow();
+        if app.stop_requested {
+            return;
+        }
+        (
+            app.saved_restart,
+            app.saved_restart_delay_secs
+                .unwrap_or(DEFAULT_RESTART_DELAY_SECS),
+        )
+    };
+    let restart = match policy {
+        RestartPolicy::Never => false,
+        RestartPolicy::OnFailure => code != Some(0),
+        RestartPolicy::Always => true,
+    };
+    if !restart {
+        return;
+    }
+
+    let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+        "restarting command in {delay}s (restart policy)"
+    )));
+    glib::timeout_add_local_once(Duration::from_secs(delay), move || {
+        let pending = {
+            let app = state.borrow();
+            app.child.is_none() && !app.stop_requested
+        };
+        if pending {
+            start_command(state, ui_tx);
+        }
+    });
+}
+
+fn save_configuration(
+    state: Rc<RefCell<AppState>>,
+    text: String,
+    log_to_file_enabled: bool,
+) -> bool {
+    let mut state = state.borrow_mut();
+    let new_autostart = state.config_autostart.is_active();
+    let mut new_log_file_path = state.saved_log_file_path.clone();
+    if log_to_file_enabled && new_log_file_path.is_none() {
+        new_log_file_path =
+            default_log_file_path(&state.profile).map(|path| path.to_string_lossy().to_string());
+    }
+    let new_config = Config {
+        command: text.clone(),
+        autostart: new_autostart,
+        log_to_file: log_to_file_enabled,
+        log_file_path: new_log_file_path.clone(),
+        ..saved_config(&state)
+    };
+
+    if let Err(err) = save_config(&state.config_path, &new_config) {
+        append_log(&mut state, format!("Failed to save configuration: {err}"));
+        return false;
+    }
+
+    state.command = text.clone();
+    state.config_last = text.clone();
+    state.saved_command = text;
+    state.saved_autostart = new_autostart;
+    state.saved_log_to_file = log_to_file_enabled;
+    state.saved_log_file_path = new_log_file_path;
+    state.log_file_path = if log_to_file_enabled {
+        state.saved_log_file_path.as_ref().map(PathBuf::from)
+    } else {
+        None
+    };
+
+    let saved_log_file_path = state.saved_log_file_path.clone();
+    let saved_log_to_file = state.saved_log_to_file;
+
+    append_log(&mut state, "Configuration updated".to_string());
+    if saved_log_to_file {
+        if let Some(path) = saved_log_file_path {
+            append_log(&mut state, format!("Log file enabled: {path}"));
+        }
+    } else {
+        append_log(&mut state, "Log file output disabled".to_string());
+    }
+
+    true
+}
+
+/// Rebuilds the profile config from what this instance last saved or loaded.
+fn saved_config(state: &AppState) -> Config {
+    Config {
+        version: CONFIG_VERSION,
+        command: state.saved_command.clone(),
+        autostart: state.saved_autostart,
+        icon_path: state.saved_icon_path.clone(),
+        log_to_file: state.saved_log_to_file,
+        log_file_path: state.saved_log_file_path.clone(),
+        limits: state.saved_limits.clone(),
+        backend: state.saved_backend,
+        desktop: state.saved_desktop.clone(),
+        hooks: state.saved_hooks.clone(),
+        restart_on_change: state.saved_restart_on_change,
+        elevation: state.saved_elevation,
+        secret_service: state.saved_secret_service,
+        extends: state.saved_extends.clone(),
+        requires: state.saved_requires.clone(),
+        stop_dependents: state.saved_stop_dependents,
+        ready_check: state.saved_ready_check.clone(),
+        env: state.saved_env.clone(),
+        vars: state.saved_vars.clone(),
+        restart: state.saved_restart,
+        restart_delay_secs: state.saved_restart_delay_secs,
+        keep_stdin_open: state.saved_keep_stdin_open,
+        pty: state.saved_pty,
+        log_final_progress_only: state.saved_log_final_progress_only,
+        log_encoding: state.saved_log_encoding.clone(),
+        log_sinks: state.saved_log_sinks.clone(),
+        log_format: state.saved_log_format,
+        history_runs: state.saved_history_runs,
+        extra: state.saved_extra.clone(),
+    }
+}
+
+fn apply_config_to_state(state: &mut AppState, config: &Config) {
+    state.command = config.command.clone();
+    state.saved_command = config.command.clone();
+    state.saved_autostart = config.autostart;
+    state.saved_icon_path = config.icon_path.clone();
+    state.saved_log_to_file = config.log_to_file;
+    state.saved_log_file_path = config.log_file_path.clone();
+    state.saved_limits = config.limits.clone();
+    state.saved_backend = config.backend;
+    state.saved_desktop = config.desktop.clone();
+    state.saved_hooks = config.hooks.clone();
+    state.saved_restart_on_change = config.restart_on_change;
+    state.saved_elevation = config.elevation;
+    state.saved_secret_service = config.secret_service;
+    state.saved_extends = config.extends.clone();
+    state.saved_requires = config.requires.clone();
+    state.saved_stop_dependents = config.stop_dependents;
+    state.saved_ready_check = config.ready_check.clone();
+    state.saved_env = config.env.clone();
+    state.saved_vars = config.vars.clone();
+    state.saved_restart = config.restart;
+    state.saved_keep_stdin_open = config.keep_stdin_open;
+    state.saved_pty = config.pty;
+    state.saved_log_final_progress_only = config.log_final_progress_only;
+    state.saved_log_encoding = config.log_encoding.clone();
+    state.saved_log_format = config.log_format;
+    state.saved_history_runs = config.history_runs;
+    if state.saved_log_sinks != config.log_sinks {
+        state.saved_log_sinks = config.log_sinks.clone();
+        state.log_forwarder = LogForwarder::default();
+    }
+    state.saved_restart_delay_secs = config.restart_delay_secs;
+    state.saved_extra = config.extra.clone();
+    state.log_file_path = resolve_log_file_path(&state.profile, config);
+}
+
+/// Watches the profile file, the global defaults and every `extends` base, so
+/// an edit to anything the profile inherits reloads it. Called again on each
+/// reload in case the `extends` chain changed.
+fn setup_config_monitor(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    let paths = config_watch_paths(&state.borrow().config_path);
+    let watched = state
+        .borrow()
+        .config_monitors
+        .iter()
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<PathBuf>>();
+    if paths == watched {
+        return;
+    }
+
+    let mut monitors = Vec::new();
+    for path in paths {
+        let file = gio::File::for_path(&path);
+        let monitor =
+            match file.monitor_file(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE) {
+                Ok(monitor) => monitor,
+                Err(err) => {
+                    append_log(
+                        &mut state.borrow_mut(),
+                        format!("Unable to watch {}: {err}", path.display()),
+                    );
+                    continue;
+                }
+            };
+
+        let state_changed = state.clone();
+        let ui_tx = ui_tx.clone();
+        monitor.connect_changed(move |_, _, _, event| {
+            if matches!(
+                event,
+                gio::FileMonitorEvent::Deleted
+                    | gio::FileMonitorEvent::MovedOut
+                    | gio::FileMonitorEvent::AttributeChanged
+                    | gio::FileMonitorEvent::PreUnmount
+                    | gio::FileMonitorEvent::Unmounted
+            ) {
+                return;
+            }
+
+            // Editors emit several events per save; coalesce them into one reload.
+            if std::mem::replace(&mut state_changed.borrow_mut().config_reload_pending, true) {
+                return;
+            }
+            let state_reload = state_changed.clone();
+            let ui_tx = ui_tx.clone();
+            glib::timeout_add_local_once(CONFIG_RELOAD_DELAY, move || {
+                state_reload.borrow_mut().config_reload_pending = false;
+                reload_config_from_disk(state_reload, ui_tx);
+            });
+        });
+        monitors.push((path, monitor));
+    }
+
+    state.borrow_mut().config_monitors = monitors;
+}
+
+/// Lists the files a profile is read from: the profile itself, the global
+/// defaults and the `extends` bases as they currently are on disk.
+fn config_watch_paths(config_path: &Path) -> Vec<PathBuf> {
+    let extends_of = |path: &Path| {
+        let content = fs::read_to_string(path).ok()?;
+        let table = toml::from_str::<toml::Table>(&content).ok()?;
+        table.get("extends")?.as_str().map(str::to_string)
+    };
+
+    let mut paths = vec![config_path.to_path_buf()];
+    paths.extend(global_config_path());
+    let mut next = extends_of(config_path);
+    for _ in 0..MAX_EXTENDS_DEPTH {
+        let Some(path) = next
+            .and_then(|base| validate_profile_name(&base).ok())
+            .and_then(|base| config_path_for_profile(&base))
+        else {
+            break;
+        };
+        if paths.contains(&path) {
+            break;
+        }
+        next = extends_of(&path);
+        paths.push(path);
+    }
+    paths
+}
+
+fn reload_config_from_disk(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    setup_config_monitor(state.clone(), ui_tx.clone());
+    let config_path = state.borrow().config_path.clone();
+    let content = match fs::read_to_string(&config_path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            append_log(
+                &mut state.borrow_mut(),
+                format!("Failed to read configuration from disk: {err}"),
+            );
+            return;
+        }
+    };
+    let config = match parse_config(&content) {
+        Ok(config) => config,
+        Err(err) => {
+            append_log(
+                &mut state.borrow_mut(),
+                format!("Configuration on disk is invalid, keeping current settings ({err})"),
+            );
+            return;
+        }
+    };
+
+    let (previous, window_dirty) = {
+        let app = state.borrow();
+        let previous = saved_config(&app);
+        if config == previous {
+            return;
+        }
+        let window_dirty = app.config_window.is_visible()
+            && config_has_unsaved_changes(
+                &app,
+                &buffer_text(&app.config_buffer),
+                app.config_autostart.is_active(),
+                app.config_log_to_file.is_active(),
+                app.config_applications.is_active(),
+                app.config_system_autostart.is_active(),
+            );
+        (previous, window_dirty)
+    };
+
+    {
+        let mut app = state.borrow_mut();
+        apply_config_to_state(&mut app, &config);
+        append_log(&mut app, "Configuration reloaded from disk".to_string());
+        for key in unknown_config_keys(&config) {
+            append_log(
+                &mut app,
+                format!("Unknown configuration key '{key}' is kept but ignored"),
+            );
+        }
+    }
+
+    if config.icon_path != previous.icon_path {
+        refresh_profile_icons(state.clone(), &config);
+    }
+
+    if window_dirty {
+        let window = state.borrow().config_window.clone();
+        append_log(
+            &mut state.borrow_mut(),
+            "Configuration changed on disk while the Configuration window has unsaved edits"
+                .to_string(),
+        );
+        if show_config_conflict_dialog(&window) {
+            reset_config_window(state.clone());
+        } else {
+            refresh_config_dirty_status(state.clone());
+        }
+    } else if state.borrow().config_window.is_visible() {
+        reset_config_window(state.clone());
+    }
+
+    let definition_changed = config.command != previous.command
+        || config.limits != previous.limits
+        || config.env != previous.env
+        || config.vars != previous.vars
+        || config.backend != previous.backend;
+    if definition_changed && state.borrow().child.is_some() {
+        if config.restart_on_change {
+            append_log(
+                &mut state.borrow_mut(),
+                "Command definition changed, restarting".to_string(),
+            );
+            stop_command_then(state, ui_tx, AfterStop::Restart);
+        } else {
+            append_log(
+                &mut state.borrow_mut(),
+                "Command definition changed; restart the command to apply it".to_string(),
+            );
+        }
+    }
+}
+
+fn refresh_profile_icons(state: Rc<RefCell<AppState>>, config: &Config) {
+    let app = state.borrow();
+    match load_tray_icon(config) {
+        Ok(icon) => {
+            if let Err(err) = app.tray.set_icon(Some(icon)) {
+                eprintln!("failed to update tray icon: {err}");
+            }
+        }
+        Err(err) => eprintln!("failed to load tray icon: {err}"),
+    }
+    if let Some(icon) = load_window_icon_pixbuf(config) {
+        gtk::Window::set_default_icon(&icon);
+        app.logs_window.set_icon(Some(&icon));
+        app.config_window.set_icon(Some(&icon));
+        app.about_window.set_icon(Some(&icon));
+    }
+}
+
+fn refresh_desktop_toggles(
+    state: Rc<RefCell<AppState>>,
+    apps_toggle: &gtk::CheckButton,
+    system_autostart_toggle: &gtk::CheckButton,
+) {
+    let profile = state.borrow().profile.clone();
+    let apps_exists = applications_desktop_path(&profile).is_some_and(|path| path.exists());
+    let autostart_exists = autostart_desktop_path(&profile).is_some_and(|path| path.exists());
+
+    {
+        let mut app = state.borrow_mut();
+        app.config_ignore = true;
+        app.config_saved_applications = apps_exists;
+        app.config_saved_system_autostart = autostart_exists;
+    }
+
+    apps_toggle.set_active(apps_exists);
+    system_autostart_toggle.set_active(autostart_exists);
+
+    {
+        let mut app = state.borrow_mut();
+        app.config_ignore = false;
+    }
+
+    refresh_config_dirty_status(state);
+}
+
+fn apply_desktop_actions(
+    state: Rc<RefCell<AppState>>,
+    apps_enabled: bool,
+    autostart_enabled: bool,
+) {
+    let exec_path = match env::current_exe() {
+        Ok(path) => path,
+        Err(err) => {
+            append_log(
+                &mut state.borrow_mut(),
+                format!("Failed to resolve executable path: {err}"),
+            );
+            return;
+        }
+    };
+
+    let (profile, icon_path, config_path, desktop_settings) = {
+        let app = state.borrow();
+        let config = match load_or_create_config(&app.config_path) {
+            Ok(config) => config,
+            Err(err) => {
+                drop(app);
+                append_log(&mut state.borrow_mut(), err);
+                return;
+            }
+        };
+        let icon_path = match resolve_icon_path_for_desktop(&config) {
+            Ok(path) => path,
+            Err(err) => {
+                drop(app);
+                append_log(
+                    &mut state.borrow_mut(),
+                    format!("Failed to prepare icon path: {err}"),
+                );
+                return;
+            }
+        };
+        (
+            app.profile.clone(),
+            icon_path,
+            app.config_path.clone(),
+            config.desktop,
+        )
+    };
+
+    let desktop_name = desktop_file_name(&profile);
+    let app_entry = desktop_entry(&exec_path, &icon_path, &profile, false, &desktop_settings);
+    let autostart_entry = desktop_entry(&exec_path, &icon_path, &profile, true, &desktop_settings);
+    for entry in [&app_entry, &autostart_entry] {
+        if let Err(err) = ensure_valid_desktop_entry(entry) {
+            append_log(
+                &mut state.borrow_mut(),
+                format!("Generated desktop entry is invalid: {err}"),
+            );
+            return;
+        }
+    }
+
+    if let Some(path) = applications_desktop_path(&profile) {
+        if apps_enabled {
+            if let Err(err) = write_desktop_file(&path, &app_entry) {
+                append_log(
+                    &mut state.borrow_mut(),
+                    format!("Failed to add Applications entry: {err}"),
+                );
+            } else {
+                append_log(
+                    &mut state.borrow_mut(),
+                    format!("Applications entry updated: {desktop_name}"),
+                );
+            }
+        } else if path.exists() {
+            match fs::remove_file(&path) {
+                Ok(_) => append_log(
+                    &mut state.borrow_mut(),
+                    format!("Applications entry removed: {desktop_name}"),
+                ),
+                Err(err) => append_log(
+                    &mut state.borrow_mut(),
+                    format!("Failed to remove Applications entry: {err}"),
+                ),
+            }
+        }
+    } else {
+        append_log(
+            &mut state.borrow_mut(),
+            "Unable to resolve Applications entry path".to_string(),
+        );
+    }
+
+    if let Some(path) = autostart_desktop_path(&profile) {
+        if autostart_enabled {
+            if let Err(err) = write_desktop_file(&path, &autostart_entry) {
+                append_log(
+                    &mut state.borrow_mut(),
+                    format!("Failed to add system autostart entry: {err}"),
+                );
+            } else {
+                append_log(
+                    &mut state.borrow_mut(),
+                    format!("System autostart entry updated: {desktop_name}"),
+                );
+            }
+        } else if path.exists() {
+            match fs::remove_file(&path) {
+                Ok(_) => append_log(
+                    &mut state.borrow_mut(),
+                    format!("System autostart entry removed: {desktop_name}"),
+                ),
+                Err(err) => append_log(
+                    &mut state.borrow_mut(),
+                    format!("Failed to remove system autostart entry: {err}"),
+                ),
+            }
+        }
+    } else {
+        append_log(
+            &mut state.borrow_mut(),
+            "Unable to resolve system autostart entry path".to_string(),
+        );
+    }
+
+    let synced =
+        load_or_create_config(&config_path).and_then(|config| save_config(&config_path, &config));
+    if let Err(err) = synced {
+        append_log(
+            &mut state.borrow_mut(),
+            format!("Failed to sync configuration file: {err}"),
+        );
+    }
+}
+
+fn applications_dir() -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| dirs.data_local_dir().join("applications"))
+}
+
+fn autostart_dir() -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| dirs.config_dir().join("autostart"))
+}
+
+fn applications_desktop_path(profile: &str) -> Option<PathBuf> {
+    applications_dir().map(|dir| dir.join(desktop_file_name(profile)))
+}
+
+fn autostart_desktop_path(profile: &str) -> Option<PathBuf> {
+    autostart_dir().map(|dir| dir.join(desktop_file_name(profile)))
+}
+
+fn systemd_service_path(profile: &str) -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| {
+        dirs.config_dir()
+            .join("systemd")
+            .join("user")
+            .join(systemd_service_name(profile))
+    })
+}
+
+fn control_socket_path(profile: &str) -> Option<PathBuf> {
+    ProjectDirs::from("com", APP_NAME, APP_NAME).and_then(|proj| {
+        proj.runtime_dir()
+            .map(|dir| dir.join(format!("{}.sock", sanitize_profile_name(profile))))
+    })
+}
+
+fn global_config_path() -> Option<PathBuf> {
+    ProjectDirs::from("com", APP_NAME, APP_NAME)
+        .map(|proj| proj.config_dir().join(GLOBAL_CONFIG_FILE_NAME))
+}
+
+fn configs_dir() -> Option<PathBuf> {
+    ProjectDirs::from("com", APP_NAME, APP_NAME).map(|proj| proj.config_dir().join("configs"))
+}
+
+fn config_path_for_profile(profile: &str) -> Option<PathBuf> {
+    configs_dir().map(|dir| dir.join(format!("{}.toml", sanitize_profile_name(profile))))
+}
+
+fn default_log_file_path(profile: &str) -> Option<PathBuf> {
+    ProjectDirs::from("com", APP_NAME, APP_NAME).map(|proj| {
+        proj.data_local_dir()
+            .join("logs")
+            .join(format!("{}.log", sanitize_profile_name(profile)))
+    })
+}
+
+fn resolve_log_file_path(profile: &str, config: &Config) -> Option<PathBuf> {
+    if !config.log_to_file {
+        return None;
+    }
+    config
+        .log_file_path
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| default_log_file_path(profile))
+}
+
+/// Loads a profile, creating it with defaults when the file does not exist yet.
+/// Unreadable or malformed files are reported instead of being replaced.
+fn load_or_create_config(path: &PathBuf) -> Result<Config, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let default = Config::default();
+            if let Err(save_err) = save_config(path, &default) {
+                eprintln!(
+                    "failed to initialize default config at {}: {save_err}",
+                    path.display()
+                );
+            }
+            return Ok(default);
+        }
+        Err(err) => {
+            return Err(format!(
+                "failed to read config at {}: {err}",
+                path.display()
+            ));
+        }
+    };
+
+    let (config, version) = parse_config_versioned(&content)
+        .map_err(|err| format!("failed to parse config at {} ({err})", path.display()))?;
+
+    if version < CONFIG_VERSION {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(path, PathBuf::from(backup))
+            .map_err(|err| format!("failed to back up config before upgrade: {err}"))?;
+        save_config(path, &config)
+            .map_err(|err| format!("failed to save upgraded config: {err}"))?;
+        eprintln!(
+            "upgraded config at {} from version {version} to {CONFIG_VERSION}",
+            path.display()
+        );
+    }
+    for key in unknown_config_keys(&config) {
+        eprintln!(
+            "warning: unknown key '{key}' in {} is kept but ignored",
+            path.display()
+        );
+    }
+
+    Ok(config)
+}
+
+fn parse_config(content: &str) -> Result<Config, String> {
+    parse_config_versioned(content).map(|(config, _)| config)
+}
+
+/// Parses a profile, upgrades it in memory and layers it over the global defaults
+/// and any `extends` chain. Returns the version found on disk.
+fn parse_config_versioned(content: &str) -> Result<(Config, u32), String> {
+    let (table, version) = parse_config_table(content)?;
+    let extends = match table.get("extends") {
+        None => None,
+        Some(toml::Value::String(base)) => Some(base.clone()),
+        Some(other) => return Err(format!("extends must be a profile name, got {other}")),
+    };
+    let has_global = global_config_path().is_some_and(|path| path.exists());
+
+    // Deserializing straight from the text keeps line/column information in errors.
+    if extends.is_none() && !has_global && version == CONFIG_VERSION {
+        let config = toml::from_str(content).map_err(|err| describe_toml_error(content, &err))?;
+        return Ok((config, version));
+    }
+
+    let mut merged = inherited_config_table(extends.as_deref())?;
+    merge_config_tables(&mut merged, table);
+    let config = toml::Value::Table(merged)
+        .try_into::<Config>()
+        .map_err(|err| err.message().trim().to_string())?;
+    Ok((config, version))
+}
+
+/// Parses a single profile file into a table upgraded to [`CONFIG_VERSION`].
+fn parse_config_table(content: &str) -> Result<(toml::Table, u32), String> {
+    let mut table: toml::Table =
+        toml::from_str(content).map_err(|err| describe_toml_error(content, &err))?;
+    let version = match table.get("version") {
+        None => 0,
+        Some(toml::Value::Integer(value)) => u32::try_from(*value)
+            .map_err(|_| format!("version must be a non-negative integer, got {value}"))?,
+        Some(other) => return Err(format!("version must be an integer, got {other}")),
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "config version {version} is newer than supported version {CONFIG_VERSION}"
+        ));
+    }
+
+    for migrate in &CONFIG_MIGRATIONS[version as usize..] {
+        migrate(&mut table);
+    }
+    table.insert(
+        "version".to_string(),
+        toml::Value::Integer(i64::from(CONFIG_VERSION)),
+    );
+    Ok((table, version))
+}
+
+/// Resolves what a profile inherits: global defaults first, then each `extends`
+/// base from the most distant ancestor down to the direct parent.
+fn inherited_config_table(extends: Option<&str>) -> Result<toml::Table, String> {
+    let mut layers = Vec::new();
+    let mut visited: Vec<String> = Vec::new();
+    let mut next = extends.map(str::to_string);
+    while let Some(base) = next {
+        let base = validate_profile_name(&base)
+            .map_err(|err| format!("invalid extends value '{base}': {err}"))?;
+        if visited.contains(&base) {
+            return Err(format!("extends cycle through profile '{base}'"));
+        }
+        if visited.len() >= MAX_EXTENDS_DEPTH {
+            return Err(format!(
+                "extends chain is deeper than {MAX_EXTENDS_DEPTH} profiles"
+            ));
+        }
+
+        let path = config_path_for_profile(&base)
+            .ok_or_else(|| "unable to resolve configuration path".to_string())?;
+        let content = fs::read_to_string(&path)
+            .map_err(|err| format!("failed to read base profile '{base}': {err}"))?;
+        let (table, _) =
+            parse_config_table(&content).map_err(|err| format!("base profile '{base}': {err}"))?;
+        next = table
+            .get("extends")
+            .and_then(toml::Value::as_str)
+            .map(str::to_string);
+        visited.push(base);
+        layers.push(table);
+    }
+
+    let mut merged = match global_config_path() {
+        Some(path) if path.exists() => {
+            let content = fs::read_to_string(&path)
+                .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+            parse_config_table(&content)
+                .map_err(|err| format!("global config {}: {err}", path.display()))?
+                .0
+        }
+        _ => toml::Table::new(),
+    };
+    for layer in layers.into_iter().rev() {
+        merge_config_tables(&mut merged, layer);
+    }
+    merged.remove("extends");
+    merged.remove("version");
+    Ok(merged)
+}
+
+fn merge_config_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge_config_tables(existing, table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Drops keys the profile would only repeat from what it inherits, unless the
+/// profile file already spells them out.
+fn strip_inherited_keys(
+    generated: &mut toml::Table,
+    inherited: &toml::Table,
+    existing: Option<&toml::Table>,
+) {
+    let keys = generated.keys().cloned().collect::<Vec<String>>();
+    for key in keys {
+        if key == "version" {
+            continue;
+        }
+        let Some(inherited_value) = inherited.get(&key) else {
+            continue;
+        };
+        let existing_value = existing.and_then(|table| table.get(&key));
+
+        if let (Some(toml::Value::Table(table)), toml::Value::Table(inherited_table)) =
+            (generated.get_mut(&key), inherited_value)
+        {
+            let existing_table = existing_value.and_then(toml::Value::as_table);
+            strip_inherited_keys(table, inherited_table, existing_table);
+            if table.is_empty() && existing_value.is_none() {
+                generated.remove(&key);
+            }
+            continue;
+        }
+
+        if existing_value.is_none() && generated.get(&key) == Some(inherited_value) {
+            generated.remove(&key);
+        }
+    }
+}
+
+/// What a profile gets for keys its file leaves out: the defaults overlaid with
+/// what it inherits. `command` has no default, so it only comes from `inherited`.
+fn config_defaults_with(inherited: toml::Table) -> Result<toml::Table, String> {
+    let mut effective = toml::Table::try_from(Config::default())
+        .map_err(|err| format!("failed to serialize config: {err}"))?;
+    effective.remove("command");
+    merge_config_tables(&mut effective, inherited);
+    Ok(effective)
+}
+
+// Unversioned files predate any schema change, so they only gain the version key.
+fn migrate_config_v0_to_v1(_table: &mut toml::Table) {}
+
+fn unknown_config_keys(config: &Config) -> Vec<String> {
+    let mut keys = config.extra.keys().cloned().collect::<Vec<String>>();
+    keys.extend(
+        config
+            .limits
+            .extra
+            .keys()
+            .map(|key| format!("limits.{key}")),
+    );
+    keys.extend(
+        config
+            .desktop
+            .extra
+            .keys()
+            .map(|key| format!("desktop.{key}")),
+    );
+    keys.extend(config.hooks.extra.keys().map(|key| format!("hooks.{key}")));
+    keys.extend(
+        config
+            .log_sinks
+            .extra
+            .keys()
+            .map(|key| format!("log_sinks.{key}")),
+    );
+    keys
+}
+
+fn describe_toml_error(content: &str, err: &toml::de::Error) -> String {
+    let message = err.message().trim();
+    let Some(span) = err.span() else {
+        return message.to_string();
+    };
+    let before = &content[..span.start.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |tail| tail.chars().count())
+        + 1;
+    format!("line {line}, column {column}: {message}")
+}
+
+fn save_config(path: &PathBuf, config: &Config) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("failed to create config dir: {err}"))?;
+    }
+
+    let mut generated = toml::Table::try_from(config)
+        .map_err(|err| format!("failed to serialize config: {err}"))?;
+    if let Ok(inherited) = inherited_config_table(config.extends.as_deref()) {
+        let effective = config_defaults_with(inherited)?;
+        let existing = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str::<toml::Table>(&content).ok());
+        strip_inherited_keys(&mut generated, &effective, existing.as_ref());
+    }
+    let payload = toml::to_string_pretty(&generated)
+        .map_err(|err| format!("failed to serialize config: {err}"))?;
+
+    // Keep the user's comments and key order by editing the existing document in place.
+    let payload = match fs::read_to_string(path) {
+        Ok(existing) if parse_config(&existing).is_ok() => {
+            merge_into_existing_config(&existing, &payload).unwrap_or(payload)
+        }
+        Ok(_) => {
+            backup_config_file(path)?;
+            payload
+        }
+        Err(_) => payload,
+    };
+
+    write_file_atomically(path, payload.as_bytes())
+        .map_err(|err| format!("failed to write config: {err}"))?;
+    Ok(())
+}
+
+fn merge_into_existing_config(existing: &str, generated: &str) -> Option<String> {
+    let mut document = existing.parse::<toml_edit::Document>().ok()?;
+    let generated = generated.parse::<toml_edit::Document>().ok()?;
+    merge_toml_table(document.as_table_mut(), generated.as_table());
+    Some(document.to_string())
+}
+
+fn merge_toml_table(target: &mut dyn toml_edit::TableLike, source: &dyn toml_edit::TableLike) {
+    let stale = target
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !source.contains_key(key))
+        .collect::<Vec<String>>();
+    for key in stale {
+        target.remove(&key);
+    }
+
+    for (key, item) in source.iter() {
+        match target.get_mut(key) {
+            Some(existing) => merge_toml_item(existing, item),
+            None => {
+                target.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+fn merge_toml_item(target: &mut toml_edit::Item, source: &toml_edit::Item) {
+    if let (Some(target_table), Some(source_table)) =
+        (target.as_table_like_mut(), source.as_table_like())
+    {
+        merge_toml_table(target_table, source_table);
+        return;
+    }
+
+    match (target.as_value_mut(), source.as_value()) {
+        (Some(existing), Some(value)) => {
+            if !toml_values_equal(existing, value) {
+                let decor = existing.decor().clone();
+                *existing = value.clone();
+                *existing.decor_mut() = decor;
+            }
+        }
+        _ => *target = source.clone(),
+    }
+}
+
+fn toml_values_equal(left: &toml_edit::Value, right: &toml_edit::Value) -> bool {
+    use toml_edit::Value;
+
+    match (left, right) {
+        (Value::String(left), Value::String(right)) => left.value() == right.value(),
+        (Value::Integer(left), Value::Integer(right)) => left.value() == right.value(),
+        (Value::Float(left), Value::Float(right)) => left.value() == right.value(),
+        (Value::Boolean(left), Value::Boolean(right)) => left.value() == right.value(),
+        (Value::Datetime(left), Value::Datetime(right)) => left.value() == right.value(),
+        (Value::Array(left), Value::Array(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .zip(right.iter())
+                    .all(|(left, right)| toml_values_equal(left, right))
+        }
+        (Value::InlineTable(left), Value::InlineTable(right)) => {
+            left.len() == right.len()
+                && left.iter().all(|(key, value)| {
+                    right
+                        .get(key)
+                        .is_some_and(|other| toml_values_equal(value, other))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Writes through a temp file in the same directory and renames it over `path`,
+/// so readers never observe a partially written file.
+fn write_file_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // Write through symlinks instead of replacing them with a regular file.
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = parent.join(format!(".{file_name}.{}.tmp", process::id()));
+    let mode = fs::metadata(&path)
+        .map(|metadata| metadata.permissions().mode() & 0o7777)
+        .unwrap_or(CONFIG_FILE_MODE);
+
+    let _ = fs::remove_file(&temp_path);
+    let result = (|| {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temp_path)?;
+        file.write_all(contents)?;
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+
+    if let Ok(dir) = fs::File::open(parent) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn backup_config_file(path: &Path) -> Result<PathBuf, String> {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{stamp}.bak"));
+    let backup = PathBuf::from(backup);
+    fs::copy(path, &backup)
+        .map_err(|err| format!("failed to back up {}: {err}", path.display()))?;
+    Ok(backup)
+}
+
+fn check_profile_from_cli(cli: &CliOptions) -> Result<(), String> {
+    let config_path = existing_profile_config(&cli.profile)?;
+    let content = fs::read_to_string(&config_path)
+        .map_err(|err| format!("failed to read {}: {err}", config_path.display()))?;
+    let config =
+        parse_config(&content).map_err(|err| format!("{}: {err}", config_path.display()))?;
+
+    for key in unknown_config_keys(&config) {
+        println!(
+            "{}: warning: unknown key '{key}' is ignored",
+            config_path.display()
+        );
+    }
+
+    let mut problems = Vec::new();
+    if let Err(err) = validate_command_override(&config.command) {
+        problems.push(err.replace("-cmd/--command value", "command"));
+    }
+    if let Err(err) = validate_command_template(&config.command, &cli.profile, &config.vars) {
+        problems.push(format!("command: {err}"));
+    }
+    if config
+        .requires
+        .iter()
+        .any(|required| required == &cli.profile)
+    {
+        problems.push("requires: a profile cannot require itself".to_string());
+    } else if !config.requires.is_empty() {
+        if let Err(err) = check_dependency_cycle(&cli.profile) {
+            problems.push(format!("requires: {err}"));
+        }
+    }
+    if let Some(dir) = history_dir(&cli.profile) {
+        if let Err(err) = load_run_history(&dir) {
+            problems.push(format!("history: {err}"));
+        }
+    }
+    if let Some(encoding) = config.log_encoding.as_deref() {
+        if let Err(err) = validate_log_encoding(encoding) {
+            problems.push(format!("log_encoding: {err}"));
+        }
+    }
+    if config.pty && config.backend == BackendKind::Systemd {
+        problems.push("pty: not supported with the systemd backend".to_string());
+    }
+    if let Ok(args) = shell_words::split(&config.command) {
+        if config.elevation == ElevationMode::Pkexec && is_sudo_command(&args) {
+            if let Err(err) = sudo_to_pkexec(&args) {
+                problems.push(format!("elevation: {err}"));
+            }
+            if !config.env.is_empty() {
+                println!(
+                    "{}: warning: pkexec clears the environment, so [env] does not reach the command",
+                    config_path.display()
+                );
+            }
+        }
+        if config.elevation == ElevationMode::SudoStdin
+            && config.backend == BackendKind::Systemd
+            && is_sudo_command(&args)
+        {
+            problems
+                .push("elevation: sudo-stdin does not work with the systemd backend".to_string());
+        }
+    }
+    for kind in HookKind::ALL {
+        if let Some(command) = config.hooks.command(kind) {
+            if let Err(err) = validate_command_template(command, &cli.profile, &config.vars) {
+                problems.push(format!("hooks.{}: {err}", kind.name()));
+            }
+        }
+    }
+    if let Some(check) = config.ready_check.as_deref() {
+        if let Err(err) = shell_words::split(check) {
+            problems.push(format!("ready_check: parse error: {err}"));
+        }
+    }
+    if let Err(err) = resolve_process_limits(&config.limits) {
+        problems.push(format!("limits: {err}"));
+    }
+    if let Some(icon) = config.icon_path.as_ref() {
+        match fs::read(icon) {
+            Ok(bytes) => {
+                if let Err(err) = image::load_from_memory(&bytes) {
+                    problems.push(format!("icon_path: invalid image {icon}: {err}"));
+                }
+            }
+            Err(err) => problems.push(format!("icon_path: unable to read {icon}: {err}")),
+        }
+    }
+    if config.log_file_path.as_deref().is_some_and(str::is_empty) {
+        problems.push("log_file_path: cannot be empty".to_string());
+    }
+    if let (Ok(exec_path), Ok(icon_path)) =
+        (env::current_exe(), resolve_icon_path_for_desktop(&config))
+    {
+        let entry = desktop_entry(&exec_path, &icon_path, &cli.profile, false, &config.desktop);
+        if let Err(err) = ensure_valid_desktop_entry(&entry) {
+            problems.push(format!("desktop: {err}"));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{}: ok", config_path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}: {problem}", config_path.display());
+    }
+    Err(format!("{} problem(s) found", problems.len()))
+}
+
+fn sanitize_profile_name(profile: &str) -> String {
+    let mut cleaned = profile
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    if cleaned.is_empty() {
+        cleaned = DEFAULT_PROFILE.to_string();
+    }
+    cleaned
+}
+
+fn profile_data_dir(profile: &str) -> Option<PathBuf> {
+    ProjectDirs::from("com", APP_NAME, APP_NAME).map(|proj| {
+        proj.data_local_dir()
+            .join("profiles")
+            .join(sanitize_profile_name(profile))
+    })
+}
+
+fn profile_icon_path(profile: &str) -> Option<PathBuf> {
+    profile_data_dir(profile).map(|dir| dir.join(ICON_FILE_NAME))
+}
+
+fn bundled_icon_path() -> Option<PathBuf> {
+    ProjectDirs::from("com", APP_NAME, APP_NAME)
+        .map(|proj| proj.data_local_dir().join(BUNDLED_ICON_FILE_NAME))
+}
+
+fn copy_icon_to_profile(source_path: &Path, profile: &str) -> Result<PathBuf, String> {
+    let bytes = fs::read(source_path).map_err(|err| format!("unable to read icon file: {err}"))?;
+    store_profile_icon(&bytes, profile)
+}
+
+fn store_profile_icon(bytes: &[u8], profile: &str) -> Result<PathBuf, String> {
+    image::load_from_memory(bytes).map_err(|err| format!("invalid icon image: {err}"))?;
+
+    let target_path = profile_icon_path(profile)
+        .ok_or_else(|| "unable to resolve icon storage path".to_string())?;
+    if let Some(parent) = target_path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("unable to create icon dir: {err}"))?;
+    }
+    fs::write(&target_path, bytes).map_err(|err| format!("unable to store icon copy: {err}"))?;
+    Ok(target_path)
+}
+
+fn ensure_bundled_icon_file() -> Result<PathBuf, std::io::Error> {
+    let icon_path = bundled_icon_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "project dirs"))?;
+    if let Some(parent) = icon_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&icon_path, include_bytes!("../assets/icon.png"))?;
+    Ok(icon_path)
+}
+
+fn resolve_icon_path_for_desktop(config: &Config) -> Result<PathBuf, std::io::Error> {
+    if let Some(path) = config.icon_path.as_ref() {
+        let icon = PathBuf::from(path);
+        if icon.exists() {
+            return Ok(icon);
+        }
+    }
+    ensure_bundled_icon_file()
+}
+
+fn load_window_icon_pixbuf(config: &Config) -> Option<Pixbuf> {
+    let icon_path = resolve_icon_path_for_desktop(config).ok()?;
+    Pixbuf::from_file(icon_path).ok()
+}
+
+fn load_tray_icon(config: &Config) -> Result<Icon, Box<dyn std::error::Error>> {
+    if let Some(path) = config.icon_path.as_ref() {
+        let icon_path = PathBuf::from(path);
+        if icon_path.exists() {
+            match fs::read(&icon_path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| image::load_from_memory(&bytes).map_err(|err| err.to_string()))
+            {
+                Ok(image) => {
+                    let rgba = image.to_rgba8();
+                    let (width, height) = rgba.dimensions();
+                    return Ok(Icon::from_rgba(rgba.into_raw(), width, height)?);
+                }
+                Err(err) => eprintln!(
+                    "failed to load profile icon at {}: {err}. falling back to bundled icon",
+                    icon_path.display()
+                ),
+            }
+        }
+    }
+
+    let bytes = include_bytes!("../assets/icon.png");
+    let image = image::load_from_memory(bytes)?;
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    Ok(Icon::from_rgba(rgba.into_raw(), width, height)?)
+}
+
+fn desktop_file_name(profile: &str) -> String {
+    format!("{APP_NAME}_{}.desktop", sanitize_profile_name(profile))
+}
+
+fn desktop_entry(
+    exec_path: &Path,
+    icon_path: &Path,
+    profile: &str,
+    autostart: bool,
+    settings: &DesktopSettings,
+) -> String {
+    let profile_name = sanitize_profile_name(profile);
+    let mut entry = String::from("[Desktop Entry]\n");
+    entry.push_str("Type=Application\n");
+
+    let display_name = settings
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("{profile_name} ({APP_NAME})"));
+    entry.push_str(&format!("Name={}\n", desktop_escape_value(&display_name)));
+
+    let comment = settings
+        .comment
+        .clone()
+        .unwrap_or_else(|| format!("Run the {profile_name} command from the system tray"));
+    entry.push_str(&format!("Comment={}\n", desktop_escape_value(&comment)));
+
+    let exec_arg = desktop_escape_arg(&exec_path.to_string_lossy());
+    let profile_arg = desktop_escape_arg(profile);
+    let exec = format!("{exec_arg} --config {profile_arg}");
+    entry.push_str(&format!("Exec={}\n", desktop_escape_value(&exec)));
+    entry.push_str(&format!(
+        "Icon={}\n",
+        desktop_escape_value(&icon_path.to_string_lossy())
+    ));
+    entry.push_str("Terminal=false\n");
+    entry.push_str("Categories=Utility;\n");
+
+    let keywords = settings
+        .keywords
+        .clone()
+        .unwrap_or_else(|| vec![APP_NAME.to_string(), "tray".to_string(), profile_name]);
+    if !keywords.is_empty() {
+        let keywords = keywords
+            .iter()
+            .map(|keyword| desktop_escape_value(keyword).replace(';', "\\;"))
+            .collect::<Vec<String>>();
+        entry.push_str(&format!("Keywords={};\n", keywords.join(";")));
+    }
+
+    let wm_class = settings.startup_wm_class.as_deref().unwrap_or(APP_NAME);
+    entry.push_str(&format!(
+        "StartupWMClass={}\n",
+        desktop_escape_value(wm_class)
+    ));
+    if autostart {
+        entry.push_str("X-GNOME-Autostart-enabled=true\n");
+    }
+
+    let actions = desktop_actions(settings);
+    if !actions.is_empty() {
+        let ids = actions
+            .iter()
+            .map(|action| action.as_str())
+            .collect::<Vec<&str>>();
+        entry.push_str(&format!("Actions={};\n", ids.join(";")));
+        for action in actions {
+            entry.push_str(&format!("\n[Desktop Action {}]\n", action.as_str()));
+            entry.push_str(&format!("Name={}\n", desktop_escape_value(action.label())));
+            let exec = format!("{exec_arg} ctl --config {profile_arg} {}", action.as_str());
+            entry.push_str(&format!("Exec={}\n", desktop_escape_value(&exec)));
+        }
+    }
+    entry
+}
+
+fn desktop_actions(settings: &DesktopSettings) -> Vec<ControlAction> {
+    match settings.actions.as_ref() {
+        Some(actions) => actions
+            .iter()
+            .copied()
+            .filter(|action| *action != ControlAction::Status)
+            .collect(),
+        None => vec![
+            ControlAction::Start,
+            ControlAction::Stop,
+            ControlAction::Restart,
+            ControlAction::Logs,
+        ],
+    }
+}
+
+fn desktop_escape_arg(value: &str) -> String {
+    let percent_escaped = value.replace('%', "%%");
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|ch| ch.is_whitespace() || DESKTOP_EXEC_RESERVED.contains(ch));
+    if !needs_quotes {
+        return percent_escaped;
+    }
+
+    let mut escaped = String::with_capacity(percent_escaped.len() + 2);
+    escaped.push('"');
+    for ch in percent_escaped.chars() {
+        if matches!(ch, '"' | '`' | '$' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped.push('"');
+    escaped
+}
+
+// String-level escaping from the Desktop Entry spec; Exec values get this on top of
+// the argument quoting above.
+fn desktop_escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, ch) in value.chars().enumerate() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            ' ' if index == 0 => escaped.push_str("\\s"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn desktop_unescape_value(value: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            // Kept escaped so list values still split correctly.
+            Some(';') => unescaped.push_str("\\;"),
+            Some(other) => return Err(format!("invalid escape sequence '\\{other}'")),
+            None => return Err("trailing backslash".to_string()),
+        }
+    }
+    Ok(unescaped)
+}
+
+fn ensure_valid_desktop_entry(contents: &str) -> Result<(), String> {
+    let problems = validate_desktop_entry(contents);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+fn validate_desktop_entry(contents: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut groups: Vec<(String, Vec<(String, String)>)> = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_no = index + 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let Some(name) = header.strip_suffix(']') else {
+                problems.push(format!("line {line_no}: malformed group header"));
+                continue;
+            };
+            if name.contains(['[', ']']) || name.chars().any(char::is_control) {
+                problems.push(format!("line {line_no}: invalid group name '{name}'"));
+            }
+            if groups.iter().any(|(group, _)| group == name) {
+                problems.push(format!("line {line_no}: duplicate group [{name}]"));
+            }
+            groups.push((name.to_string(), Vec::new()));
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            problems.push(format!("line {line_no}: expected Key=Value"));
+            continue;
+        };
+        let key = key.trim_end();
+        let value = value.trim_start();
+        let Some((_, entries)) = groups.last_mut() else {
+            problems.push(format!("line {line_no}: key '{key}' outside of a group"));
+            continue;
+        };
+
+        let (base, locale) = match key.split_once('[') {
+            Some((base, rest)) => (base, Some(rest)),
+            None => (key, None),
+        };
+        if base.is_empty()
+            || !base
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+        {
+            problems.push(format!("line {line_no}: invalid key '{key}'"));
+        }
+        if locale.is_some_and(|locale| !locale.ends_with(']') || locale.len() < 2) {
+            problems.push(format!("line {line_no}: malformed locale in key '{key}'"));
+        }
+        if entries.iter().any(|(existing, _)| existing == key) {
+            problems.push(format!("line {line_no}: duplicate key '{key}'"));
+        }
+        if value.chars().any(char::is_control) {
+            problems.push(format!("line {line_no}: control character in '{key}'"));
+        }
+
+        match desktop_unescape_value(value) {
+            Ok(unescaped) => entries.push((key.to_string(), unescaped)),
+            Err(err) => {
+                problems.push(format!("line {line_no}: {err} in '{key}'"));
+                entries.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+
+    let Some((first_group, main)) = groups.first() else {
+        problems.push("missing [Desktop Entry] group".to_string());
+        return problems;
+    };
+    if first_group != "Desktop Entry" {
+        problems.push("first group must be [Desktop Entry]".to_string());
+        return problems;
+    }
+
+    let lookup = |entries: &[(String, String)], key: &str| {
+        entries
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value.clone())
+    };
+
+    let entry_type = lookup(main, "Type");
+    match entry_type.as_deref() {
+        None => problems.push("missing required key 'Type'".to_string()),
+        Some("Application" | "Link" | "Directory") => {}
+        Some(other) => problems.push(format!("unknown Type '{other}'")),
+    }
+    if lookup(main, "Name").is_none() {
+        problems.push("missing required key 'Name'".to_string());
+    }
+
+    for key in DESKTOP_BOOLEAN_KEYS {
+        if let Some(value) = lookup(main, key) {
+            if value != "true" && value != "false" {
+                problems.push(format!("'{key}' must be true or false, got '{value}'"));
+            }
+        }
+    }
+
+    let dbus_activatable = lookup(main, "DBusActivatable").as_deref() == Some("true");
+    match lookup(main, "Exec") {
+        Some(exec) => {
+            if let Err(err) = validate_desktop_exec(&exec) {
+                problems.push(format!("invalid Exec: {err}"));
+            }
+        }
+        None if entry_type.as_deref() == Some("Application") && !dbus_activatable => {
+            problems.push("missing 'Exec' for Application entry".to_string());
+        }
+        None => {}
+    }
+
+    let declared_actions = lookup(main, "Actions")
+        .map(|actions| {
+            actions
+                .split(';')
+                .filter(|action| !action.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    for action in &declared_actions {
+        let group_name = format!("Desktop Action {action}");
+        match groups.iter().find(|(group, _)| *group == group_name) {
+            Some((_, entries)) => {
+                if lookup(entries, "Name").is_none() {
+                    problems.push(format!("[{group_name}] is missing 'Name'"));
+                }
+                match lookup(entries, "Exec") {
+                    Some(exec) => {
+                        if let Err(err) = validate_desktop_exec(&exec) {
+                            problems.push(format!("invalid Exec in [{group_name}]: {err}"));
+                        }
+                    }
+                    None if !dbus_activatable => {
+                        problems.push(format!("[{group_name}] is missing 'Exec'"));
+                    }
+                    None => {}
+                }
+            }
+            None => problems.push(format!("action '{action}' has no [{group_name}] group")),
+        }
+    }
+
+    for (group, _) in groups.iter().skip(1) {
+        if let Some(action) = group.strip_prefix("Desktop Action ") {
+            if !declared_actions.iter().any(|declared| declared == action) {
+                problems.push(format!("[{group}] is not listed in 'Actions'"));
+            }
+        }
+    }
+
+    problems
+}
+
+fn validate_desktop_exec(exec: &str) -> Result<(), String> {
+    if exec.trim().is_empty() {
+        return Err("empty command line".to_string());
+    }
+
+    let mut in_quotes = false;
+    let mut chars = exec.chars();
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' => in_quotes = false,
+                '\\' => match chars.next() {
+                    Some('"' | '`' | '$' | '\\') => {}
+                    Some(other) => {
+                        return Err(format!("invalid escape '\\{other}' inside quotes"));
+                    }
+                    None => return Err("unterminated escape inside quotes".to_string()),
+                },
+                '`' | '$' => return Err(format!("unescaped '{ch}' inside quotes")),
+                '%' if chars.next() != Some('%') => {
+                    return Err("field codes are not allowed inside quotes".to_string());
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match ch {
+            '"' => in_quotes = true,
+            '%' => match chars.next() {
+                Some(code) if DESKTOP_EXEC_FIELD_CODES.contains(code) => {}
+                Some(code) => return Err(format!("unknown field code '%{code}'")),
+                None => return Err("dangling '%' at end of command line".to_string()),
+            },
+            ch if ch != ' ' && (ch.is_whitespace() || DESKTOP_EXEC_RESERVED.contains(ch)) => {
+                return Err(format!("reserved character '{ch}' must be quoted"));
+            }
+            _ => {}
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted argument".to_string());
+    }
+    Ok(())
+}
+
+fn systemd_service_name(profile: &str) -> String {
+    format!(
+        "{SYSTEMD_UNIT_PREFIX}{}.service",
+        sanitize_profile_name(profile)
+    )
+}
+
+fn systemd_service_unit(exec_path: &Path, profile: &str) -> String {
+    let mut unit = String::from("[Unit]\n");
+    unit.push_str(&format!(
+        "Description={APP_NAME} tray ({})\n",
+        sanitize_profile_name(profile)
+    ));
+    unit.push_str("PartOf=graphical-session.target\n");
+    unit.push_str("After=graphical-session.target\n");
+    unit.push('\n');
+
+    unit.push_str("[Service]\n");
+    unit.push_str("Type=simple\n");
+    let exec = format!(
+        "{} --config {}",
+        systemd_escape_arg(&exec_path.to_string_lossy()),
+        systemd_escape_arg(profile)
+    );
+    unit.push_str(&format!("ExecStart={exec}\n"));
+    unit.push_str("Restart=on-failure\n");
+    unit.push_str("RestartSec=3\n");
+    unit.push('\n');
+
+    unit.push_str("[Install]\n");
+    unit.push_str("WantedBy=graphical-session.target\n");
+    unit
+}
+
+fn systemd_escape_arg(value: &str) -> String {
+    let specifier_escaped = value.replace('%', "%%").replace('$', "$$");
+    let needs_quotes = value
+        .chars()
+        .any(|ch| ch.is_whitespace() || ch == '"' || ch == '\'' || ch == '\\' || ch == ';');
+    if !needs_quotes {
+        return specifier_escaped;
+    }
+
+    let mut escaped = String::with_capacity(specifier_escaped.len() + 2);
+    escaped.push('"');
+    for ch in specifier_escaped.chars() {
+        if ch == '"' || ch == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn write_desktop_file(path: &PathBuf, contents: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+/// Writes a systemd user unit. It is replaced atomically so a `daemon-reload`
+/// racing with the write never sees half a unit.
+fn write_unit_file(path: &Path, contents: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_file_atomically(path, contents.as_bytes())
+}
+
+fn buffer_text(buffer: &gtk::TextBuffer) -> String {
+    let start = buffer.start_iter();
+    let end = buffer.end_iter();
+    buffer
+        .text(&start, &end, true)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn append_log_to_file(path: &Path, line: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{line}")
+}
+
+fn append_log(state: &mut AppState, line: String) {
+    append_output(state, LogStream::Tray, LogSegment::Line(line));
+}
+
+fn append_output(state: &mut AppState, stream: LogStream, segment: LogSegment) {
+    match segment {
+        LogSegment::Line(line) => {
+            finish_progress_log(state);
+            add_log_line(state, line.clone());
+            write_log_record(state, stream, &line);
+        }
+        LogSegment::Progress(text) => update_progress_log(state, stream, text, false),
+        LogSegment::ProgressEnd(text) => update_progress_log(state, stream, text, true),
+    }
+}
+
+/// Shows a line of `\r` progress output. While a progress sequence is open
+/// each update replaces the previous one instead of adding a line.
+fn update_progress_log(state: &mut AppState, stream: LogStream, text: String, finished: bool) {
+    // Progress from another stream must not overwrite the open sequence.
+    if state
+        .log_progress
+        .as_ref()
+        .is_some_and(|(open, _)| *open != stream)
+    {
+        finish_progress_log(state);
+    }
+    if state.log_progress.is_some() {
+        replace_last_log_line(state, text.clone());
+    } else {
+        add_log_line(state, text.clone());
+    }
+    if finished {
+        // A `\r\n` line ending repeats the update the log file already has.
+        let previous = state.log_progress.take();
+        let repeated = previous.is_some_and(|(_, previous)| previous == text);
+        if state.saved_log_final_progress_only || !repeated {
+            write_log_record(state, stream, &text);
+        }
+    } else {
+        if !state.saved_log_final_progress_only {
+            write_log_record(state, stream, &text);
+        }
+        state.log_progress = Some((stream, text));
+    }
+}
+
+/// Closes an open progress sequence, writing its last update to the log file
+/// if intermediate updates were held back.
+fn finish_progress_log(state: &mut AppState) {
+    if let Some((stream, text)) = state.log_progress.take() {
+        if state.saved_log_final_progress_only {
+            write_log_record(state, stream, &text);
+        }
+    }
+}
+
+fn add_log_line(state: &mut AppState, line: String) {
+    let mut rebuild = false;
+    if state.log_lines.len() >= MAX_LOG_LINES {
+        state.log_lines.pop_front();
+        rebuild = true;
+    }
+    state.log_lines.push_back(line.clone());
+
+    if rebuild {
+        state.logs_buffer.set_text("");
+        for line in &state.log_lines {
+            state.log_last_line_offset = state.logs_buffer.end_iter().offset();
+            insert_log_line(&state.logs_buffer, line);
+        }
+    } else {
+        state.log_last_line_offset = state.logs_buffer.end_iter().offset();
+        insert_log_line(&state.logs_buffer, &line);
+    }
+
+    scroll_logs_to_end(state);
+    set_logs_status(&state.logs_status_label, state.log_lines.len(), None);
+}
+
+fn replace_last_log_line(state: &mut AppState, line: String) {
+    let Some(last) = state.log_lines.back_mut() else {
+        add_log_line(state, line);
+        return;
+    };
+    *last = line.clone();
+    let mut start_iter = state.logs_buffer.iter_at_offset(state.log_last_line_offset);
+    let mut end_iter = state.logs_buffer.end_iter();
+    state.logs_buffer.delete(&mut start_iter, &mut end_iter);
+    insert_log_line(&state.logs_buffer, &line);
+    scroll_logs_to_end(state);
+}
+
+fn scroll_logs_to_end(state: &AppState) {
+    let mut end_iter = state.logs_buffer.end_iter();
+    state
+        .logs_view
+        .scroll_to_iter(&mut end_iter, 0.0, false, 0.0, 0.0);
+}
+
+/// Writes a finished log line to the log file and the configured log sinks.
+fn write_log_record(state: &mut AppState, stream: LogStream, line: &str) {
+    let line = strip_ansi(line);
+    match state.saved_log_format {
+        LogFormat::Text => write_log_file_line(state, &line),
+        LogFormat::Jsonl => write_log_file_line(state, &json_line_record(state, stream, &line)),
+    }
+    write_run_log_line(state, &line);
+    forward_log_line(state, stream, &line);
+}
+
+fn write_run_log_line(state: &AppState, line: &str) {
+    if let Some(path) = state.run_log_path.as_ref() {
+        if let Err(err) = append_log_to_file(path, line) {
+            eprintln!("failed to write run log at {}: {err}", path.display());
+        }
+    }
+}
+
+fn write_log_file_line(state: &AppState, record: &str) {
+    if let Some(path) = state.log_file_path.as_ref() {
+        if let Err(err) = append_log_to_file(path, record) {
+            eprintln!("failed to write log file at {}: {err}", path.display());
+        }
+    }
+}
+
+/// A change in the command's lifecycle. JSON log files record these as event
+/// records instead of text lines.
+#[derive(Debug, Clone, Copy)]
+enum RunEvent {
+    Started,
+    Exited(Option<i32>),
+}
+
+impl RunEvent {
+    fn message(self) -> String {
+        match self {
+            RunEvent::Started => "command started".to_string(),
+            RunEvent::Exited(Some(code)) => format!("command exited with code {code}"),
+            RunEvent::Exited(None) => "command exited".to_string(),
+        }
+    }
+}
+
+fn log_run_event(state: &mut AppState, event: RunEvent) {
+    let message = event.message();
+    finish_progress_log(state);
+    if let RunEvent::Started = event {
+        record_run_launched(state);
+    }
+    add_log_line(state, message.clone());
+    match state.saved_log_format {
+        LogFormat::Text => write_log_file_line(state, &message),
+        LogFormat::Jsonl => write_log_file_line(state, &json_event_record(state, event)),
+    }
+    write_run_log_line(state, &message);
+    forward_log_line(state, LogStream::Tray, &message);
+    if let RunEvent::Exited(code) = event {
+        record_run_end(state, code);
+    }
+}
+
+fn history_dir(profile: &str) -> Option<PathBuf> {
+    profile_data_dir(profile).map(|dir| dir.join(HISTORY_DIR_NAME))
+}
+
+fn load_run_history(dir: &Path) -> Result<RunHistory, String> {
+    let path = dir.join(HISTORY_FILE_NAME);
+    match fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents)
+            .map_err(|err| format!("failed to parse {}: {err}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(RunHistory::default()),
+        Err(err) => Err(format!("failed to read {}: {err}", path.display())),
+    }
+}
+
+fn save_run_history(dir: &Path, history: &RunHistory) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+    let contents = toml::to_string(history)
+        .map_err(|err| format!("failed to serialize run history: {err}"))?;
+    let path = dir.join(HISTORY_FILE_NAME);
+    write_file_atomically(&path, contents.as_bytes())
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))
+}
+
+/// Adds a new run to the history as soon as Start is requested, so requires
+/// and pre_start output land in its log, and points the run log at its own
+/// file. The oldest runs beyond `history_runs` are dropped with their output.
+fn record_run_start(state: &mut AppState) {
+    if state.run_log_path.is_some() {
+        // The previous run's exit was never seen; close its record anyway.
+        record_run_end(state, None);
+    }
+    let limit = state.saved_history_runs.unwrap_or(DEFAULT_HISTORY_RUNS);
+    let (Some(dir), Some(id)) = (history_dir(&state.profile), state.run_id.clone()) else {
+        return;
+    };
+    if limit == 0 {
+        return;
+    }
+    let mut history = match load_run_history(&dir) {
+        Ok(history) => history,
+        Err(err) => {
+            // Keep the unreadable file for inspection rather than overwrite it.
+            match backup_config_file(&dir.join(HISTORY_FILE_NAME)) {
+                Ok(backup) => append_log(
+                    state,
+                    format!(
+                        "{err}; saved it as {} and started a new run history",
+                        backup.display()
+                    ),
+                ),
+                Err(backup_err) => {
+                    append_log(
+                        state,
+                        format!("{err}; {backup_err}; this run is not recorded"),
+                    );
+                    return;
+                }
+            }
+            RunHistory::default()
+        }
+    };
+    let record = RunRecord {
+        log: format!("{id}.log"),
+        id,
+        started: rfc3339_timestamp(std::time::SystemTime::now()),
+        ended: None,
+        exit_code: None,
+        pid: state.run_pid,
+        not_started: false,
+    };
+    let log_path = dir.join(&record.log);
+    history.runs.push(record);
+    let excess = history.runs.len().saturating_sub(limit);
+    for old in history.runs.drain(..excess) {
+        let _ = fs::remove_file(dir.join(&old.log));
+    }
+    match save_run_history(&dir, &history) {
+        Ok(()) => state.run_log_path = Some(log_path),
+        Err(err) => append_log(state, err),
+    }
+}
+
+/// Fills in the pid once the command is running. A run that was not started
+/// from the tray (an attached systemd unit) gets its record here instead.
+fn record_run_launched(state: &mut AppState) {
+    let current = state
+        .run_log_path
+        .as_deref()
+        .and_then(Path::file_stem)
+        .and_then(|stem| stem.to_str());
+    if current.is_none() || current != state.run_id.as_deref() {
+        record_run_start(state);
+        return;
+    }
+    let pid = state.run_pid;
+    update_run_record(state, false, |record| record.pid = pid);
+}
+
+fn record_run_end(state: &mut AppState, code: Option<i32>) {
+    update_run_record(state, true, |record| {
+        record.ended = Some(rfc3339_timestamp(std::time::SystemTime::now()));
+        record.exit_code = code;
+    });
+}
+
+fn record_run_not_started(state: &mut AppState) {
+    update_run_record(state, true, |record| {
+        record.ended = Some(rfc3339_timestamp(std::time::SystemTime::now()));
+        record.not_started = true;
+    });
+}
+
+/// Edits the history record of the run the run log belongs to, which is no
+/// longer `run_id` when a new run starts before the old one was closed.
+/// `close` also stops writing to that run's log.
+fn update_run_record(state: &mut AppState, close: bool, update: impl FnOnce(&mut RunRecord)) {
+    let Some(log_path) = state.run_log_path.clone() else {
+        return;
+    };
+    if close {
+        state.run_log_path = None;
+    }
+    let (Some(dir), Some(id)) = (
+        log_path.parent(),
+        log_path.file_stem().and_then(|stem| stem.to_str()),
+    ) else {
+        return;
+    };
+    let result = load_run_history(dir).and_then(|mut history| {
+        if let Some(record) = history.runs.iter_mut().find(|record| record.id == id) {
+            update(record);
+        }
+        save_run_history(dir, &history)
+    });
+    if let Err(err) = result {
+        append_log(state, err);
+    }
+}
+
+/// Formats a history timestamp for display, dropping fractions and offset.
+fn history_time(timestamp: &str) -> String {
+    timestamp
+        .get(..19)
+        .unwrap_or(timestamp)
+        .replacen('T', " ", 1)
+}
+
+fn history_row_label(record: &RunRecord, current_run: Option<&str>) -> String {
+    let status = match (&record.ended, record.exit_code) {
+        (Some(_), Some(code)) => format!("exit code {code}"),
+        (Some(_), None) if record.not_started => "did not start".to_string(),
+        (Some(_), None) => "killed".to_string(),
+        (None, _) if current_run == Some(record.id.as_str()) => "running".to_string(),
+        (None, _) => "did not finish".to_string(),
+    };
+    format!("{}  {status}", history_time(&record.started))
+}
+
+/// Opens a window listing the profile's past runs next to the output of the
+/// selected run.
+fn show_history_window(state: &AppState) {
+    let history = match history_dir(&state.profile)
+        .ok_or_else(|| "unable to resolve history directory".to_string())
+        .and_then(|dir| load_run_history(&dir).map(|history| (dir, history)))
+    {
+        Ok(history) => history,
+        Err(err) => {
+            let dialog = gtk::MessageDialog::new(
+                Some(&state.logs_window),
+                gtk::DialogFlags::empty(),
+                gtk::MessageType::Error,
+                gtk::ButtonsType::Close,
+                "Run history is not available.",
+            );
+            dialog.set_secondary_text(Some(&err));
+            dialog.connect_response(|dialog, _| dialog.close());
+            dialog.show_all();
+            return;
+        }
+    };
+    let (dir, history) = history;
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(&format!("Run History ({})", state.profile));
+    window.set_default_size(900, 520);
+    window.set_transient_for(Some(&state.logs_window));
+
+    let list = gtk::ListBox::new();
+    list.set_selection_mode(gtk::SelectionMode::Single);
+    let runs = history.runs.iter().rev().cloned().collect::<Vec<_>>();
+    for record in &runs {
+        let label = gtk::Label::new(Some(&history_row_label(record, state.run_id.as_deref())));
+        label.set_xalign(0.0);
+        label.set_margin_start(8);
+        label.set_margin_end(8);
+        label.set_margin_top(6);
+        label.set_margin_bottom(6);
+        list.add(&label);
+    }
+    if runs.is_empty() {
+        let label = gtk::Label::new(Some("No runs recorded yet"));
+        label.set_margin_top(12);
+        list.set_placeholder(Some(&label));
+        label.show();
+    }
+
+    let buffer = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
+    let text_view = gtk::TextView::with_buffer(&buffer);
+    text_view.set_editable(false);
+    text_view.set_monospace(true);
+    text_view.set_cursor_visible(false);
+    text_view.set_left_margin(8);
+    text_view.set_right_margin(8);
+    text_view.set_top_margin(8);
+    text_view.set_bottom_margin(8);
+
+    let buffer_select = buffer.clone();
+    list.connect_row_selected(move |_, row| {
+        let Some(record) = row.and_then(|row| usize::try_from(row.index()).ok()) else {
+            buffer_select.set_text("");
+            return;
+        };
+        let Some(record) = runs.get(record) else {
+            return;
+        };
+        let text = match fs::read(dir.join(&record.log)) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(err) => format!("output of this run is not available: {err}"),
+        };
+        buffer_select.set_text(&text);
+    });
+
+    let list_scroller =
+        gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    list_scroller.set_size_request(260, -1);
+    list_scroller.add(&list);
+    let text_scroller =
+        gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+    text_scroller.set_hexpand(true);
+    text_scroller.set_vexpand(true);
+    text_scroller.add(&text_view);
+
+    let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+    paned.pack1(&list_scroller, false, false);
+    paned.pack2(&text_scroller, true, false);
+
+    let copy_button = gtk::Button::with_label("Copy Output");
+    let buffer_copy = buffer.clone();
+    copy_button.connect_clicked(move |_| {
+        let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+        clipboard.set_text(&buffer_text(&buffer_copy));
+    });
+    let actions = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    actions.set_margin_start(8);
+    actions.set_margin_end(8);
+    actions.set_margin_top(8);
+    actions.set_margin_bottom(4);
+    actions.pack_end(&copy_button, false, false, 0);
+
+    let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    container.pack_start(&actions, false, false, 0);
+    container.pack_start(&paned, true, true, 0);
+    window.add(&container);
+    window.show_all();
+
+    if let Some(row) = list.row_at_index(0) {
+        list.select_row(Some(&row));
+    }
+}
+
+/// Identifies one run of the command in JSON log records.
+fn new_run_id() -> String {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("{millis}-{}", process::id())
+}
+
+/// Opens a JSON log record with the fields shared by lines and events.
+fn json_record_start(state: &AppState, pid: Option<u32>) -> String {
+    format!(
+        "{{\"timestamp\":{},\"profile\":{},\"run_id\":{},\"pid\":{}",
+        json_string(&rfc3339_timestamp(std::time::SystemTime::now())),
+        json_string(&state.profile),
+        state
+            .run_id
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        pid.map_or_else(|| "null".to_string(), |pid| pid.to_string())
+    )
+}
+
+fn json_line_record(state: &AppState, stream: LogStream, line: &str) -> String {
+    let pid = match stream {
+        LogStream::Stdout | LogStream::Stderr => state.run_pid,
+        LogStream::HookStdout | LogStream::HookStderr | LogStream::Tray | LogStream::Journal => {
+            None
+        }
+    };
+    format!(
+        "{},\"stream\":{},\"text\":{}}}",
+        json_record_start(state, pid),
+        json_string(stream.name()),
+        json_string(line)
+    )
+}
+
+fn json_event_record(state: &AppState, event: RunEvent) -> String {
+    let start = json_record_start(state, state.run_pid);
+    match event {
+        RunEvent::Started => format!("{start},\"event\":\"started\"}}"),
+        RunEvent::Exited(code) => format!(
+            "{start},\"event\":\"exited\",\"exit_code\":{}}}",
+            code.map_or_else(|| "null".to_string(), |code| code.to_string())
+        ),
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if u32::from(ch) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(ch))),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Where a log line came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LogStream {
+    Stdout,
+    Stderr,
+    /// Output of a pre_start, post_start, pre_stop or post_exit hook.
+    HookStdout,
+    HookStderr,
+    /// Messages from the tray itself, such as "command started".
+    Tray,
+    /// Command output read back from the journal for the systemd backend.
+    Journal,
+}
+
+impl LogStream {
+    fn name(self) -> &'static str {
+        match self {
+            LogStream::Stdout | LogStream::HookStdout => "stdout",
+            LogStream::Stderr | LogStream::HookStderr => "stderr",
+            LogStream::Tray => APP_NAME,
+            LogStream::Journal => "journal",
+        }
+    }
+
+    /// Syslog severity, which journald uses as `PRIORITY` as well.
+    fn severity(self) -> u8 {
+        match self {
+            LogStream::Stderr | LogStream::HookStderr => 3,
+            LogStream::Tray => 5,
+            LogStream::Stdout | LogStream::HookStdout | LogStream::Journal => 6,
+        }
+    }
+}
+
+/// Sockets for the configured log sinks, connected on first use.
+#[derive(Default)]
+struct LogForwarder {
+    journald: SinkSocket,
+    syslog: SinkSocket,
+}
+
+#[derive(Default)]
+enum SinkSocket {
+    #[default]
+    Closed,
+    Datagram(UnixDatagram),
+    Stream(UnixStream),
+    /// Connecting failed; lines are dropped until `retry_at`, and each
+    /// failed retry doubles `delay`.
+    Failed {
+        retry_at: Instant,
+        delay: Duration,
+    },
+}
+
+impl SinkSocket {
+    fn send(&mut self, path: &str, name: &str, record: &[u8]) {
+        let previous_delay = match self {
+            SinkSocket::Closed => Some(None),
+            SinkSocket::Failed { retry_at, delay } if Instant::now() >= *retry_at => {
+                Some(Some(*delay))
+            }
+            _ => None,
+        };
+        if let Some(previous_delay) = previous_delay {
+            *self = match connect_sink_socket(path) {
+                Ok(socket) => socket,
+                Err(err) => {
+                    // Retries fail quietly until the sink comes back.
+                    if previous_delay.is_none() {
+                        eprintln!("failed to connect to {name} at {path}: {err}");
+                    }
+                    let delay = previous_delay.map_or(SINK_RETRY_MIN_DELAY, |delay| {
+                        (delay * 2).min(SINK_RETRY_MAX_DELAY)
+                    });
+                    SinkSocket::Failed {
+                        retry_at: Instant::now() + delay,
+                        delay,
+                    }
+                }
+            };
+        }
+        let result = match self {
+            SinkSocket::Datagram(socket) => socket.send(record).map(|_| ()),
+            // Stream sockets need RFC 6587 octet counting to delimit records.
+            SinkSocket::Stream(socket) => socket
+                .write_all(format!("{} ", record.len()).as_bytes())
+                .and_then(|_| socket.write_all(record)),
+            SinkSocket::Closed | SinkSocket::Failed { .. } => return,
+        };
+        match result {
+            Ok(()) => {}
+            // The receiver is not keeping up; drop the line instead of
+            // blocking the interface. A stream may hold half a record now,
+            // so it is reconnected below instead.
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    && matches!(self, SinkSocket::Datagram(_)) => {}
+            Err(err) => {
+                eprintln!("failed to forward log line to {name}: {err}");
+                *self = SinkSocket::Closed;
+            }
+        }
+    }
+}
+
+fn connect_sink_socket(path: &str) -> io::Result<SinkSocket> {
+    let datagram = UnixDatagram::unbound()?;
+    match datagram.connect(path) {
+        Ok(()) => {
+            datagram.set_nonblocking(true)?;
+            Ok(SinkSocket::Datagram(datagram))
+        }
+        Err(err) if err.raw_os_error() == Some(libc::EPROTOTYPE) => {
+            let stream = UnixStream::connect(path)?;
+            stream.set_nonblocking(true)?;
+            Ok(SinkSocket::Stream(stream))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn forward_log_line(state: &mut AppState, stream: LogStream, line: &str) {
+    let sinks = &state.saved_log_sinks;
+    // The systemd backend's output is in the journal already.
+    if stream == LogStream::Journal || !(sinks.journald || sinks.syslog) {
+        return;
+    }
+    let identifier = format!("{APP_NAME}-{}", state.profile);
+    if sinks.journald {
+        let record = journald_record(&identifier, &state.profile, stream, line);
+        state
+            .log_forwarder
+            .journald
+            .send(JOURNALD_SOCKET, "journald", &record);
+    }
+    if sinks.syslog {
+        let path = sinks
+            .syslog_socket
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SYSLOG_SOCKET.to_string());
+        let record = syslog_record(&identifier, stream, line);
+        state
+            .log_forwarder
+            .syslog
+            .send(&path, "syslog", record.as_bytes());
+    }
+}
+
+/// Builds a datagram for journald's native protocol.
+fn journald_record(identifier: &str, profile: &str, stream: LogStream, line: &str) -> Vec<u8> {
+    let mut record = Vec::new();
+    let mut field = |name: &str, value: &str| {
+        if value.contains('\n') {
+            record.extend_from_slice(name.as_bytes());
+            record.push(b'\n');
+            record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            record.extend_from_slice(value.as_bytes());
+        } else {
+            record.extend_from_slice(format!("{name}={value}").as_bytes());
+        }
+        record.push(b'\n');
+    };
+    field("MESSAGE", line);
+    field("PRIORITY", &stream.severity().to_string());
+    field("SYSLOG_IDENTIFIER", identifier);
+    field("GIVETRAY_PROFILE", profile);
+    field("GIVETRAY_STREAM", stream.name());
+    record
+}
+
+/// Formats an RFC 5424 message with the stream name as MSGID.
+fn syslog_record(identifier: &str, stream: LogStream, line: &str) -> String {
+    let priority = SYSLOG_FACILITY_USER * 8 + stream.severity();
+    // APP-NAME is limited to 48 printable ASCII characters.
+    let app_name = identifier
+        .chars()
+        .map(|ch| if ch.is_ascii_graphic() { ch } else { '_' })
+        .take(48)
+        .collect::<String>();
+    format!(
+        "<{priority}>1 {} {} {app_name} {} {} - {line}",
+        rfc3339_timestamp(std::time::SystemTime::now()),
+        hostname(),
+        process::id(),
+        stream.name()
+    )
+}
+
+/// Formats a time as RFC 3339 in local time with microseconds.
+fn rfc3339_timestamp(time: std::time::SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&seconds, &mut tm);
+    }
+    let offset = tm.tm_gmtoff / 60;
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}{sign}{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        since_epoch.subsec_micros(),
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    let len = buf.iter().position(|&byte| byte == 0).unwrap_or(buf.len());
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(name) if result == 0 && !name.is_empty() => name.to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+/// Appends a line to the Logs window, rendering ANSI colours and bold as tags.
+fn insert_log_line(buffer: &gtk::TextBuffer, line: &str) {
+    let mut end_iter = buffer.end_iter();
+    if line.contains('\x1b') {
+        for (text, style) in parse_ansi_line(line) {
+            let offset = end_iter.offset();
+            buffer.insert(&mut end_iter, &text);
+            let start_iter = buffer.iter_at_offset(offset);
+            for tag in ansi_tags(buffer, style) {
+                buffer.apply_tag(&tag, &start_iter, &end_iter);
+            }
+        }
+    } else {
+        buffer.insert(&mut end_iter, line);
+    }
+    buffer.insert(&mut end_iter, "\n");
+}
+
+fn ansi_tags(buffer: &gtk::TextBuffer, style: AnsiStyle) -> Vec<gtk::TextTag> {
+    let Some(table) = buffer.tag_table() else {
+        return Vec::new();
+    };
+    let lookup = |name: &str, setup: &dyn Fn(&gtk::TextTag)| {
+        table.lookup(name).unwrap_or_else(|| {
+            let tag = gtk::TextTag::new(Some(name));
+            setup(&tag);
+            table.add(&tag);
+            tag
+        })
+    };
+    let mut tags = Vec::new();
+    if let Some((r, g, b)) = style.foreground {
+        let color = format!("#{r:02x}{g:02x}{b:02x}");
+        tags.push(lookup(&format!("ansi-fg-{color}"), &|tag| {
+            tag.set_foreground(Some(&color))
+        }));
+    }
+    if style.bold {
+        tags.push(lookup("ansi-bold", &|tag| tag.set_weight(700)));
+    }
+    tags
+}
+
+/// Text attributes selected by ANSI SGR escape sequences.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct AnsiStyle {
+    foreground: Option<(u8, u8, u8)>,
+    bold: bool,
+}
+
+/// Splits a line into runs of text with the ANSI style in effect for each.
+/// Foreground colours and bold are kept; every other escape sequence is dropped.
+fn parse_ansi_line(line: &str) -> Vec<(String, AnsiStyle)> {
+    let mut runs = Vec::new();
+    let mut style = AnsiStyle::default();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut params = String::new();
+                let mut terminator = None;
+                for ch in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&ch) {
+                        terminator = Some(ch);
+                        break;
+                    }
+                    params.push(ch);
+                }
+                if terminator == Some('m') {
+                    if !text.is_empty() {
+                        runs.push((std::mem::take(&mut text), style));
+                    }
+                    apply_sgr(&mut style, &params);
+                }
+            }
+            Some(']') => {
+                // Operating system commands such as window titles end with BEL or ST.
+                while let Some(ch) = chars.next() {
+                    if ch == '\x07' {
+                        break;
+                    }
+                    if ch == '\x1b' {
+                        chars.next_if_eq(&'\\');
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !text.is_empty() {
+        runs.push((text, style));
+    }
+    runs
+}
+
+fn apply_sgr(style: &mut AnsiStyle, params: &str) {
+    let mut codes = params.split(';').map(|code| {
+        if code.is_empty() {
+            Some(0)
+        } else {
+            code.parse::<u16>().ok()
+        }
+    });
+    while let Some(code) = codes.next() {
+        match code {
+            Some(0) => *style = AnsiStyle::default(),
+            Some(1) => style.bold = true,
+            Some(22) => style.bold = false,
+            Some(code @ 30..=37) => style.foreground = Some(ANSI_PALETTE[usize::from(code - 30)]),
+            Some(code @ 90..=97) => {
+                style.foreground = Some(ANSI_PALETTE[usize::from(code - 90 + 8)])
+            }
+            Some(39) => style.foreground = None,
+            Some(selector @ (38 | 48)) => {
+                let color = match codes.next().flatten() {
+                    Some(5) => codes.next().flatten().map(ansi_256_color),
+                    Some(2) => {
+                        let mut channel =
+                            || codes.next().flatten().map(|value| value.min(255) as u8);
+                        match (channel(), channel(), channel()) {
+                            (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                if selector == 38 {
+                    style.foreground = color;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn ansi_256_color(index: u16) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_PALETTE[usize::from(index)],
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u16| {
+                if value == 0 {
+                    0
+                } else {
+                    (55 + value * 40) as u8
+                }
+            };
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        _ => {
+            let gray = (8 + (index.min(255) - 232) * 10) as u8;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn strip_ansi(line: &str) -> String {
+    if !line.contains('\x1b') {
+        return line.to_string();
+    }
+    parse_ansi_line(line)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect()
+}
+
+/// Builds the launch for a start: expands the command (asking for prompts and
+/// secrets) and applies elevation and limits. Returns `None` when the start
+/// was cancelled or failed, after logging why.
+fn prepare_launch(
+    state: &Rc<RefCell<AppState>>,
+    ui_tx: &Sender<UiEvent>,
+) -> Option<(BackendKind, LaunchSpec)> {
+    let mut args = match expand_command_for_start(state) {
+        Ok(Some(args)) if !args.is_empty() => args,
+        Ok(Some(_)) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog("command is empty".to_string()));
+            return None;
+        }
+        Ok(None) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog("start cancelled".to_string()));
+            return None;
+        }
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!("command: {err}")));
+            return None;
+        }
+    };
+
+    let backend_kind = state.borrow().saved_backend;
+    if backend_kind == BackendKind::Systemd && state.borrow().saved_pty {
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog(
+            "pty mode is not supported with the systemd backend".to_string(),
+        ));
+        return None;
+    }
+    if let Some(encoding) = state.borrow().saved_log_encoding.as_deref() {
+        if let Err(err) = validate_log_encoding(encoding) {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "log_encoding: {err}; output is decoded as UTF-8"
+            )));
+        }
+    }
+    let mut env = state.borrow().saved_env.clone();
+    let elevation = state.borrow().saved_elevation;
+    let sudo_password = if is_sudo_command(&args) {
+        match elevation {
+            ElevationMode::SudoStdin => {
+                if backend_kind == BackendKind::Systemd {
+                    let _ = ui_tx.send_blocking(UiEvent::AppendLog(
+                        "sudo password prompts are not supported with the systemd backend"
+                            .to_string(),
+                    ));
+                    return None;
+                }
+                ensure_sudo_flag(&mut args, "-S");
+                // The password is only sent once sudo shows the marker prompt.
+                if strip_sudo_prompt(&mut args) {
+                    let _ = ui_tx.send_blocking(UiEvent::AppendLog(
+                        "sudo -p/--prompt is replaced so the password prompt can be answered"
+                            .to_string(),
+                    ));
+                }
+                args.splice(1..1, ["-p".to_string(), SUDO_PROMPT_MARKER.to_string()]);
+                match stored_sudo_password(state) {
+                    Some(password) => Some(password),
+                    None => match prompt_sudo_password(SUDO_PROMPT) {
+                        Some(password) => {
+                            let mut state = state.borrow_mut();
+                            if state.saved_secret_service {
+                                state.pending_sudo_secret = Some(password.clone());
+                            }
+                            Some(password)
+                        }
+                        None => {
+                            let _ = ui_tx.send_blocking(UiEvent::AppendLog(
+                                "sudo password prompt cancelled".to_string(),
+                            ));
+                            return None;
+                        }
+                    },
+                }
+            }
+            ElevationMode::Askpass => {
+                let wrapper = match write_askpass_wrapper() {
+                    Ok(path) => path,
+                    Err(err) => {
+                        let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                            "unable to set up the askpass helper: {err}"
+                        )));
+                        return None;
+                    }
+                };
+                ensure_sudo_flag(&mut args, "-A");
+                env.insert(
+                    "SUDO_ASKPASS".to_string(),
+                    wrapper.to_string_lossy().to_string(),
+                );
+                None
+            }
+            ElevationMode::Pkexec => {
+                args = match sudo_to_pkexec(&args) {
+                    Ok(args) => Zeroizing::new(args),
+                    Err(err) => {
+                        let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+                        return None;
+                    }
+                };
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let limits = match resolve_process_limits(&state.borrow().saved_limits) {
+        Ok(limits) => limits,
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "invalid resource limits: {err}"
+            )));
+            return None;
+        }
+    };
+
+    Some((
+        backend_kind,
+        LaunchSpec {
+            unit_name: systemd_unit_name(&state.borrow().profile),
+            args,
+            limits,
+            env,
+            sudo_armed: Arc::new(AtomicBool::new(sudo_password.is_some())),
+            sudo_password,
+            keep_stdin_open: state.borrow().saved_keep_stdin_open,
+            pty: state.borrow().saved_pty,
+            log_encoding: state.borrow().saved_log_encoding.clone(),
+        },
+    ))
+}
+
+fn start_command(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    if state.borrow().child.is_some() {
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog("command is already running".to_string()));
+        return;
+    }
+    if state.borrow().starting {
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog(
+            "command is already starting".to_string(),
+        ));
+        return;
+    }
+    {
+        // Set before anything is logged so that requires and pre_start output
+        // belongs to the new run.
+        let mut state = state.borrow_mut();
+        state.stop_requested = false;
+        state.run_id = Some(new_run_id());
+        state.run_pid = None;
+        record_run_start(&mut state);
+        // Prompts and the Secret Service run nested main loops, so a second
+        // Start could arrive before this one has launched anything.
+        state.starting = true;
+    }
+
+    let prepared = prepare_launch(&state, &ui_tx);
+    state.borrow_mut().starting = false;
+    let Some((backend_kind, spec)) = prepared else {
+        abandon_start(&state);
+        return;
+    };
+
+    let pre_start = match prepare_hook(&state.borrow(), HookKind::PreStart) {
+        Ok(hook) => hook,
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+            abandon_start(&state);
+            return;
+        }
+    };
+    let (profile, requires) = {
+        let state = state.borrow();
+        (state.profile.clone(), state.saved_requires.clone())
+    };
+    if pre_start.is_none() && requires.is_empty() {
+        launch_command(state, ui_tx, backend_kind, spec);
+        return;
+    }
+
+    // Dependencies and the hook may take a while (a VPN connecting, a share
+    // mounting), so they run off the GTK thread and the launch continues once
+    // they report back.
+    state.borrow_mut().starting = true;
+    let (done_tx, done_rx) = async_channel::bounded(1);
+    let worker_ui_tx = ui_tx.clone();
+    thread::spawn(move || {
+        let result = start_dependencies(&profile, &requires, &worker_ui_tx).and_then(|_| {
+            match pre_start.as_ref() {
+                Some(hook) => run_hook(hook, &worker_ui_tx, &[]),
+                None => Ok(()),
+            }
+        });
+        let _ = done_tx.send_blocking(result);
+    });
+    MainContext::default().spawn_local(async move {
+        let result = done_rx
+            .recv()
+            .await
+            .unwrap_or_else(|_| Err("start preparation did not report back".to_string()));
+        let cancelled = {
+            let mut state = state.borrow_mut();
+            state.starting = false;
+            state.stop_requested
+        };
+        match result {
+            Err(err) => {
+                let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!("{err}; start aborted")));
+                abandon_start(&state);
+            }
+            Ok(()) if cancelled => {
+                let _ = ui_tx.send_blocking(UiEvent::AppendLog("start cancelled".to_string()));
+                abandon_start(&state);
+            }
+            Ok(()) => launch_command(state, ui_tx, backend_kind, spec),
+        }
+    });
+}
+
+/// Drops what a start that never launched its command had resolved, so its
+/// secrets do not stay in memory until the next run, and closes its history
+/// record.
+fn abandon_start(state: &Rc<RefCell<AppState>>) {
+    let mut state = state.borrow_mut();
+    state.run_secrets.clear();
+    record_run_not_started(&mut state);
+}
 
-    let coffee =
-        gtk::LinkButton::with_label("https://buymeacoffee.com/allenguarnes", "Buy Me a Coffee");
-    coffee.set_halign(gtk::Align::Start);
+/// Starts every required profile through its instance (launching one if needed)
+/// and waits until each reports that its command is running.
+fn start_dependencies(
+    profile: &str,
+    requires: &[String],
+    ui_tx: &Sender<UiEvent>,
+) -> Result<(), String> {
+    if requires.is_empty() {
+        return Ok(());
+    }
+    check_dependency_cycle(profile)?;
+
+    for dependency in requires {
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+            "starting required profile {dependency}"
+        )));
+        let mut reply = send_control_action(dependency, ControlAction::Start)
+            .map_err(|err| format!("required profile {dependency}: {err}"))?;
+        let deadline = Instant::now() + DEPENDENCY_START_TIMEOUT;
+        while reply != "running" {
+            if Instant::now() >= deadline {
+                return Err(format!(
+                    "required profile {dependency} did not start within {}s",
+                    DEPENDENCY_START_TIMEOUT.as_secs()
+                ));
+            }
+            thread::sleep(Duration::from_millis(250));
+            // A freshly launched instance has no socket yet and reports "stopped".
+            reply = send_control_action(dependency, ControlAction::Status)
+                .map_err(|err| format!("required profile {dependency}: {err}"))?;
+        }
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+            "required profile {dependency} is running"
+        )));
+    }
+    Ok(())
+}
 
-    let description = gtk::Label::new(Some(
-        "Run terminal commands from a tray icon with profile-based settings and logs.",
-    ));
-    description.set_halign(gtk::Align::Start);
-    description.set_xalign(0.0);
-    description.set_line_wrap(true);
+fn profile_requires(profile: &str) -> Result<Vec<String>, String> {
+    let config_path = existing_profile_config(profile)
+        .map_err(|_| format!("required profile {profile} does not exist"))?;
+    let content = fs::read_to_string(&config_path)
+        .map_err(|err| format!("failed to read {}: {err}", config_path.display()))?;
+    let config =
+        parse_config(&content).map_err(|err| format!("{}: {err}", config_path.display()))?;
+    Ok(config.requires)
+}
 
-    let licenses = gtk::Label::new(Some("License: MIT OR Apache-2.0"));
-    licenses.set_halign(gtk::Align::Start);
-    licenses.set_xalign(0.0);
-    licenses.set_line_wrap(true);
+/// Walks the `requires` graph from the profile's file on disk and reports
+/// missing profiles and cycles, which would otherwise only show up as timeouts.
+fn check_dependency_cycle(profile: &str) -> Result<(), String> {
+    fn visit(profile: &str, path: &mut Vec<String>, done: &mut Vec<String>) -> Result<(), String> {
+        if done.iter().any(|known| known == profile) {
+            return Ok(());
+        }
+        if let Some(index) = path.iter().position(|known| known == profile) {
+            let mut cycle = path[index..].to_vec();
+            cycle.push(profile.to_string());
+            return Err(format!("dependency cycle: {}", cycle.join(" -> ")));
+        }
+        path.push(profile.to_string());
+        for dependency in profile_requires(profile)? {
+            validate_profile_name(&dependency)
+                .map_err(|err| format!("invalid requires entry '{dependency}': {err}"))?;
+            visit(&dependency, path, done)?;
+        }
+        path.pop();
+        done.push(profile.to_string());
+        Ok(())
+    }
 
-    let header = gtk::Box::new(gtk::Orientation::Horizontal, 10);
-    header.set_halign(gtk::Align::Start);
+    visit(profile, &mut Vec::new(), &mut Vec::new())
+}
 
-    if let Some(icon) = window_icon {
-        let about_icon = icon
-            .scale_simple(56, 56, InterpType::Bilinear)
-            .unwrap_or_else(|| icon.clone());
-        let icon_image = gtk::Image::from_pixbuf(Some(&about_icon));
-        icon_image.set_halign(gtk::Align::Start);
-        header.pack_start(&icon_image, false, false, 0);
+/// Asks running instances whose profile requires `profile` to stop.
+fn stop_dependent_profiles(profile: &str, ui_tx: &Sender<UiEvent>) {
+    let profiles = match existing_profiles() {
+        Ok(profiles) => profiles,
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+            return;
+        }
+    };
+    for dependent in profiles {
+        if dependent == profile || !instance_is_running(&dependent) {
+            continue;
+        }
+        let requires_profile = profile_requires(&dependent)
+            .map(|requires| requires.iter().any(|required| required == profile))
+            .unwrap_or(false);
+        if !requires_profile {
+            continue;
+        }
+        let line = match send_control_action(&dependent, ControlAction::Stop) {
+            Ok(_) => format!("stopping dependent profile {dependent}"),
+            Err(err) => format!("failed to stop dependent profile {dependent}: {err}"),
+        };
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog(line));
     }
+}
 
-    let title_block = gtk::Box::new(gtk::Orientation::Vertical, 2);
-    title_block.pack_start(&title, false, false, 0);
-    title_block.pack_start(&subtitle, false, false, 0);
-    header.pack_start(&title_block, false, false, 0);
+fn launch_command(
+    state: Rc<RefCell<AppState>>,
+    ui_tx: Sender<UiEvent>,
+    backend_kind: BackendKind,
+    spec: LaunchSpec,
+) {
+    let sudo_password = spec.sudo_password.clone();
+    let sudo_armed = spec.sudo_armed.clone();
+    let child = match command_backend(backend_kind).spawn(spec, &ui_tx) {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+            abandon_start(&state);
+            return;
+        }
+    };
 
-    let links = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    links.pack_start(&github, false, false, 0);
-    links.pack_start(&coffee, false, false, 0);
+    {
+        let mut state = state.borrow_mut();
+        state.run_pid = child.pid();
+        state.child = Some(child);
+        state.sudo_attempts = u32::from(sudo_password.is_some());
+        state.sudo_armed = sudo_armed;
+        if std::mem::take(&mut state.sudo_auth_failed) {
+            let tooltip = format!("{APP_NAME} ({})", state.profile);
+            let _ = state.tray.set_tooltip(Some(tooltip));
+        }
+        log_run_event(&mut state, RunEvent::Started);
+    }
+    if sudo_password.is_some() {
+        state.borrow_mut().sudo_password = sudo_password;
+        schedule_sudo_stdin_close(state.clone());
+    }
+    let _ = ui_tx.send_blocking(UiEvent::SetRunning(true));
+    run_hook_in_background(&state.borrow(), HookKind::PostStart, &ui_tx, Vec::new());
+    begin_ready_check(state, &ui_tx);
+}
 
-    let divider = gtk::Separator::new(gtk::Orientation::Horizontal);
+fn begin_ready_check(state: Rc<RefCell<AppState>>, ui_tx: &Sender<UiEvent>) {
+    let ready_check = {
+        let mut state = state.borrow_mut();
+        state.ready = state.saved_ready_check.is_none();
+        state.saved_ready_check.clone()
+    };
+    let Some(check) = ready_check else {
+        return;
+    };
+    match shell_words::split(&check) {
+        Ok(args) if !args.is_empty() => schedule_ready_check(state, Rc::new(args)),
+        Ok(_) => state.borrow_mut().ready = true,
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "ready_check: parse error: {err}"
+            )));
+        }
+    }
+}
 
-    let container = gtk::Box::new(gtk::Orientation::Vertical, 6);
-    container.set_margin_start(12);
-    container.set_margin_end(12);
-    container.set_margin_top(12);
-    container.set_margin_bottom(12);
-    container.pack_start(&header, false, false, 0);
-    container.pack_start(&description, false, false, 0);
-    container.pack_start(&divider, false, false, 4);
-    container.pack_start(&version, false, false, 0);
-    container.pack_start(&author, false, false, 0);
-    container.pack_start(&links, false, false, 0);
-    container.pack_start(&licenses, false, false, 0);
+/// Runs `ready_check` every second until it succeeds, then reports the
+/// command as running to profiles that require it.
+fn schedule_ready_check(state: Rc<RefCell<AppState>>, args: Rc<Vec<String>>) {
+    let run_id = state.borrow().run_id.clone();
+    glib::timeout_add_local_once(READY_CHECK_INTERVAL, move || {
+        {
+            let state = state.borrow();
+            if state.child.is_none() || state.ready || state.run_id != run_id {
+                return;
+            }
+        }
+        let (done_tx, done_rx) = async_channel::bounded(1);
+        let command = args.as_ref().clone();
+        thread::spawn(move || {
+            let ready = Command::new(&command[0])
+                .args(&command[1..])
+                .env_remove(BG_CHILD_ENV)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+            let _ = done_tx.send_blocking(ready);
+        });
+        MainContext::default().spawn_local(async move {
+            if !done_rx.recv().await.unwrap_or(false) {
+                schedule_ready_check(state, args);
+                return;
+            }
+            let mut state = state.borrow_mut();
+            if state.child.is_some() && state.run_id == run_id {
+                state.ready = true;
+                append_log(&mut state, "command is ready".to_string());
+            }
+        });
+    });
+}
 
-    window.add(&container);
-    window.connect_delete_event(|window, _| {
-        window.hide();
-        Propagation::Stop
+fn write_sudo_password(child: &mut dyn RunningCommand, password: &str) -> io::Result<()> {
+    let mut line = Zeroizing::new(Vec::with_capacity(password.len() + 1));
+    line.extend_from_slice(password.as_bytes());
+    line.push(b'\n');
+    child.write_stdin(&line)
+}
+
+/// Closes the command's stdin once sudo has had time to reject the latest
+/// password. A rejection bumps the generation and cancels the pending close.
+fn schedule_sudo_stdin_close(state: Rc<RefCell<AppState>>) {
+    let generation = {
+        let mut state = state.borrow_mut();
+        state.sudo_stdin_generation += 1;
+        state.sudo_stdin_generation
+    };
+    glib::timeout_add_local_once(SUDO_AUTH_GRACE, move || {
+        let accepted = {
+            let mut state = state.borrow_mut();
+            if state.sudo_stdin_generation != generation {
+                return;
+            }
+            disarm_sudo_prompt(&mut state);
+            let keep_open = state.saved_keep_stdin_open;
+            let accepted = match state.child.as_mut() {
+                Some(child) => {
+                    if !keep_open {
+                        child.close_stdin();
+                    }
+                    state.pending_sudo_secret.take()
+                }
+                None => None,
+            };
+            update_logs_input(&state);
+            accepted
+        };
+        // sudo did not complain, so the password is worth remembering.
+        if let Some(password) = accepted {
+            let profile = state.borrow().profile.clone();
+            let result = SecretService::connect()
+                .and_then(|service| service.store(&profile, SUDO_SECRET_NAME, &password));
+            if let Err(err) = result {
+                append_log(&mut state.borrow_mut(), err);
+            }
+        }
     });
+}
 
-    window.show_all();
-    window.hide();
+fn stored_sudo_password(state: &Rc<RefCell<AppState>>) -> Option<Zeroizing<String>> {
+    let profile = {
+        let state = state.borrow();
+        if !state.saved_secret_service {
+            return None;
+        }
+        state.profile.clone()
+    };
+    match SecretService::connect().and_then(|service| service.lookup(&profile, SUDO_SECRET_NAME)) {
+        Ok(password) => password,
+        Err(err) => {
+            append_log(&mut state.borrow_mut(), err);
+            None
+        }
+    }
+}
 
-    window
+/// Stops treating sudo prompts as password rejections once authentication has
+/// settled.
+fn disarm_sudo_prompt(state: &mut AppState) {
+    state.sudo_attempts = 0;
+    state.sudo_password = None;
+    state.sudo_armed.store(false, Ordering::Relaxed);
 }
 
-fn setup_config_handlers(state: Rc<RefCell<AppState>>) {
-    let view = state.borrow().config_view.clone();
-    let buffer = state.borrow().config_buffer.clone();
-    let window = state.borrow().config_window.clone();
-    let autostart_toggle = state.borrow().config_autostart.clone();
-    let log_to_file_toggle = state.borrow().config_log_to_file.clone();
-    let save_button = state.borrow().config_save_button.clone();
-    let apps_toggle = state.borrow().config_applications.clone();
-    let system_autostart_toggle = state.borrow().config_system_autostart.clone();
+/// Sends the password once sudo first asks for it. Stdin stays open after it
+/// so a retry can follow if sudo rejects it; the grace timer closes it once
+/// authentication settles.
+fn answer_sudo_prompt(state: &mut AppState) {
+    let Some(password) = state.sudo_password.take() else {
+        return;
+    };
+    let result = match state.child.as_mut() {
+        Some(child) => write_sudo_password(child.as_mut(), &password),
+        None => return,
+    };
+    if let Err(err) = result {
+        append_log(
+            state,
+            format!("failed to send sudo password to process: {err}"),
+        );
+    }
+}
 
-    let state_close = state.clone();
-    let buffer_close = buffer.clone();
-    let autostart_toggle_close = autostart_toggle.clone();
-    let log_to_file_toggle_close = log_to_file_toggle.clone();
-    let apps_toggle_close = apps_toggle.clone();
-    let system_autostart_toggle_close = system_autostart_toggle.clone();
-    window.connect_delete_event(move |window, _| {
-        let current_text = buffer_text(&buffer_close);
-        let has_unsaved = {
-            let app = state_close.borrow();
-            config_has_unsaved_changes(
-                &app,
-                &current_text,
-                autostart_toggle_close.is_active(),
-                log_to_file_toggle_close.is_active(),
-                apps_toggle_close.is_active(),
-                system_autostart_toggle_close.is_active(),
-            )
-        };
+/// Asks for the password again after sudo prompted for it a second time.
+fn retry_sudo_password(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    let attempt = {
+        let mut state = state.borrow_mut();
+        if state.child.is_none()
+            || state.sudo_attempts == 0
+            || !state.sudo_armed.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        state.sudo_stdin_generation += 1;
+        state.sudo_attempts += 1;
+        state.sudo_attempts
+    };
+    if attempt > SUDO_PASSWORD_TRIES {
+        return;
+    }
 
-        if !has_unsaved {
-            window.hide();
-            return Propagation::Stop;
+    let message = format!(
+        "Sorry, try again. Enter sudo password (attempt {attempt} of {SUDO_PASSWORD_TRIES}):"
+    );
+    let Some(password) = prompt_sudo_password(&message) else {
+        let _ = ui_tx.send_blocking(UiEvent::AppendLog(
+            "sudo password prompt cancelled".to_string(),
+        ));
+        stop_command(state, ui_tx);
+        return;
+    };
+
+    let (profile, use_store) = {
+        let state = state.borrow();
+        (state.profile.clone(), state.saved_secret_service)
+    };
+    if use_store {
+        // The stored password may be the one that was just rejected.
+        let _ =
+            SecretService::connect().and_then(|service| service.delete(&profile, SUDO_SECRET_NAME));
+        state.borrow_mut().pending_sudo_secret = Some(password.clone());
+    }
+
+    let result = match state.borrow_mut().child.as_mut() {
+        Some(child) => write_sudo_password(child.as_mut(), &password),
+        None => return,
+    };
+    match result {
+        Ok(()) => schedule_sudo_stdin_close(state),
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "failed to send sudo password to process: {err}"
+            )));
         }
+    }
+}
 
-        match show_config_close_dialog(window) {
-            ConfigCloseAction::Save => {
-                save_from_config_widgets(
-                    state_close.clone(),
-                    &buffer_close,
-                    &log_to_file_toggle_close,
-                    &apps_toggle_close,
-                    &system_autostart_toggle_close,
-                );
-                window.hide();
+fn show_sudo_auth_failed(state: &mut AppState) {
+    disarm_sudo_prompt(state);
+    state.sudo_auth_failed = true;
+    append_log(
+        state,
+        "sudo authentication failed: the password was rejected".to_string(),
+    );
+    let line_count = state.log_lines.len();
+    set_logs_status(
+        &state.logs_status_label,
+        line_count,
+        Some("sudo authentication failed"),
+    );
+    let tooltip = format!("{APP_NAME} ({}): sudo authentication failed", state.profile);
+    let _ = state.tray.set_tooltip(Some(tooltip));
+
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>,
+        gtk::DialogFlags::empty(),
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Close,
+        "sudo authentication failed.",
+    );
+    dialog.set_secondary_text(Some(&format!(
+        "The password for profile {} was rejected {SUDO_PASSWORD_TRIES} times and the command did not start.",
+        state.profile
+    )));
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show_all();
+}
+
+fn stop_command(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
+    state.borrow_mut().stop_requested = true;
+    let child = state.borrow_mut().child.take();
+    if let Some(mut child) = child {
+        state.borrow_mut().stopping = true;
+        let pre_stop = prepare_hook(&state.borrow(), HookKind::PreStop);
+        thread::spawn(move || {
+            match pre_stop {
+                Ok(Some(hook)) => {
+                    if let Err(err) = run_hook(&hook, &ui_tx, &[]) {
+                        let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+                }
             }
-            ConfigCloseAction::Discard => {
-                refresh_config_dirty_status(state_close.clone());
-                window.hide();
+            let code = child.terminate(Duration::from_secs(2));
+            let _ = ui_tx.send_blocking(UiEvent::ProcessExited(code));
+        });
+    }
+}
+
+/// Stops the command without waiting for it and runs `after` once it has
+/// exited and its post_exit hook has finished.
+fn stop_command_then(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>, after: AfterStop) {
+    let stopped = {
+        let mut state = state.borrow_mut();
+        if state.child.is_none() && !state.stopping {
+            true
+        } else {
+            // Quitting wins over a restart that is already queued.
+            if state.after_stop != AfterStop::Quit {
+                state.after_stop = after;
             }
-            ConfigCloseAction::Cancel => {}
+            false
         }
+    };
+    if stopped {
+        finish_stop(state, ui_tx, after);
+    } else if state.borrow().child.is_some() {
+        stop_command(state, ui_tx);
+    }
+}
 
-        Propagation::Stop
-    });
+fn finish_stop(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>, after: AfterStop) {
+    match after {
+        AfterStop::Nothing => {}
+        AfterStop::Restart => {
+            append_log(&mut state.borrow_mut(), "command restarting".to_string());
+            start_command(state, ui_tx);
+        }
+        AfterStop::Quit => gtk::main_quit(),
+    }
+}
 
-    let state_save = state.clone();
-    let buffer_save = buffer.clone();
-    let log_to_file_toggle_save = log_to_file_toggle.clone();
-    let apps_toggle_save = apps_toggle.clone();
-    let system_autostart_save = system_autostart_toggle.clone();
-    save_button.connect_clicked(move |_| {
-        save_from_config_widgets(
-            state_save.clone(),
-            &buffer_save,
-            &log_to_file_toggle_save,
-            &apps_toggle_save,
-            &system_autostart_save,
-        );
-    });
+/// Resolves a hook command: expands templates with the values used for the
+/// current run and splits it the same way as the main command.
+fn prepare_hook(state: &AppState, kind: HookKind) -> Result<Option<PreparedHook>, String> {
+    let Some(command) = state.saved_hooks.command(kind) else {
+        return Ok(None);
+    };
+    let args = expand_command_template(
+        command,
+        &state.profile,
+        &state.saved_vars,
+        &state.last_prompt_values,
+        &state.run_secrets,
+    )
+    .map_err(|err| format!("{} hook: {err}", kind.name()))?;
+    if args.is_empty() {
+        return Ok(None);
+    }
 
-    let state_changed = state.clone();
-    let buffer_changed = buffer.clone();
-    buffer.connect_changed(move |_| {
-        let text = buffer_text(&buffer_changed);
-        let mut state = state_changed.borrow_mut();
-        if state.config_ignore || text == state.config_last {
-            return;
+    let mut env = state.saved_env.clone();
+    env.insert("GIVETRAY_PROFILE".to_string(), state.profile.clone());
+    env.insert("GIVETRAY_HOOK".to_string(), kind.name().to_string());
+    let timeout = Duration::from_secs(
+        state
+            .saved_hooks
+            .timeout_secs
+            .unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS),
+    );
+    Ok(Some(PreparedHook {
+        kind,
+        args,
+        env,
+        timeout,
+        log_encoding: state.saved_log_encoding.clone(),
+    }))
+}
+
+fn run_hook_in_background(
+    state: &AppState,
+    kind: HookKind,
+    ui_tx: &Sender<UiEvent>,
+    extra_env: Vec<(&'static str, String)>,
+) {
+    run_hook_then(state, kind, ui_tx, extra_env, || {});
+}
+
+/// Runs a hook on a worker thread and calls `then` on the GTK thread once it
+/// has finished. Without a hook `then` still runs from an idle callback, so
+/// callers may hold the state borrow.
+fn run_hook_then(
+    state: &AppState,
+    kind: HookKind,
+    ui_tx: &Sender<UiEvent>,
+    extra_env: Vec<(&'static str, String)>,
+    then: impl FnOnce() + 'static,
+) {
+    let hook = match prepare_hook(state, kind) {
+        Ok(hook) => hook,
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
+            None
         }
-        let last = state.config_last.clone();
-        state.config_undo.push(last);
-        if state.config_undo.len() > MAX_UNDO {
-            state.config_undo.remove(0);
+    };
+    let Some(hook) = hook else {
+        glib::idle_add_local_once(then);
+        return;
+    };
+    let (done_tx, done_rx) = async_channel::bounded::<()>(1);
+    let ui_tx = ui_tx.clone();
+    thread::spawn(move || {
+        if let Err(err) = run_hook(&hook, &ui_tx, &extra_env) {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(err));
         }
-        state.config_last = text;
-        state.config_redo.clear();
-        drop(state);
-        refresh_config_dirty_status(state_changed.clone());
+        let _ = done_tx.send_blocking(());
     });
-
-    let state_autostart_toggled = state.clone();
-    autostart_toggle.connect_toggled(move |_| {
-        refresh_config_dirty_status(state_autostart_toggled.clone());
+    MainContext::default().spawn_local(async move {
+        let _ = done_rx.recv().await;
+        then();
     });
+}
 
-    let state_logfile_toggled = state.clone();
-    log_to_file_toggle.connect_toggled(move |_| {
-        refresh_config_dirty_status(state_logfile_toggled.clone());
-    });
+/// Runs a hook to completion, streaming its output to the Logs window with a
+/// `[hook]` prefix. The hook is killed once its timeout passes.
+fn run_hook(
+    hook: &PreparedHook,
+    ui_tx: &Sender<UiEvent>,
+    extra_env: &[(&str, String)],
+) -> Result<(), String> {
+    let name = hook.kind.name();
+    let mut cmd = Command::new(&hook.args[0]);
+    cmd.args(&hook.args[1..]);
+    cmd.env_remove(BG_CHILD_ENV);
+    cmd.envs(&hook.env);
+    for (key, value) in extra_env {
+        cmd.env(key, value);
+    }
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
 
-    let state_apps_toggled = state.clone();
-    apps_toggle.connect_toggled(move |_| {
-        refresh_config_dirty_status(state_apps_toggled.clone());
-    });
+    let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!("running {name} hook")));
+    let mut child = cmd
+        .spawn()
+        .map_err(|err| format!("{name} hook failed to start: {err}"))?;
+    let prefix = format!("[{name}] ");
+    if let Some(stdout) = child.stdout.take() {
+        spawn_reader(
+            stdout,
+            ui_tx.clone(),
+            LogStream::HookStdout,
+            prefix.clone(),
+            hook.log_encoding.clone(),
+        );
+    }
+    if let Some(stderr) = child.stderr.take() {
+        spawn_reader(
+            stderr,
+            ui_tx.clone(),
+            LogStream::HookStderr,
+            prefix,
+            hook.log_encoding.clone(),
+        );
+    }
 
-    let state_system_toggled = state.clone();
-    system_autostart_toggle.connect_toggled(move |_| {
-        refresh_config_dirty_status(state_system_toggled.clone());
-    });
+    let deadline = Instant::now() + hook.timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => {
+                return Err(match status.code() {
+                    Some(code) => format!("{name} hook exited with code {code}"),
+                    None => format!("{name} hook was terminated by a signal"),
+                });
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "{name} hook timed out after {}s",
+                    hook.timeout.as_secs()
+                ));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(100)),
+            Err(err) => return Err(format!("{name} hook: {err}")),
+        }
+    }
+}
 
-    let state_keys = state.clone();
-    let buffer_keys = buffer.clone();
-    view.connect_key_press_event(move |_, event| {
-        let key = event.keyval();
-        let modifiers = event.state();
-        let ctrl = modifiers.contains(gdk::ModifierType::CONTROL_MASK);
-        let shift = modifiers.contains(gdk::ModifierType::SHIFT_MASK);
+fn command_backend(kind: BackendKind) -> Box<dyn CommandBackend> {
+    match kind {
+        BackendKind::Direct => Box::new(DirectBackend),
+        BackendKind::Systemd => Box::new(SystemdBackend),
+    }
+}
 
-        if ctrl && shift && (key == gdk::keys::constants::Z || key == gdk::keys::constants::z) {
-            if let Some(next) = {
-                let mut state = state_keys.borrow_mut();
-                let next = state.config_redo.pop();
-                if let Some(ref value) = next {
-                    let last = state.config_last.clone();
-                    state.config_undo.push(last);
-                    state.config_last = value.clone();
-                    state.config_ignore = true;
-                }
-                next
-            } {
-                buffer_keys.set_text(&next);
-                state_keys.borrow_mut().config_ignore = false;
-                refresh_config_dirty_status(state_keys.clone());
-                return Propagation::Stop;
-            }
-            return Propagation::Proceed;
+impl CommandBackend for DirectBackend {
+    fn spawn(
+        &self,
+        spec: LaunchSpec,
+        ui_tx: &Sender<UiEvent>,
+    ) -> Result<Box<dyn RunningCommand>, String> {
+        let args = spec.args;
+        let mut cmd = Command::new(&args[0]);
+        cmd.env_remove(BG_CHILD_ENV);
+        if spec.pty {
+            cmd.env("TERM", PTY_TERM);
+        }
+        cmd.envs(&spec.env);
+        if args.len() > 1 {
+            cmd.args(&args[1..]);
         }
 
-        if ctrl && !shift && (key == gdk::keys::constants::z || key == gdk::keys::constants::Z) {
-            if let Some(prev) = {
-                let mut state = state_keys.borrow_mut();
-                let prev = state.config_undo.pop();
-                if let Some(ref value) = prev {
-                    let last = state.config_last.clone();
-                    state.config_redo.push(last);
-                    state.config_last = value.clone();
-                    state.config_ignore = true;
-                }
-                prev
-            } {
-                buffer_keys.set_text(&prev);
-                state_keys.borrow_mut().config_ignore = false;
-                refresh_config_dirty_status(state_keys.clone());
-                return Propagation::Stop;
+        let master = if spec.pty {
+            let (master, slave) =
+                open_pty().map_err(|err| format!("failed to open pseudo-terminal: {err}"))?;
+            let stdio = || {
+                slave
+                    .try_clone()
+                    .map(Stdio::from)
+                    .map_err(|err| format!("failed to open pseudo-terminal: {err}"))
+            };
+            cmd.stdin(stdio()?);
+            cmd.stdout(stdio()?);
+            cmd.stderr(stdio()?);
+            Some(master)
+        } else {
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
+            if spec.sudo_password.is_some() || spec.keep_stdin_open {
+                cmd.stdin(Stdio::piped());
+            }
+            None
+        };
+
+        #[cfg(unix)]
+        {
+            let limits = spec.limits;
+            let controlling_terminal = spec.pty;
+            unsafe {
+                cmd.pre_exec(move || {
+                    if controlling_terminal {
+                        attach_controlling_terminal()?;
+                    }
+                    apply_process_limits(&limits)
+                });
             }
-            return Propagation::Proceed;
         }
 
-        if ctrl && (key == gdk::keys::constants::y || key == gdk::keys::constants::Y) {
-            if let Some(next) = {
-                let mut state = state_keys.borrow_mut();
-                let next = state.config_redo.pop();
-                if let Some(ref value) = next {
-                    let last = state.config_last.clone();
-                    state.config_undo.push(last);
-                    state.config_last = value.clone();
-                    state.config_ignore = true;
+        let mut child = cmd
+            .spawn()
+            .map_err(|err| format!("failed to start command: {err}"))?;
+        // The command holds the only slave descriptors from here on, so the
+        // master sees EOF as soon as it exits.
+        drop(cmd);
+
+        let running: Box<dyn RunningCommand> = match master {
+            Some(master) => {
+                let reader = master
+                    .try_clone()
+                    .map(PtyReader)
+                    .map_err(|err| format!("failed to read pseudo-terminal: {err}"))?;
+                if spec.sudo_password.is_some() {
+                    spawn_sudo_stderr_reader(
+                        reader,
+                        ui_tx.clone(),
+                        LogStream::Stdout,
+                        spec.log_encoding.clone(),
+                        spec.sudo_armed.clone(),
+                    );
+                } else {
+                    spawn_reader(
+                        reader,
+                        ui_tx.clone(),
+                        LogStream::Stdout,
+                        String::new(),
+                        spec.log_encoding.clone(),
+                    );
                 }
-                next
-            } {
-                buffer_keys.set_text(&next);
-                state_keys.borrow_mut().config_ignore = false;
-                refresh_config_dirty_status(state_keys.clone());
-                return Propagation::Stop;
+                Box::new(PtyChild {
+                    child,
+                    input: StdinWriter::spawn(master, ui_tx.clone()),
+                })
             }
-            return Propagation::Proceed;
-        }
+            None => {
+                if let Some(stdout) = child.stdout.take() {
+                    spawn_reader(
+                        stdout,
+                        ui_tx.clone(),
+                        LogStream::Stdout,
+                        String::new(),
+                        spec.log_encoding.clone(),
+                    );
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    if spec.sudo_password.is_some() {
+                        spawn_sudo_stderr_reader(
+                            stderr,
+                            ui_tx.clone(),
+                            LogStream::Stderr,
+                            spec.log_encoding.clone(),
+                            spec.sudo_armed.clone(),
+                        );
+                    } else {
+                        spawn_reader(
+                            stderr,
+                            ui_tx.clone(),
+                            LogStream::Stderr,
+                            String::new(),
+                            spec.log_encoding.clone(),
+                        );
+                    }
+                }
+                let stdin = child
+                    .stdin
+                    .take()
+                    .map(|stdin| StdinWriter::spawn(stdin, ui_tx.clone()));
+                Box::new(PipedChild { child, stdin })
+            }
+        };
 
-        Propagation::Proceed
-    });
+        Ok(running)
+    }
 }
 
-enum ConfigCloseAction {
-    Save,
-    Discard,
-    Cancel,
+/// A direct child running on a pseudo-terminal whose master end the tray owns.
+///
+/// Input stays available for the whole run: end of input and interrupts are
+/// sent as terminal control characters instead of closing a pipe.
+struct PtyChild {
+    child: Child,
+    input: StdinWriter,
 }
 
-fn show_config_close_dialog(parent: &gtk::Window) -> ConfigCloseAction {
-    let dialog = gtk::MessageDialog::new(
-        Some(parent),
-        gtk::DialogFlags::MODAL,
-        gtk::MessageType::Question,
-        gtk::ButtonsType::None,
-        "You have unsaved configuration changes.",
-    );
-    dialog.set_secondary_text(Some("Save changes before closing this window?"));
-    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
-    dialog.add_button("Discard", gtk::ResponseType::No);
-    dialog.add_button("Save", gtk::ResponseType::Yes);
-    dialog.set_default_response(gtk::ResponseType::Yes);
+impl RunningCommand for PtyChild {
+    fn try_wait(&mut self) -> io::Result<Option<Option<i32>>> {
+        RunningCommand::try_wait(&mut self.child)
+    }
 
-    let response = dialog.run();
-    dialog.close();
+    fn terminate(&mut self, timeout: Duration) -> Option<i32> {
+        RunningCommand::terminate(&mut self.child, timeout)
+    }
 
-    match response {
-        gtk::ResponseType::Yes => ConfigCloseAction::Save,
-        gtk::ResponseType::No => ConfigCloseAction::Discard,
-        _ => ConfigCloseAction::Cancel,
+    fn write_stdin(&mut self, data: &[u8]) -> io::Result<()> {
+        self.input.write(data)
+    }
+
+    fn has_stdin(&self) -> bool {
+        self.input.is_open()
+    }
+
+    fn is_terminal(&self) -> bool {
+        true
+    }
+
+    fn pid(&self) -> Option<u32> {
+        Some(self.child.id())
+    }
+
+    fn signal(&mut self, signal: libc::c_int) -> io::Result<()> {
+        self.child.signal(signal)
     }
 }
 
-fn save_from_config_widgets(
-    state: Rc<RefCell<AppState>>,
-    buffer: &gtk::TextBuffer,
-    log_to_file_toggle: &gtk::CheckButton,
-    apps_toggle: &gtk::CheckButton,
-    system_autostart_toggle: &gtk::CheckButton,
-) {
-    let text = buffer_text(buffer);
-    let saved = save_configuration(state.clone(), text, log_to_file_toggle.is_active());
-    if saved {
-        apply_desktop_actions(
-            state.clone(),
-            apps_toggle.is_active(),
-            system_autostart_toggle.is_active(),
-        );
-        refresh_desktop_toggles(state.clone(), apps_toggle, system_autostart_toggle);
+/// A direct child whose stdin, if any, is a pipe fed by a [`StdinWriter`].
+struct PipedChild {
+    child: Child,
+    stdin: Option<StdinWriter>,
+}
+
+impl RunningCommand for PipedChild {
+    fn try_wait(&mut self) -> io::Result<Option<Option<i32>>> {
+        RunningCommand::try_wait(&mut self.child)
+    }
+
+    fn terminate(&mut self, timeout: Duration) -> Option<i32> {
+        RunningCommand::terminate(&mut self.child, timeout)
+    }
+
+    fn write_stdin(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.write(data),
+            None => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "stdin is already closed",
+            )),
+        }
+    }
+
+    fn close_stdin(&mut self) {
+        // Queued input is still written before the pipe is closed.
+        self.stdin = None;
+    }
+
+    fn has_stdin(&self) -> bool {
+        self.stdin.as_ref().is_some_and(StdinWriter::is_open)
+    }
+
+    fn pid(&self) -> Option<u32> {
+        Some(self.child.id())
+    }
+
+    fn signal(&mut self, signal: libc::c_int) -> io::Result<()> {
+        self.child.signal(signal)
     }
-    refresh_config_dirty_status(state);
 }
 
-fn config_has_unsaved_changes(
-    state: &AppState,
-    current_command: &str,
-    current_autostart: bool,
-    current_log_to_file: bool,
-    current_applications: bool,
-    current_system_autostart: bool,
-) -> bool {
-    current_command != state.saved_command
-        || current_autostart != state.saved_autostart
-        || current_log_to_file != state.saved_log_to_file
-        || current_applications != state.config_saved_applications
-        || current_system_autostart != state.config_saved_system_autostart
+/// Writes a command's input from a worker thread so a command that stops
+/// reading cannot block the interface. Dropping it closes the input once
+/// everything queued has been written.
+struct StdinWriter {
+    queue: mpsc::Sender<Zeroizing<Vec<u8>>>,
+    failed: Arc<AtomicBool>,
 }
 
-fn refresh_config_dirty_status(state: Rc<RefCell<AppState>>) {
-    let (status_label, status_text) = {
-        let app = state.borrow();
-        if app.config_ignore {
-            return;
+impl StdinWriter {
+    fn spawn<W: Write + Send + 'static>(mut input: W, ui_tx: Sender<UiEvent>) -> Self {
+        let (queue, pending) = mpsc::channel::<Zeroizing<Vec<u8>>>();
+        let failed = Arc::new(AtomicBool::new(false));
+        let writer_failed = failed.clone();
+        thread::spawn(move || {
+            for data in pending {
+                if let Err(err) = input.write_all(&data).and_then(|_| input.flush()) {
+                    writer_failed.store(true, Ordering::Relaxed);
+                    let _ = ui_tx.send_blocking(UiEvent::StdinFailed(err));
+                    break;
+                }
+            }
+        });
+        Self { queue, failed }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "stdin is already closed");
+        if !self.is_open() {
+            return Err(closed());
         }
+        self.queue
+            .send(Zeroizing::new(data.to_vec()))
+            .map_err(|_| closed())
+    }
 
-        let command = buffer_text(&app.config_buffer);
-        let unsaved = config_has_unsaved_changes(
-            &app,
-            &command,
-            app.config_autostart.is_active(),
-            app.config_log_to_file.is_active(),
-            app.config_applications.is_active(),
-            app.config_system_autostart.is_active(),
-        );
-        (
-            app.config_status_label.clone(),
-            if unsaved { "Unsaved changes" } else { "Saved" },
+    fn is_open(&self) -> bool {
+        !self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// Reads a pseudo-terminal master, treating the EIO Linux reports once the
+/// last slave descriptor is closed as end of file.
+struct PtyReader(fs::File);
+
+impl io::Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(err) if err.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Opens a pseudo-terminal and returns its master and slave ends.
+///
+/// Echo is turned off because the Logs window shows sent input itself and
+/// sudo's password must not be echoed back into the log. Output newlines are
+/// left untranslated so a `\r` in the output is always a real carriage return.
+fn open_pty() -> io::Result<(fs::File, fs::File)> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let size = libc::winsize {
+        ws_row: PTY_ROWS,
+        ws_col: PTY_COLUMNS,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            &size,
         )
     };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (master, slave) = unsafe { (fs::File::from_raw_fd(master), fs::File::from_raw_fd(slave)) };
+    for file in [&master, &slave] {
+        unsafe {
+            libc::fcntl(file.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
 
-    status_label.set_text(status_text);
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(slave.as_raw_fd(), &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    termios.c_lflag &= !libc::ECHO;
+    termios.c_oflag &= !libc::ONLCR;
+    if unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((master, slave))
 }
 
-fn setup_logs_handlers(state: Rc<RefCell<AppState>>) {
-    let clear_button = state.borrow().logs_clear_button.clone();
-    let copy_button = state.borrow().logs_copy_button.clone();
-    let buffer = state.borrow().logs_buffer.clone();
-    let status_label = state.borrow().logs_status_label.clone();
+/// Runs in the forked child: starts a new session and makes the pseudo-terminal
+/// on stdin its controlling terminal so job control and ^C work.
+fn attach_controlling_terminal() -> io::Result<()> {
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
 
-    let state_clear = state.clone();
-    let buffer_clear = buffer.clone();
-    let status_clear = status_label.clone();
-    clear_button.connect_clicked(move |_| {
-        let mut state = state_clear.borrow_mut();
-        state.log_lines.clear();
-        buffer_clear.set_text("");
-        set_logs_status(&status_clear, 0, Some("cleared"));
-    });
+impl RunningCommand for Child {
+    fn try_wait(&mut self) -> io::Result<Option<Option<i32>>> {
+        Child::try_wait(self).map(|status| status.map(|status| status.code()))
+    }
 
-    let buffer_copy = buffer.clone();
-    let state_copy = state.clone();
-    let status_copy = status_label.clone();
-    copy_button.connect_clicked(move |_| {
-        let text = buffer_text(&buffer_copy);
-        let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
-        clipboard.set_text(&text);
-        let line_count = state_copy.borrow().log_lines.len();
-        set_logs_status(&status_copy, line_count, Some("copied"));
-    });
-}
+    fn terminate(&mut self, timeout: Duration) -> Option<i32> {
+        terminate_child(self, timeout);
+        self.wait().ok().and_then(|status| status.code())
+    }
 
-fn set_logs_status(label: &gtk::Label, line_count: usize, detail: Option<&str>) {
-    let text = match detail {
-        Some(detail) => format!("{line_count} lines | {detail}"),
-        None => format!("{line_count} lines"),
-    };
-    label.set_text(&text);
+    fn pid(&self) -> Option<u32> {
+        Some(self.id())
+    }
+
+    fn signal(&mut self, signal: libc::c_int) -> io::Result<()> {
+        if unsafe { libc::kill(self.id() as i32, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
 }
 
-fn setup_log_receiver(state: Rc<RefCell<AppState>>, receiver: Receiver<UiEvent>) {
-    MainContext::default().spawn_local(async move {
-        while let Ok(event) = receiver.recv().await {
-            let mut state = state.borrow_mut();
-            match event {
-                UiEvent::AppendLog(line) => append_log(&mut state, line),
-                UiEvent::ProcessExited(code) => {
-                    state.child = None;
-                    state.start_stop_item.set_text("Start");
-                    let msg = match code {
-                        Some(code) => format!("command exited with code {code}"),
-                        None => "command exited".to_string(),
-                    };
-                    append_log(&mut state, msg);
-                }
-                UiEvent::SetRunning(running) => {
-                    state
-                        .start_stop_item
-                        .set_text(if running { "Stop" } else { "Start" });
-                }
+impl CommandBackend for SystemdBackend {
+    fn spawn(
+        &self,
+        spec: LaunchSpec,
+        ui_tx: &Sender<UiEvent>,
+    ) -> Result<Box<dyn RunningCommand>, String> {
+        if systemd_unit_is_active(&spec.unit_name)
+            .map_err(|err| format!("failed to query systemd unit: {err}"))?
+        {
+            return Err(format!(
+                "systemd unit {} is already running",
+                spec.unit_name
+            ));
+        }
+        let _ = systemctl_user()
+            .args(["reset-failed", &spec.unit_name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        let mut cmd = Command::new("systemd-run");
+        cmd.args(["--user", "--quiet", "--same-dir"])
+            .arg(format!("--unit={}", spec.unit_name))
+            .arg("--property=TimeoutStopSec=2");
+        for property in systemd_limit_properties(&spec.limits) {
+            cmd.arg(format!("--property={property}"));
+        }
+        for name in SYSTEMD_PASSTHROUGH_ENV {
+            if let Ok(value) = env::var(name) {
+                cmd.arg(format!("--setenv={name}={value}"));
             }
         }
-    });
+        for (name, value) in &spec.env {
+            cmd.arg(format!("--setenv={name}={value}"));
+        }
+        cmd.arg("--").args(spec.args.iter());
+        cmd.env_remove(BG_CHILD_ENV);
+        cmd.stdin(Stdio::null());
+
+        let since = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let output = cmd
+            .output()
+            .map_err(|err| format!("failed to run systemd-run: {err}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "systemd-run failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let journal = follow_systemd_journal(
+            &spec.unit_name,
+            Some(since),
+            spec.log_encoding.clone(),
+            ui_tx,
+        );
+        Ok(Box::new(SystemdUnit::new(spec.unit_name, journal)))
+    }
 }
 
-fn setup_menu_polling(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
-    glib::timeout_add_local(Duration::from_millis(150), move || {
-        while let Ok(event) = MenuEvent::receiver().try_recv() {
-            let id = event.id;
-            if id == "start-stop" {
-                let running = state.borrow().child.is_some();
-                if running {
-                    stop_command(state.clone(), ui_tx.clone());
-                } else {
-                    start_command(state.clone(), ui_tx.clone());
+impl SystemdUnit {
+    /// Starts watching the unit. `systemctl` can take a while to answer, so
+    /// it is polled from a worker thread rather than the process watcher.
+    ///
+    /// A failing `systemctl` is reported once, then polled with a growing
+    /// interval until it answers again.
+    fn new(unit: String, journal: Option<Child>) -> Self {
+        let (status_tx, status) = mpsc::channel();
+        let released = Arc::new(AtomicBool::new(false));
+        let watched = unit.clone();
+        let watcher_released = released.clone();
+        thread::spawn(move || {
+            let mut interval = SYSTEMD_POLL_INTERVAL;
+            let mut failing = false;
+            loop {
+                thread::sleep(interval);
+                if watcher_released.load(Ordering::Relaxed) {
+                    break;
                 }
-            } else if id == "logs" {
-                let window = state.borrow().logs_window.clone();
-                window.show_all();
-                window.resize(820, 520);
-            } else if id == "configure" {
-                let (
-                    window,
-                    view,
-                    buffer,
-                    autostart_toggle,
-                    log_to_file_toggle,
-                    command,
-                    autostart,
-                    log_to_file,
-                ) = {
-                    let state = state.borrow();
-                    (
-                        state.config_window.clone(),
-                        state.config_view.clone(),
-                        state.config_buffer.clone(),
-                        state.config_autostart.clone(),
-                        state.config_log_to_file.clone(),
-                        state.saved_command.clone(),
-                        state.saved_autostart,
-                        state.saved_log_to_file,
-                    )
-                };
-                let (apps_toggle, system_autostart_toggle) = {
-                    let state = state.borrow();
-                    (
-                        state.config_applications.clone(),
-                        state.config_system_autostart.clone(),
-                    )
+                let result = match systemd_unit_is_active(&watched) {
+                    Ok(true) => {
+                        interval = SYSTEMD_POLL_INTERVAL;
+                        failing = false;
+                        continue;
+                    }
+                    Ok(false) => Ok(finish_systemd_unit(&watched)),
+                    Err(err) => {
+                        interval = (interval * 2).min(SYSTEMD_POLL_MAX_INTERVAL);
+                        if std::mem::replace(&mut failing, true) {
+                            continue;
+                        }
+                        Err(err)
+                    }
                 };
-                {
-                    let mut state = state.borrow_mut();
-                    state.config_ignore = true;
-                    state.config_last = command.clone();
-                    state.config_undo.clear();
-                    state.config_redo.clear();
-                }
-                buffer.set_text(&command);
-                autostart_toggle.set_active(autostart);
-                log_to_file_toggle.set_active(log_to_file);
-                refresh_desktop_toggles(state.clone(), &apps_toggle, &system_autostart_toggle);
-                refresh_config_dirty_status(state.clone());
-                window.show_all();
-                view.grab_focus();
-            } else if id == "about" {
-                let window = state.borrow().about_window.clone();
-                window.show_all();
-            } else if id == "exit" {
-                stop_command_blocking(state.clone());
-                gtk::main_quit();
+                let finished = result.is_ok();
+                if status_tx.send(result).is_err() || finished {
+                    break;
+                }
             }
+        });
+        Self {
+            unit,
+            journal,
+            status,
+            released,
         }
+    }
 
-        ControlFlow::Continue
-    });
+    fn stop_journal(&mut self) {
+        if let Some(mut journal) = self.journal.take() {
+            let _ = journal.kill();
+            let _ = journal.wait();
+        }
+    }
 }
 
-fn install_log_filters() {
-    glib::log_set_handler(
-        Some("libayatana-appindicator"),
-        LogLevels::LEVEL_WARNING,
-        false,
-        false,
-        |_domain, _level, _message| {},
-    );
+impl Drop for SystemdUnit {
+    fn drop(&mut self) {
+        self.released.store(true, Ordering::Relaxed);
+    }
 }
 
-fn install_css() {
-    let provider = gtk::CssProvider::new();
-    let css = b"
-        textview,
-        textview text {
-            font-family: monospace;
-            font-size: 11pt;
+/// Reads the exit code of a unit that has stopped and clears its failed state
+/// so the next launch can reuse the name.
+fn finish_systemd_unit(unit: &str) -> Option<i32> {
+    let code = systemd_unit_exit_code(unit);
+    let _ = systemctl_user()
+        .args(["reset-failed", unit])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    code
+}
+
+impl RunningCommand for SystemdUnit {
+    fn try_wait(&mut self) -> io::Result<Option<Option<i32>>> {
+        match self.status.try_recv() {
+            Ok(Ok(code)) => {
+                self.stop_journal();
+                Ok(Some(code))
+            }
+            Ok(Err(err)) => Err(err),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(io::Error::other("systemd unit watcher stopped"))
+            }
         }
-    ";
-    provider.load_from_data(css).expect("failed to load CSS");
+    }
 
-    if let Some(screen) = gdk::Screen::default() {
-        gtk::StyleContext::add_provider_for_screen(
-            &screen,
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
+    fn terminate(&mut self, _timeout: Duration) -> Option<i32> {
+        // TimeoutStopSec set at launch makes systemd escalate to SIGKILL itself.
+        let _ = systemctl_user()
+            .args(["stop", &self.unit])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        // The watcher sees the unit go inactive and collects its exit code;
+        // waiting for it keeps the two from racing over reset-failed.
+        let deadline = Instant::now() + SYSTEMD_POLL_INTERVAL * 4;
+        let code = loop {
+            match self
+                .status
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(Ok(code)) => break code,
+                Ok(Err(_)) => continue,
+                Err(_) => break finish_systemd_unit(&self.unit),
+            }
+        };
+        self.stop_journal();
+        code
+    }
+
+    fn signal(&mut self, signal: libc::c_int) -> io::Result<()> {
+        let status = systemctl_user()
+            .args(["kill", "--signal", &signal.to_string(), &self.unit])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "systemctl kill exited with {status}"
+            )))
+        }
     }
 }
 
-fn setup_process_watcher(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
-    glib::timeout_add_local(Duration::from_millis(500), move || {
-        let mut should_emit = None;
-        {
-            let mut state = state.borrow_mut();
-            if let Some(child) = state.child.as_mut() {
-                match child.try_wait() {
-                    Ok(Some(status)) => {
-                        should_emit = Some(status.code());
-                        state.child = None;
-                    }
-                    Ok(None) => {}
-                    Err(err) => {
-                        append_log(&mut state, format!("failed to check command status: {err}"));
-                    }
-                }
+fn attach_systemd_unit(state: Rc<RefCell<AppState>>, ui_tx: &Sender<UiEvent>) {
+    let unit = systemd_unit_name(&state.borrow().profile);
+    match systemd_unit_is_active(&unit) {
+        Ok(true) => {
+            let encoding = state.borrow().saved_log_encoding.clone();
+            let journal = follow_systemd_journal(&unit, None, encoding, ui_tx);
+            {
+                let mut state = state.borrow_mut();
+                state.child = Some(Box::new(SystemdUnit::new(unit.clone(), journal)));
+                state.run_pid = None;
+                state.run_id = Some(new_run_id());
             }
+            let _ = ui_tx.send_blocking(UiEvent::SetRunning(true));
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "attached to running systemd unit {unit}"
+            )));
+            begin_ready_check(state, ui_tx);
         }
-
-        if let Some(code) = should_emit {
-            let _ = ui_tx.send_blocking(UiEvent::ProcessExited(code));
+        Ok(false) => {}
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "failed to query systemd unit: {err}"
+            )));
         }
+    }
+}
 
-        ControlFlow::Continue
-    });
+fn systemd_unit_name(profile: &str) -> String {
+    format!(
+        "{SYSTEMD_UNIT_PREFIX}{}{SYSTEMD_RUN_UNIT_SUFFIX}",
+        sanitize_profile_name(profile)
+    )
 }
 
-fn save_configuration(
-    state: Rc<RefCell<AppState>>,
-    text: String,
-    log_to_file_enabled: bool,
-) -> bool {
-    let mut state = state.borrow_mut();
-    let new_autostart = state.config_autostart.is_active();
-    let mut new_log_file_path = state.saved_log_file_path.clone();
-    if log_to_file_enabled && new_log_file_path.is_none() {
-        new_log_file_path =
-            default_log_file_path(&state.profile).map(|path| path.to_string_lossy().to_string());
+fn systemctl_user() -> Command {
+    let mut cmd = Command::new("systemctl");
+    cmd.arg("--user");
+    cmd
+}
+
+fn systemd_unit_is_active(unit: &str) -> io::Result<bool> {
+    let status = systemctl_user()
+        .args(["is-active", "--quiet", unit])
+        .stdin(Stdio::null())
+        .status()?;
+    Ok(status.success())
+}
+
+fn systemd_unit_exit_code(unit: &str) -> Option<i32> {
+    let output = systemctl_user()
+        .args([
+            "show",
+            "--property=LoadState,ExecMainCode,ExecMainStatus",
+            unit,
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+
+    let mut load_state = "";
+    let mut main_code = "";
+    let mut main_status = "";
+    for line in text.lines() {
+        match line.split_once('=') {
+            Some(("LoadState", value)) => load_state = value,
+            Some(("ExecMainCode", value)) => main_code = value,
+            Some(("ExecMainStatus", value)) => main_status = value,
+            _ => {}
+        }
     }
-    let new_config = Config {
-        command: text.clone(),
-        autostart: new_autostart,
-        icon_path: state.saved_icon_path.clone(),
-        log_to_file: log_to_file_enabled,
-        log_file_path: new_log_file_path.clone(),
+
+    // ExecMainCode 1 is CLD_EXITED; anything else means the status is a signal number.
+    if load_state != "loaded" || main_code != "1" {
+        return None;
+    }
+    main_status.parse().ok()
+}
+
+fn follow_systemd_journal(
+    unit: &str,
+    since_epoch: Option<u64>,
+    log_encoding: Option<String>,
+    ui_tx: &Sender<UiEvent>,
+) -> Option<Child> {
+    let mut cmd = Command::new("journalctl");
+    cmd.args(["--user", "--unit", unit, "--follow", "--output=cat"]);
+    match since_epoch {
+        Some(epoch) => cmd.arg(format!("--since=@{epoch}")),
+        None => cmd.arg("--lines=100"),
     };
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    match cmd.spawn() {
+        Ok(mut child) => {
+            if let Some(stdout) = child.stdout.take() {
+                spawn_reader(
+                    stdout,
+                    ui_tx.clone(),
+                    LogStream::Journal,
+                    String::new(),
+                    log_encoding.clone(),
+                );
+            }
+            if let Some(stderr) = child.stderr.take() {
+                spawn_reader(
+                    stderr,
+                    ui_tx.clone(),
+                    LogStream::Tray,
+                    String::new(),
+                    log_encoding,
+                );
+            }
+            Some(child)
+        }
+        Err(err) => {
+            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
+                "failed to follow journal for {unit}: {err}"
+            )));
+            None
+        }
+    }
+}
 
-    if let Err(err) = save_config(&state.config_path, &new_config) {
-        append_log(&mut state, format!("Failed to save configuration: {err}"));
-        return false;
+fn systemd_limit_properties(limits: &ProcessLimits) -> Vec<String> {
+    let mut properties = Vec::new();
+    if let Some(value) = limits.address_space {
+        properties.push(format!("LimitAS={value}"));
+    }
+    if let Some(value) = limits.open_files {
+        properties.push(format!("LimitNOFILE={value}"));
+    }
+    if let Some(value) = limits.cpu_seconds {
+        properties.push(format!("LimitCPU={value}"));
+    }
+    if let Some(value) = limits.nice {
+        properties.push(format!("Nice={value}"));
+    }
+    if let Some(ioprio) = limits.ioprio {
+        let class = match ioprio >> 13 {
+            1 => "realtime",
+            3 => "idle",
+            _ => "best-effort",
+        };
+        properties.push(format!("IOSchedulingClass={class}"));
+        properties.push(format!("IOSchedulingPriority={}", ioprio & 0x7));
+    }
+    properties
+}
+
+fn terminate_child(child: &mut Child, timeout: Duration) {
+    if let Ok(Some(_)) = child.try_wait() {
+        return;
+    }
+    let pid = child.id();
+    unsafe {
+        libc::kill(pid as i32, libc::SIGTERM);
+    }
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => return,
+            Ok(None) => {}
+            Err(_) => break,
+        }
+        if start.elapsed() > timeout {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
     }
 
-    state.command = text.clone();
-    state.config_last = text.clone();
-    state.saved_command = text;
-    state.saved_autostart = new_autostart;
-    state.saved_log_to_file = log_to_file_enabled;
-    state.saved_log_file_path = new_log_file_path;
-    state.log_file_path = if log_to_file_enabled {
-        state.saved_log_file_path.as_ref().map(PathBuf::from)
-    } else {
-        None
-    };
+    let _ = child.kill();
+}
 
-    let saved_log_file_path = state.saved_log_file_path.clone();
-    let saved_log_to_file = state.saved_log_to_file;
+/// Reads sudo's stderr, reporting a repeated password prompt as a rejection
+/// and stripping the prompt marker from everything else.
+fn spawn_sudo_stderr_reader<R: std::io::Read + Send + 'static>(
+    reader: R,
+    ui_tx: Sender<UiEvent>,
+    stream: LogStream,
+    encoding: Option<String>,
+    armed: Arc<AtomicBool>,
+) {
+    thread::spawn(move || {
+        let watcher = SudoPromptWatcher {
+            inner: reader,
+            ui_tx: ui_tx.clone(),
+            armed: armed.clone(),
+            matched: 0,
+            prompts: 0,
+        };
+        let mut splitter = LogSplitter::new(watcher, encoding);
+        loop {
+            let segment = match splitter.next_segment() {
+                Ok(Some(segment)) => segment,
+                Ok(None) => break,
+                Err(err) => {
+                    let _ =
+                        ui_tx.send_blocking(UiEvent::AppendLog(format!("log read error: {err}")));
+                    break;
+                }
+            };
+            // With -S the prompt is not followed by a newline, so it prefixes
+            // whatever sudo or the command writes next.
+            let line = segment.text();
+            let prompted = line.contains(SUDO_PROMPT_MARKER);
+            if !prompted {
+                // Output without a prompt in front means sudo has handed over
+                // to the command.
+                armed.store(false, Ordering::Relaxed);
+            }
+            let text = line.replace(SUDO_PROMPT_MARKER, "");
+            if text.is_empty() && prompted {
+                continue;
+            }
+            let _ = ui_tx.send_blocking(UiEvent::Output {
+                stream,
+                segment: segment.with_text(text),
+            });
+        }
+    });
+}
 
-    append_log(&mut state, "Configuration updated".to_string());
-    if saved_log_to_file {
-        if let Some(path) = saved_log_file_path {
-            append_log(&mut state, format!("Log file enabled: {path}"));
+/// Watches sudo's stderr for the `-p` prompt marker as the bytes arrive. The
+/// prompt has no newline after it, so waiting for the line would deadlock
+/// against sudo waiting for the password. The password goes out on the first
+/// prompt, so it never reaches the command when sudo has cached credentials.
+struct SudoPromptWatcher<R> {
+    inner: R,
+    ui_tx: Sender<UiEvent>,
+    armed: Arc<AtomicBool>,
+    matched: usize,
+    prompts: u32,
+}
+
+impl<R: std::io::Read> std::io::Read for SudoPromptWatcher<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let marker = SUDO_PROMPT_MARKER.as_bytes();
+        for &byte in &buf[..read] {
+            if byte == marker[self.matched] {
+                self.matched += 1;
+            } else {
+                self.matched = usize::from(byte == marker[0]);
+            }
+            if self.matched == marker.len() {
+                self.matched = 0;
+                self.prompts += 1;
+                if !self.armed.load(Ordering::Relaxed) {
+                    continue;
+                }
+                // Every prompt after the first follows a rejected password.
+                let event = if self.prompts == 1 {
+                    UiEvent::SudoPrompted
+                } else {
+                    UiEvent::SudoPasswordRejected
+                };
+                let _ = self.ui_tx.send_blocking(event);
+            }
         }
-    } else {
-        append_log(&mut state, "Log file output disabled".to_string());
+        Ok(read)
     }
-
-    true
 }
 
-fn refresh_desktop_toggles(
-    state: Rc<RefCell<AppState>>,
-    apps_toggle: &gtk::CheckButton,
-    system_autostart_toggle: &gtk::CheckButton,
+fn spawn_reader<R: std::io::Read + Send + 'static>(
+    reader: R,
+    ui_tx: Sender<UiEvent>,
+    stream: LogStream,
+    prefix: String,
+    encoding: Option<String>,
 ) {
-    let profile = state.borrow().profile.clone();
-    let apps_exists = applications_desktop_path(&profile).is_some_and(|path| path.exists());
-    let autostart_exists = autostart_desktop_path(&profile).is_some_and(|path| path.exists());
+    thread::spawn(move || {
+        let mut splitter = LogSplitter::new(reader, encoding);
+        loop {
+            match splitter.next_segment() {
+                Ok(Some(segment)) => {
+                    let text = format!("{prefix}{}", segment.text());
+                    let _ = ui_tx.send_blocking(UiEvent::Output {
+                        stream,
+                        segment: segment.with_text(text),
+                    });
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    let _ =
+                        ui_tx.send_blocking(UiEvent::AppendLog(format!("log read error: {err}")));
+                    break;
+                }
+            }
+        }
+    });
+}
 
-    {
-        let mut app = state.borrow_mut();
-        app.config_ignore = true;
-        app.config_saved_applications = apps_exists;
-        app.config_saved_system_autostart = autostart_exists;
-    }
+/// How a chunk of command output was terminated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineEnd {
+    Newline,
+    CarriageReturn,
+    Eof,
+}
 
-    apps_toggle.set_active(apps_exists);
-    system_autostart_toggle.set_active(autostart_exists);
+/// A piece of command output ready for the log.
+enum LogSegment {
+    Line(String),
+    /// Text followed by a lone `\r`; the next segment redraws it.
+    Progress(String),
+    /// The last state of a progress sequence, ended by a newline.
+    ProgressEnd(String),
+}
 
-    {
-        let mut app = state.borrow_mut();
-        app.config_ignore = false;
+impl LogSegment {
+    fn text(&self) -> &str {
+        match self {
+            LogSegment::Line(text) | LogSegment::Progress(text) | LogSegment::ProgressEnd(text) => {
+                text
+            }
+        }
     }
 
-    refresh_config_dirty_status(state);
+    fn with_text(self, text: String) -> Self {
+        match self {
+            LogSegment::Line(_) => LogSegment::Line(text),
+            LogSegment::Progress(_) => LogSegment::Progress(text),
+            LogSegment::ProgressEnd(_) => LogSegment::ProgressEnd(text),
+        }
+    }
 }
 
-fn apply_desktop_actions(
-    state: Rc<RefCell<AppState>>,
-    apps_enabled: bool,
-    autostart_enabled: bool,
-) {
-    let exec_path = match env::current_exe() {
-        Ok(path) => path,
-        Err(err) => {
-            append_log(
-                &mut state.borrow_mut(),
-                format!("Failed to resolve executable path: {err}"),
-            );
-            return;
+/// Splits command output on `\n` and on lone `\r`, so progress bars that
+/// redraw a line show up as progress updates rather than one endless line.
+///
+/// Output is read as raw bytes: undecodable input and overlong lines are
+/// logged in a damaged form instead of stopping the reader, which would leave
+/// the command blocked on a full pipe.
+struct LogSplitter<R> {
+    reader: BufReader<R>,
+    buf: Vec<u8>,
+    progress: Option<String>,
+    encoding: Option<String>,
+}
+
+impl<R: std::io::Read> LogSplitter<R> {
+    fn new(reader: R, encoding: Option<String>) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            buf: Vec::new(),
+            progress: None,
+            encoding,
         }
-    };
+    }
 
-    let (profile, icon_path, config_path) = {
-        let app = state.borrow();
-        let config = load_or_create_config(&app.config_path);
-        let icon_path = match resolve_icon_path_for_desktop(&config) {
-            Ok(path) => path,
-            Err(err) => {
-                drop(app);
-                append_log(
-                    &mut state.borrow_mut(),
-                    format!("Failed to prepare icon path: {err}"),
-                );
-                return;
+    fn next_segment(&mut self) -> io::Result<Option<LogSegment>> {
+        loop {
+            self.buf.clear();
+            let (end, dropped) =
+                read_log_segment(&mut self.reader, &mut self.buf, MAX_LOG_LINE_BYTES)?;
+            if end == LineEnd::Eof && self.buf.is_empty() {
+                return Ok(self.progress.take().map(LogSegment::ProgressEnd));
             }
-        };
-        (app.profile.clone(), icon_path, app.config_path.clone())
-    };
-
-    let desktop_name = desktop_file_name(&profile);
-
-    if let Some(path) = applications_desktop_path(&profile) {
-        if apps_enabled {
-            let content = desktop_entry(&exec_path, &icon_path, &profile, false);
-            if let Err(err) = write_desktop_file(&path, &content) {
-                append_log(
-                    &mut state.borrow_mut(),
-                    format!("Failed to add Applications entry: {err}"),
-                );
-            } else {
-                append_log(
-                    &mut state.borrow_mut(),
-                    format!("Applications entry updated: {desktop_name}"),
-                );
+            let mut text = decode_log_bytes(&self.buf, self.encoding.as_deref());
+            if dropped > 0 {
+                text.push_str(&format!(" [{dropped} more bytes truncated]"));
             }
-        } else if path.exists() {
-            match fs::remove_file(&path) {
-                Ok(_) => append_log(
-                    &mut state.borrow_mut(),
-                    format!("Applications entry removed: {desktop_name}"),
-                ),
-                Err(err) => append_log(
-                    &mut state.borrow_mut(),
-                    format!("Failed to remove Applications entry: {err}"),
-                ),
+            if end == LineEnd::CarriageReturn {
+                // A leading `\r` only moves the cursor back to the start.
+                if text.is_empty() {
+                    continue;
+                }
+                self.progress = Some(text.clone());
+                return Ok(Some(LogSegment::Progress(text)));
             }
+            return Ok(Some(match self.progress.take() {
+                // `\r\n` ends the line that was just drawn.
+                Some(last) if text.is_empty() => LogSegment::ProgressEnd(last),
+                Some(_) => LogSegment::ProgressEnd(text),
+                None => LogSegment::Line(text),
+            }));
         }
-    } else {
-        append_log(
-            &mut state.borrow_mut(),
-            "Unable to resolve Applications entry path".to_string(),
-        );
     }
+}
 
-    if let Some(path) = autostart_desktop_path(&profile) {
-        if autostart_enabled {
-            let content = desktop_entry(&exec_path, &icon_path, &profile, true);
-            if let Err(err) = write_desktop_file(&path, &content) {
-                append_log(
-                    &mut state.borrow_mut(),
-                    format!("Failed to add system autostart entry: {err}"),
-                );
-            } else {
-                append_log(
-                    &mut state.borrow_mut(),
-                    format!("System autostart entry updated: {desktop_name}"),
-                );
+/// Reads up to the next `\n` or `\r`, leaving the terminator out of `buf`.
+/// At most `limit` bytes are kept; the number of bytes skipped beyond that is
+/// returned with the line end.
+fn read_log_segment<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<(LineEnd, usize)> {
+    let mut dropped = 0;
+    let mut keep = |buf: &mut Vec<u8>, bytes: &[u8]| {
+        let room = limit.saturating_sub(buf.len()).min(bytes.len());
+        buf.extend_from_slice(&bytes[..room]);
+        dropped += bytes.len() - room;
+    };
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if available.is_empty() {
+            return Ok((LineEnd::Eof, dropped));
+        }
+        match available
+            .iter()
+            .position(|&byte| byte == b'\n' || byte == b'\r')
+        {
+            Some(pos) => {
+                let end = if available[pos] == b'\n' {
+                    LineEnd::Newline
+                } else {
+                    LineEnd::CarriageReturn
+                };
+                keep(buf, &available[..pos]);
+                reader.consume(pos + 1);
+                return Ok((end, dropped));
             }
-        } else if path.exists() {
-            match fs::remove_file(&path) {
-                Ok(_) => append_log(
-                    &mut state.borrow_mut(),
-                    format!("System autostart entry removed: {desktop_name}"),
-                ),
-                Err(err) => append_log(
-                    &mut state.borrow_mut(),
-                    format!("Failed to remove system autostart entry: {err}"),
-                ),
+            None => {
+                let len = available.len();
+                keep(buf, available);
+                reader.consume(len);
             }
         }
-    } else {
-        append_log(
-            &mut state.borrow_mut(),
-            "Unable to resolve system autostart entry path".to_string(),
-        );
-    }
-
-    let config = load_or_create_config(&config_path);
-    if let Err(err) = save_config(&config_path, &config) {
-        append_log(
-            &mut state.borrow_mut(),
-            format!("Failed to sync configuration file: {err}"),
-        );
     }
 }
 
-fn applications_desktop_path(profile: &str) -> Option<PathBuf> {
-    BaseDirs::new().map(|dirs| {
-        dirs.data_local_dir()
-            .join("applications")
-            .join(desktop_file_name(profile))
-    })
+/// Decodes a line of command output. Bytes that are invalid in the encoding
+/// become U+FFFD, as do control characters the Logs window cannot show.
+fn decode_log_bytes(bytes: &[u8], encoding: Option<&str>) -> String {
+    let text = match encoding {
+        Some(encoding) if !is_utf8_encoding(encoding) => convert_to_utf8(bytes, encoding),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    let garbled = |ch: char| ch.is_control() && ch != '\t' && ch != '\x1b';
+    if text.contains(garbled) {
+        text.chars()
+            .map(|ch| if garbled(ch) { '\u{fffd}' } else { ch })
+            .collect()
+    } else {
+        text
+    }
 }
 
-fn autostart_desktop_path(profile: &str) -> Option<PathBuf> {
-    BaseDirs::new().map(|dirs| {
-        dirs.config_dir()
-            .join("autostart")
-            .join(desktop_file_name(profile))
-    })
+fn convert_to_utf8(mut bytes: &[u8], encoding: &str) -> String {
+    let mut text = String::new();
+    while !bytes.is_empty() {
+        match glib::convert(bytes, "UTF-8", encoding) {
+            Ok((converted, _)) => {
+                text.push_str(&String::from_utf8_lossy(&converted));
+                break;
+            }
+            Err(glib::CvtError::IllegalSequence { offset, .. }) => {
+                text.push_str(&convert_to_utf8(&bytes[..offset], encoding));
+                text.push('\u{fffd}');
+                bytes = &bytes[offset + 1..];
+            }
+            // A truncated multi-byte sequence at the end of the line, or an
+            // encoding iconv does not know.
+            Err(_) => {
+                text.push_str(&String::from_utf8_lossy(bytes));
+                break;
+            }
+        }
+    }
+    text
 }
 
-fn config_path_for_profile(profile: &str) -> Option<PathBuf> {
-    ProjectDirs::from("com", APP_NAME, APP_NAME).map(|proj| {
-        proj.config_dir()
-            .join("configs")
-            .join(format!("{}.toml", sanitize_profile_name(profile)))
-    })
+fn is_utf8_encoding(encoding: &str) -> bool {
+    encoding.eq_ignore_ascii_case("utf-8") || encoding.eq_ignore_ascii_case("utf8")
 }
 
-fn default_log_file_path(profile: &str) -> Option<PathBuf> {
-    ProjectDirs::from("com", APP_NAME, APP_NAME).map(|proj| {
-        proj.data_local_dir()
-            .join("logs")
-            .join(format!("{}.log", sanitize_profile_name(profile)))
-    })
+fn validate_log_encoding(encoding: &str) -> Result<(), String> {
+    if is_utf8_encoding(encoding) {
+        return Ok(());
+    }
+    // Output is split on `\n` and `\r` before it is decoded, so those bytes
+    // (and the rest of ASCII) must mean the same in the encoding.
+    let ascii = (1..0x80).collect::<Vec<u8>>();
+    let (decoded, _) = glib::convert(&ascii, "UTF-8", encoding)
+        .map_err(|err| format!("unsupported encoding {encoding}: {err}"))?;
+    if *decoded != *ascii {
+        return Err(format!(
+            "unsupported encoding {encoding}: only ASCII-compatible encodings are supported"
+        ));
+    }
+    Ok(())
 }
 
-fn resolve_log_file_path(profile: &str, config: &Config) -> Option<PathBuf> {
-    if !config.log_to_file {
-        return None;
+/// Whether this process may set a nice value below zero: as root, with
+/// CAP_SYS_NICE, or within RLIMIT_NICE.
+fn may_lower_nice(value: libc::c_int) -> bool {
+    const CAP_SYS_NICE: u32 = 23;
+    if unsafe { libc::geteuid() } == 0 {
+        return true;
     }
-    config
-        .log_file_path
-        .as_ref()
-        .map(PathBuf::from)
-        .or_else(|| default_log_file_path(profile))
+    let capable = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("CapEff:"))
+                .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        })
+        .is_some_and(|caps| caps & (1 << CAP_SYS_NICE) != 0);
+    if capable {
+        return true;
+    }
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NICE, &mut limit) } != 0 {
+        return false;
+    }
+    // RLIMIT_NICE allows nice values down to 20 - rlim_cur.
+    limit.rlim_cur == libc::RLIM_INFINITY || i64::from(value) >= 20 - limit.rlim_cur as i64
 }
 
-fn load_or_create_config(path: &PathBuf) -> Config {
-    let default = Config {
-        command: DEFAULT_COMMAND.to_string(),
-        autostart: false,
-        icon_path: None,
-        log_to_file: false,
-        log_file_path: None,
+fn resolve_process_limits(limits: &ResourceLimits) -> Result<ProcessLimits, String> {
+    let address_space = match limits.address_space_mb {
+        Some(0) => return Err("address_space_mb must be greater than 0".to_string()),
+        Some(mb) => Some(
+            mb.checked_mul(1024 * 1024)
+                .ok_or_else(|| "address_space_mb is too large".to_string())?
+                as libc::rlim_t,
+        ),
+        None => None,
     };
 
-    let content = match fs::read_to_string(path) {
-        Ok(data) => data,
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-            if let Err(save_err) = save_config(path, &default) {
-                eprintln!(
-                    "failed to initialize default config at {}: {save_err}",
-                    path.display()
-                );
-            }
-            return default;
+    let open_files = match limits.open_files {
+        Some(0) => return Err("open_files must be greater than 0".to_string()),
+        other => other.map(|value| value as libc::rlim_t),
+    };
+
+    let cpu_seconds = match limits.cpu_seconds {
+        Some(0) => return Err("cpu_seconds must be greater than 0".to_string()),
+        other => other.map(|value| value as libc::rlim_t),
+    };
+
+    let nice = match limits.nice {
+        Some(value) if !(-20..=19).contains(&value) => {
+            return Err(format!("nice must be between -20 and 19, got {value}"));
         }
-        Err(err) => {
-            eprintln!("failed to read config at {}: {err}", path.display());
-            return default;
+        // setpriority would only fail in the child, after the fork.
+        Some(value) if value < 0 && !may_lower_nice(value) => {
+            return Err(format!("nice {value}: negative nice requires CAP_SYS_NICE"));
+        }
+        other => other,
+    };
+
+    let ioprio = match (limits.ionice_class.as_deref(), limits.ionice_level) {
+        (None, None) => None,
+        (_, Some(level)) if level > 7 => {
+            return Err(format!("ionice_level must be between 0 and 7, got {level}"));
+        }
+        (class, level) => {
+            let class_id = match class.unwrap_or("best-effort") {
+                "realtime" => 1,
+                "best-effort" => 2,
+                "idle" => 3,
+                other => {
+                    return Err(format!(
+                        "unknown ionice_class '{other}': use realtime, best-effort or idle"
+                    ));
+                }
+            };
+            let level = if class_id == 3 { 0 } else { level.unwrap_or(4) };
+            Some((class_id << 13) | libc::c_int::from(level))
+        }
+    };
+
+    Ok(ProcessLimits {
+        address_space,
+        open_files,
+        cpu_seconds,
+        nice,
+        ioprio,
+    })
+}
+
+// Runs in the forked child before exec, so it must stay async-signal-safe.
+fn apply_process_limits(limits: &ProcessLimits) -> io::Result<()> {
+    let rlimits = [
+        (libc::RLIMIT_AS, limits.address_space),
+        (libc::RLIMIT_NOFILE, limits.open_files),
+        (libc::RLIMIT_CPU, limits.cpu_seconds),
+    ];
+    for (resource, value) in rlimits {
+        let Some(value) = value else {
+            continue;
+        };
+        let mut current = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource, &mut current) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let capped = value.min(current.rlim_max);
+        let next = libc::rlimit {
+            rlim_cur: capped,
+            rlim_max: capped,
+        };
+        if unsafe { libc::setrlimit(resource, &next) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if let Some(nice) = limits.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+            return Err(io::Error::last_os_error());
         }
-    };
+    }
 
-    match toml::from_str(&content) {
-        Ok(config) => config,
-        Err(err) => {
-            eprintln!("failed to parse config at {}: {err}", path.display());
-            default
+    if let Some(ioprio) = limits.ioprio {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } == -1 {
+            return Err(io::Error::last_os_error());
         }
     }
+
+    Ok(())
 }
 
-fn save_config(path: &PathBuf, config: &Config) -> Result<(), String> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).map_err(|err| format!("failed to create config dir: {err}"))?;
+#[derive(Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Variable(String),
+}
+
+/// Splits a command template into literal text and `${NAME}` placeholders.
+/// `$${` stands for a literal `${`.
+fn parse_command_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(index) = rest.find('$') {
+        text.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            text.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| "unterminated ${ placeholder".to_string())?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err("empty ${} placeholder".to_string());
+            }
+            if !text.is_empty() {
+                parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+            }
+            parts.push(TemplatePart::Variable(name.to_string()));
+            rest = &after[end + 1..];
+        } else {
+            text.push('$');
+            rest = &rest[1..];
+        }
     }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+    Ok(parts)
+}
 
-    let payload = toml::to_string_pretty(config)
-        .map_err(|err| format!("failed to serialize config: {err}"))?;
-    fs::write(path, payload).map_err(|err| format!("failed to write config: {err}"))?;
-    Ok(())
+/// Names of the `${KIND:NAME}` placeholders (`prompt` or `secret`), in order of first use.
+fn command_template_names(template: &str, kind: &str) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    for part in parse_command_template(template)? {
+        if let TemplatePart::Variable(name) = part {
+            let found = name
+                .strip_prefix(kind)
+                .and_then(|rest| rest.strip_prefix(':'));
+            if let Some(found) = found {
+                if !names.iter().any(|known| known == found) {
+                    names.push(found.to_string());
+                }
+            }
+        }
+    }
+    Ok(names)
 }
 
-fn sanitize_profile_name(profile: &str) -> String {
-    let mut cleaned = profile
-        .chars()
-        .map(|ch| {
-            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
-                ch
+/// Expands placeholders and splits the result into arguments. Built-in and
+/// `[vars]` values are inserted as-is, so one value may hold several
+/// arguments; prompted values and secrets are quoted to stay one argument.
+/// The expansion is allocated once at its final size so no partial copy of a
+/// secret is left behind.
+fn expand_command_template(
+    template: &str,
+    profile: &str,
+    vars: &BTreeMap<String, String>,
+    prompted: &BTreeMap<String, String>,
+    secrets: &BTreeMap<String, Zeroizing<String>>,
+) -> Result<Zeroizing<Vec<String>>, String> {
+    let parts = parse_command_template(template)?;
+    let mut values = Vec::with_capacity(parts.len());
+    for part in &parts {
+        values.push(match part {
+            TemplatePart::Text(text) => (Cow::Borrowed(text.as_str()), false),
+            TemplatePart::Variable(name) => (
+                template_value(name, profile, vars, prompted, secrets)?,
+                name.starts_with("prompt:") || name.starts_with("secret:"),
+            ),
+        });
+    }
+    // Quoting at worst turns every character into `'\''`, plus the quotes.
+    let capacity = values
+        .iter()
+        .map(|(value, quoted)| {
+            if *quoted {
+                value.len() * 4 + 2
             } else {
-                '_'
+                value.len()
             }
         })
-        .collect::<String>();
-
-    if cleaned.is_empty() {
-        cleaned = DEFAULT_PROFILE.to_string();
+        .sum();
+    let mut expanded = Zeroizing::new(String::with_capacity(capacity));
+    let mut quoting = QuoteState::Plain;
+    for (value, quoted) in &values {
+        if *quoted {
+            push_quoted(&mut expanded, value, quoting);
+        } else {
+            expanded.push_str(value);
+            quoting = quoting.after(value);
+        }
     }
-    cleaned
+    shell_words::split(&expanded)
+        .map(Zeroizing::new)
+        .map_err(|err| format!("parse error: {err}"))
 }
 
-fn profile_icon_path(profile: &str) -> Option<PathBuf> {
-    ProjectDirs::from("com", APP_NAME, APP_NAME).map(|proj| {
-        proj.data_local_dir()
-            .join("profiles")
-            .join(sanitize_profile_name(profile))
-            .join(ICON_FILE_NAME)
-    })
+/// Where the expansion is in shell quoting terms, so an inserted value can be
+/// quoted to match its surroundings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuoteState {
+    Plain,
+    PlainEscape,
+    Single,
+    Double,
+    DoubleEscape,
 }
 
-fn bundled_icon_path() -> Option<PathBuf> {
-    ProjectDirs::from("com", APP_NAME, APP_NAME)
-        .map(|proj| proj.data_local_dir().join(BUNDLED_ICON_FILE_NAME))
+impl QuoteState {
+    fn after(self, text: &str) -> Self {
+        text.chars().fold(self, |state, ch| match (state, ch) {
+            (QuoteState::Plain, '\\') => QuoteState::PlainEscape,
+            (QuoteState::Plain, '\'') => QuoteState::Single,
+            (QuoteState::Plain, '"') => QuoteState::Double,
+            (QuoteState::PlainEscape, _) => QuoteState::Plain,
+            (QuoteState::Single, '\'') => QuoteState::Plain,
+            (QuoteState::Double, '\\') => QuoteState::DoubleEscape,
+            (QuoteState::Double, '"') => QuoteState::Plain,
+            (QuoteState::DoubleEscape, _) => QuoteState::Double,
+            (state, _) => state,
+        })
+    }
 }
 
-fn copy_icon_to_profile(source_path: &Path, profile: &str) -> Result<PathBuf, String> {
-    let bytes = fs::read(source_path).map_err(|err| format!("unable to read icon file: {err}"))?;
-    image::load_from_memory(&bytes).map_err(|err| format!("invalid icon image: {err}"))?;
-
-    let target_path = profile_icon_path(profile)
-        .ok_or_else(|| "unable to resolve icon storage path".to_string())?;
-    if let Some(parent) = target_path.parent() {
-        fs::create_dir_all(parent).map_err(|err| format!("unable to create icon dir: {err}"))?;
+/// Appends `value` so that splitting reads it back literally.
+fn push_quoted(out: &mut String, value: &str, quoting: QuoteState) {
+    match quoting {
+        QuoteState::Plain | QuoteState::PlainEscape => {
+            out.push('\'');
+            push_single_quoted(out, value);
+            out.push('\'');
+        }
+        QuoteState::Single => push_single_quoted(out, value),
+        QuoteState::Double | QuoteState::DoubleEscape => {
+            for ch in value.chars() {
+                if matches!(ch, '$' | '`' | '"' | '\\') {
+                    out.push('\\');
+                }
+                out.push(ch);
+            }
+        }
     }
-    fs::write(&target_path, bytes).map_err(|err| format!("unable to store icon copy: {err}"))?;
-    Ok(target_path)
 }
 
-fn ensure_bundled_icon_file() -> Result<PathBuf, std::io::Error> {
-    let icon_path = bundled_icon_path()
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "project dirs"))?;
-    if let Some(parent) = icon_path.parent() {
-        fs::create_dir_all(parent)?;
+fn push_single_quoted(out: &mut String, value: &str) {
+    for ch in value.chars() {
+        if ch == '\'' {
+            out.push_str("'\\''");
+        } else {
+            out.push(ch);
+        }
     }
-    fs::write(&icon_path, include_bytes!("../assets/icon.png"))?;
-    Ok(icon_path)
 }
 
-fn resolve_icon_path_for_desktop(config: &Config) -> Result<PathBuf, std::io::Error> {
-    if let Some(path) = config.icon_path.as_ref() {
-        let icon = PathBuf::from(path);
-        if icon.exists() {
-            return Ok(icon);
-        }
+/// Resolves one `${NAME}` placeholder. Built-in names take precedence over
+/// `[vars]`; prompted values and secrets are borrowed rather than copied.
+fn template_value<'a>(
+    name: &str,
+    profile: &'a str,
+    vars: &'a BTreeMap<String, String>,
+    prompted: &'a BTreeMap<String, String>,
+    secrets: &'a BTreeMap<String, Zeroizing<String>>,
+) -> Result<Cow<'a, str>, String> {
+    if name == "PROFILE" {
+        Ok(Cow::Borrowed(profile))
+    } else if name == "HOME" {
+        env::var("HOME")
+            .ok()
+            .or_else(|| BaseDirs::new().map(|dirs| dirs.home_dir().display().to_string()))
+            .map(Cow::Owned)
+            .ok_or_else(|| "unable to resolve ${HOME}".to_string())
+    } else if name == "date" {
+        Ok(Cow::Owned(local_date()))
+    } else if let Some(var) = name.strip_prefix("env:") {
+        env::var(var)
+            .map(Cow::Owned)
+            .map_err(|_| format!("unset variable ${{{name}}}"))
+    } else if let Some(prompt) = name.strip_prefix("prompt:") {
+        prompted
+            .get(prompt)
+            .map(|value| Cow::Borrowed(value.as_str()))
+            .ok_or_else(|| format!("no value given for ${{{name}}}"))
+    } else if let Some(secret) = name.strip_prefix("secret:") {
+        secrets
+            .get(secret)
+            .map(|value| Cow::Borrowed(value.as_str()))
+            .ok_or_else(|| format!("no value given for ${{{name}}}"))
+    } else {
+        vars.get(name)
+            .map(|value| Cow::Borrowed(value.as_str()))
+            .ok_or_else(|| format!("unknown variable ${{{name}}}"))
     }
-    ensure_bundled_icon_file()
 }
 
-fn load_window_icon_pixbuf(config: &Config) -> Option<Pixbuf> {
-    let icon_path = resolve_icon_path_for_desktop(config).ok()?;
-    Pixbuf::from_file(icon_path).ok()
+fn validate_command_template(
+    template: &str,
+    profile: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let prompted = command_template_names(template, "prompt")?
+        .into_iter()
+        .map(|name| (name, String::new()))
+        .collect();
+    let secrets = command_template_names(template, "secret")?
+        .into_iter()
+        .map(|name| (name, Zeroizing::new(String::new())))
+        .collect();
+    expand_command_template(template, profile, vars, &prompted, &secrets).map(|_| ())
 }
 
-fn load_tray_icon(config: &Config) -> Result<Icon, Box<dyn std::error::Error>> {
-    if let Some(path) = config.icon_path.as_ref() {
-        let icon_path = PathBuf::from(path);
-        if icon_path.exists() {
-            match fs::read(&icon_path)
-                .map_err(|err| err.to_string())
-                .and_then(|bytes| image::load_from_memory(&bytes).map_err(|err| err.to_string()))
-            {
-                Ok(image) => {
-                    let rgba = image.to_rgba8();
-                    let (width, height) = rgba.dimensions();
-                    return Ok(Icon::from_rgba(rgba.into_raw(), width, height)?);
+/// Expands the configured command, asking for `${prompt:NAME}` values and
+/// resolving `${secret:NAME}` values first. Returns `Ok(None)` when a prompt is cancelled.
+fn expand_command_for_start(
+    state: &Rc<RefCell<AppState>>,
+) -> Result<Option<Zeroizing<Vec<String>>>, String> {
+    let (command, profile, vars, last_values, hooks) = {
+        let state = state.borrow();
+        (
+            state.command.clone(),
+            state.profile.clone(),
+            state.saved_vars.clone(),
+            state.last_prompt_values.clone(),
+            state.saved_hooks.clone(),
+        )
+    };
+
+    // Hooks run later in the same start, so their secrets are resolved now too.
+    let mut secret_names = command_template_names(&command, "secret")?;
+    for kind in HookKind::ALL {
+        if let Some(hook) = hooks.command(kind) {
+            for name in command_template_names(hook, "secret")? {
+                if !secret_names.contains(&name) {
+                    secret_names.push(name);
                 }
-                Err(err) => eprintln!(
-                    "failed to load profile icon at {}: {err}. falling back to bundled icon",
-                    icon_path.display()
-                ),
             }
         }
     }
+    let Some(secrets) = resolve_secrets(state, &secret_names) else {
+        return Ok(None);
+    };
+    state.borrow_mut().run_secrets = secrets;
 
-    let bytes = include_bytes!("../assets/icon.png");
-    let image = image::load_from_memory(bytes)?;
-    let rgba = image.to_rgba8();
-    let (width, height) = rgba.dimensions();
-    Ok(Icon::from_rgba(rgba.into_raw(), width, height)?)
-}
+    let prompts = command_template_names(&command, "prompt")?;
+    let prompted = if prompts.is_empty() {
+        BTreeMap::new()
+    } else {
+        let defaults = prompts
+            .iter()
+            .map(|name| {
+                let value = last_values
+                    .get(name)
+                    .or_else(|| vars.get(name))
+                    .cloned()
+                    .unwrap_or_default();
+                (name.clone(), value)
+            })
+            .collect::<Vec<(String, String)>>();
+        let Some(values) = prompt_command_values("Command Parameters", &defaults, false) else {
+            return Ok(None);
+        };
+        state.borrow_mut().last_prompt_values.extend(values.clone());
+        values
+    };
 
-fn desktop_file_name(profile: &str) -> String {
-    format!("{APP_NAME}_{}.desktop", sanitize_profile_name(profile))
+    let state = state.borrow();
+    expand_command_template(&command, &profile, &vars, &prompted, &state.run_secrets).map(Some)
 }
 
-fn desktop_entry(exec_path: &Path, icon_path: &Path, profile: &str, autostart: bool) -> String {
-    let mut entry = String::from("[Desktop Entry]\n");
-    entry.push_str("Type=Application\n");
+/// Looks up `${secret:NAME}` values in the Secret Service when the profile opts
+/// in, asking for the rest (and storing them). Returns `None` when cancelled.
+fn resolve_secrets(
+    state: &Rc<RefCell<AppState>>,
+    names: &[String],
+) -> Option<BTreeMap<String, Zeroizing<String>>> {
+    let mut secrets = BTreeMap::new();
+    if names.is_empty() {
+        return Some(secrets);
+    }
+    let (profile, use_store) = {
+        let state = state.borrow();
+        (state.profile.clone(), state.saved_secret_service)
+    };
 
-    let display_name = format!("{} ({APP_NAME})", sanitize_profile_name(profile));
-    entry.push_str(&format!("Name={display_name}\n"));
+    let service = if use_store {
+        match SecretService::connect() {
+            Ok(service) => Some(service),
+            Err(err) => {
+                append_log(&mut state.borrow_mut(), err);
+                None
+            }
+        }
+    } else {
+        None
+    };
 
-    let exec = format!(
-        "{} --config {}",
-        desktop_escape_arg(&exec_path.to_string_lossy()),
-        desktop_escape_arg(profile)
-    );
-    entry.push_str(&format!("Exec={exec}\n"));
-    entry.push_str(&format!("Icon={}\n", icon_path.to_string_lossy()));
-    entry.push_str("Terminal=false\n");
-    entry.push_str("Categories=Utility;\n");
-    if autostart {
-        entry.push_str("X-GNOME-Autostart-enabled=true\n");
+    let mut missing = Vec::new();
+    for name in names {
+        let stored = service
+            .as_ref()
+            .map(|service| service.lookup(&profile, name))
+            .transpose()
+            .unwrap_or_else(|err| {
+                append_log(&mut state.borrow_mut(), err);
+                None
+            })
+            .flatten();
+        match stored {
+            Some(value) => {
+                secrets.insert(name.clone(), value);
+            }
+            None => missing.push((name.clone(), String::new())),
+        }
     }
-    entry
-}
-
-fn desktop_escape_arg(value: &str) -> String {
-    let percent_escaped = value.replace('%', "%%");
-    let needs_quotes = value
-        .chars()
-        .any(|ch| ch.is_whitespace() || ch == '"' || ch == '\\');
-    if !needs_quotes {
-        return percent_escaped;
+    if missing.is_empty() {
+        return Some(secrets);
     }
 
-    let mut escaped = String::with_capacity(percent_escaped.len() + 2);
-    escaped.push('"');
-    for ch in percent_escaped.chars() {
-        if ch == '"' || ch == '\\' {
-            escaped.push('\\');
+    let values = prompt_command_values("Command Secrets", &missing, true)?;
+    for (name, value) in values {
+        let value = Zeroizing::new(value);
+        if let Some(service) = service.as_ref() {
+            if let Err(err) = service.store(&profile, &name, &value) {
+                append_log(&mut state.borrow_mut(), err);
+            }
         }
-        escaped.push(ch);
+        secrets.insert(name, value);
     }
-    escaped.push('"');
-    escaped
+    Some(secrets)
 }
 
-fn write_desktop_file(path: &PathBuf, contents: &str) -> Result<(), std::io::Error> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+fn local_date() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::localtime_r(&now, &mut tm);
     }
-    fs::write(path, contents)
+    format!(
+        "{:04}-{:02}-{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday
+    )
 }
 
-fn buffer_text(buffer: &gtk::TextBuffer) -> String {
-    let start = buffer.start_iter();
-    let end = buffer.end_iter();
-    buffer
-        .text(&start, &end, true)
-        .unwrap_or_default()
-        .to_string()
+/// Minimal client for the freedesktop Secret Service. It uses the `plain`
+/// session algorithm and keys items by application, profile and secret name.
+struct SecretService {
+    connection: gio::DBusConnection,
+    session: glib::variant::ObjectPath,
 }
 
-fn append_log_to_file(path: &Path, line: &str) -> Result<(), std::io::Error> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+impl SecretService {
+    fn connect() -> Result<Self, String> {
+        let connection = match env::var(SECRET_SERVICE_BUS_ENV) {
+            Ok(address) => gio::DBusConnection::for_address_sync(
+                &address,
+                gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                    | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                None,
+                gio::Cancellable::NONE,
+            ),
+            Err(_) => gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE),
+        }
+        .map_err(|err| format!("secret service: unable to connect to D-Bus: {err}"))?;
+
+        let input = glib::Variant::from_variant(&"".to_variant());
+        let reply = call_secret_service(
+            &connection,
+            SECRET_SERVICE_PATH,
+            SECRET_SERVICE_IFACE,
+            "OpenSession",
+            Some(("plain", input).to_variant()),
+        )?;
+        let session = reply
+            .child_value(1)
+            .get::<glib::variant::ObjectPath>()
+            .ok_or_else(|| "secret service: unexpected OpenSession reply".to_string())?;
+        Ok(Self {
+            connection,
+            session,
+        })
     }
 
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path)?;
-    writeln!(file, "{line}")
-}
-
-fn append_log(state: &mut AppState, line: String) {
-    let mut rebuild = false;
-    if state.log_lines.len() >= MAX_LOG_LINES {
-        state.log_lines.pop_front();
-        rebuild = true;
+    fn call(
+        &self,
+        path: &str,
+        interface: &str,
+        method: &str,
+        parameters: Option<glib::Variant>,
+    ) -> Result<glib::Variant, String> {
+        call_secret_service(&self.connection, path, interface, method, parameters)
     }
-    state.log_lines.push_back(line.clone());
 
-    if rebuild {
-        let payload = state
-            .log_lines
-            .iter()
-            .cloned()
-            .collect::<Vec<String>>()
-            .join("\n");
-        state.logs_buffer.set_text(&payload);
-    } else {
-        let mut end_iter = state.logs_buffer.end_iter();
-        state.logs_buffer.insert(&mut end_iter, &line);
-        state.logs_buffer.insert(&mut end_iter, "\n");
+    fn attributes(profile: &str, name: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("application".to_string(), APP_NAME.to_string()),
+            ("profile".to_string(), profile.to_string()),
+            ("secret".to_string(), name.to_string()),
+        ])
     }
 
-    let mut end_iter = state.logs_buffer.end_iter();
-    state
-        .logs_view
-        .scroll_to_iter(&mut end_iter, 0.0, false, 0.0, 0.0);
-
-    set_logs_status(&state.logs_status_label, state.log_lines.len(), None);
-
-    if let Some(path) = state.log_file_path.as_ref() {
-        if let Err(err) = append_log_to_file(path, &line) {
-            eprintln!("failed to write log file at {}: {err}", path.display());
+    /// Finds the item for a secret, unlocking it first if needed.
+    fn find_item(&self, profile: &str, name: &str) -> Result<Option<String>, String> {
+        let reply = self.call(
+            SECRET_SERVICE_PATH,
+            SECRET_SERVICE_IFACE,
+            "SearchItems",
+            Some((Self::attributes(profile, name),).to_variant()),
+        )?;
+        let (unlocked, locked) = reply
+            .get::<(
+                Vec<glib::variant::ObjectPath>,
+                Vec<glib::variant::ObjectPath>,
+            )>()
+            .ok_or_else(|| "secret service: unexpected SearchItems reply".to_string())?;
+        if let Some(item) = unlocked.first() {
+            return Ok(Some(item.to_string()));
         }
-    }
-}
-
-fn start_command(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
-    if state.borrow().child.is_some() {
-        let _ = ui_tx.send_blocking(UiEvent::AppendLog("command is already running".to_string()));
-        return;
-    }
+        let Some(item) = locked.first() else {
+            return Ok(None);
+        };
 
-    let command = state.borrow().command.clone();
-    let mut args = match shell_words::split(&command) {
-        Ok(parts) if !parts.is_empty() => parts,
-        Ok(_) => {
-            let _ = ui_tx.send_blocking(UiEvent::AppendLog("command is empty".to_string()));
-            return;
-        }
-        Err(err) => {
-            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!("command parse error: {err}")));
-            return;
+        let reply = self.call(
+            SECRET_SERVICE_PATH,
+            SECRET_SERVICE_IFACE,
+            "Unlock",
+            Some((vec![item.clone()],).to_variant()),
+        )?;
+        let (unlocked, prompt) = reply
+            .get::<(Vec<glib::variant::ObjectPath>, glib::variant::ObjectPath)>()
+            .ok_or_else(|| "secret service: unexpected Unlock reply".to_string())?;
+        if !unlocked.is_empty() {
+            return Ok(Some(item.to_string()));
         }
-    };
-
-    let sudo_password = if is_sudo_command(&args) {
-        ensure_sudo_stdin_flag(&mut args);
-        match prompt_sudo_password() {
-            Some(password) => Some(password),
-            None => {
-                let _ = ui_tx.send_blocking(UiEvent::AppendLog(
-                    "sudo password prompt cancelled".to_string(),
-                ));
-                return;
-            }
+        match self.complete_prompt(&prompt)? {
+            Some(_) => Ok(Some(item.to_string())),
+            None => Err("secret service: unlocking was dismissed".to_string()),
         }
-    } else {
-        None
-    };
-
-    let mut cmd = Command::new(&args[0]);
-    cmd.env_remove(BG_CHILD_ENV);
-    if args.len() > 1 {
-        cmd.args(&args[1..]);
     }
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
-    if sudo_password.is_some() {
-        cmd.stdin(Stdio::piped());
+
+    fn lookup(&self, profile: &str, name: &str) -> Result<Option<Zeroizing<String>>, String> {
+        let Some(item) = self.find_item(profile, name)? else {
+            return Ok(None);
+        };
+        let reply = self.call(
+            &item,
+            SECRET_ITEM_IFACE,
+            "GetSecret",
+            Some((self.session.clone(),).to_variant()),
+        )?;
+        let (_, _, value, _) = reply
+            .child_value(0)
+            .get::<(glib::variant::ObjectPath, Vec<u8>, Vec<u8>, String)>()
+            .ok_or_else(|| "secret service: unexpected GetSecret reply".to_string())?;
+        let value = Zeroizing::new(value);
+        let value = std::str::from_utf8(&value)
+            .map_err(|_| format!("secret service: secret {name} is not valid UTF-8"))?;
+        Ok(Some(Zeroizing::new(value.to_string())))
     }
 
-    let mut child = match cmd.spawn() {
-        Ok(child) => child,
-        Err(err) => {
-            let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
-                "failed to start command: {err}"
-            )));
-            return;
+    fn store(&self, profile: &str, name: &str, value: &str) -> Result<(), String> {
+        let reply = self.call(
+            SECRET_SERVICE_PATH,
+            SECRET_SERVICE_IFACE,
+            "ReadAlias",
+            Some(("default",).to_variant()),
+        )?;
+        let collection = reply
+            .child_value(0)
+            .get::<glib::variant::ObjectPath>()
+            .filter(|path| path.as_str() != "/")
+            .ok_or_else(|| "secret service: no default keyring".to_string())?;
+
+        let properties = HashMap::from([
+            (
+                "org.freedesktop.Secret.Item.Label".to_string(),
+                format!("{APP_NAME} {profile}: {name}").to_variant(),
+            ),
+            (
+                "org.freedesktop.Secret.Item.Attributes".to_string(),
+                Self::attributes(profile, name).to_variant(),
+            ),
+        ]);
+        let secret = (
+            self.session.clone(),
+            Vec::<u8>::new(),
+            value.as_bytes(),
+            "text/plain; charset=utf8",
+        );
+        let reply = self.call(
+            &collection,
+            SECRET_COLLECTION_IFACE,
+            "CreateItem",
+            Some((properties, secret, true).to_variant()),
+        )?;
+        let prompt = reply
+            .child_value(1)
+            .get::<glib::variant::ObjectPath>()
+            .ok_or_else(|| "secret service: unexpected CreateItem reply".to_string())?;
+        if prompt.as_str() != "/" && self.complete_prompt(&prompt)?.is_none() {
+            return Err("secret service: storing the secret was dismissed".to_string());
         }
-    };
+        Ok(())
+    }
 
-    if let Some(password) = sudo_password {
-        if let Some(mut stdin) = child.stdin.take() {
-            if let Err(err) = stdin
-                .write_all(password.as_bytes())
-                .and_then(|_| stdin.write_all(b"\n"))
-            {
-                let _ = ui_tx.send_blocking(UiEvent::AppendLog(format!(
-                    "failed to send sudo password to process: {err}"
-                )));
-            }
-        } else {
-            let _ = ui_tx.send_blocking(UiEvent::AppendLog(
-                "unable to access sudo stdin pipe".to_string(),
-            ));
+    /// Deletes a stored secret. Returns false when there was nothing to delete.
+    fn delete(&self, profile: &str, name: &str) -> Result<bool, String> {
+        let Some(item) = self.find_item(profile, name)? else {
+            return Ok(false);
+        };
+        let reply = self.call(&item, SECRET_ITEM_IFACE, "Delete", None)?;
+        let prompt = reply
+            .child_value(0)
+            .get::<glib::variant::ObjectPath>()
+            .ok_or_else(|| "secret service: unexpected Delete reply".to_string())?;
+        if prompt.as_str() != "/" && self.complete_prompt(&prompt)?.is_none() {
+            return Err("secret service: deleting the secret was dismissed".to_string());
         }
+        Ok(true)
     }
 
-    if let Some(stdout) = child.stdout.take() {
-        spawn_reader(stdout, ui_tx.clone());
-    }
-    if let Some(stderr) = child.stderr.take() {
-        spawn_reader(stderr, ui_tx.clone());
+    /// Shows a Secret Service prompt (e.g. a keyring unlock dialog) and waits
+    /// for it. Returns `None` when the user dismissed it.
+    fn complete_prompt(
+        &self,
+        prompt: &glib::variant::ObjectPath,
+    ) -> Result<Option<glib::Variant>, String> {
+        // Wait on a private context so the Completed signal is delivered
+        // without dispatching the UI's own sources from inside this call.
+        let context = MainContext::new();
+        let main_loop = glib::MainLoop::new(Some(&context), false);
+        let outcome = Rc::new(RefCell::new(None));
+        let result = context
+            .with_thread_default(|| {
+                let outcome_signal = outcome.clone();
+                let loop_signal = main_loop.clone();
+                let subscription = self.connection.signal_subscribe(
+                    None,
+                    Some(SECRET_PROMPT_IFACE),
+                    Some("Completed"),
+                    Some(prompt.as_str()),
+                    None,
+                    gio::DBusSignalFlags::NONE,
+                    move |_, _, _, _, _, parameters| {
+                        *outcome_signal.borrow_mut() = parameters.get::<(bool, glib::Variant)>();
+                        loop_signal.quit();
+                    },
+                );
+                let loop_timeout = main_loop.clone();
+                let timeout = glib::timeout_source_new(
+                    SECRET_PROMPT_TIMEOUT,
+                    None,
+                    glib::Priority::DEFAULT,
+                    move || {
+                        loop_timeout.quit();
+                        glib::ControlFlow::Break
+                    },
+                );
+                timeout.attach(Some(&context));
+
+                let result = self.call(
+                    prompt.as_str(),
+                    SECRET_PROMPT_IFACE,
+                    "Prompt",
+                    Some(("",).to_variant()),
+                );
+                if result.is_ok() && outcome.borrow().is_none() {
+                    main_loop.run();
+                }
+                timeout.destroy();
+                self.connection.signal_unsubscribe(subscription);
+                result
+            })
+            .map_err(|err| format!("secret service: {err}"))?;
+        result?;
+
+        let outcome = outcome.borrow_mut().take();
+        match outcome {
+            Some((true, _)) => Ok(None),
+            Some((false, value)) => Ok(Some(value)),
+            None => Err("secret service: prompt timed out".to_string()),
+        }
     }
-
-    state.borrow_mut().child = Some(child);
-    let _ = ui_tx.send_blocking(UiEvent::SetRunning(true));
-    let _ = ui_tx.send_blocking(UiEvent::AppendLog("command started".to_string()));
 }
 
-fn stop_command(state: Rc<RefCell<AppState>>, ui_tx: Sender<UiEvent>) {
-    let child = state.borrow_mut().child.take();
-    if let Some(mut child) = child {
-        thread::spawn(move || {
-            terminate_child(&mut child, Duration::from_secs(2));
-            let code = child.wait().ok().and_then(|status| status.code());
-            let _ = ui_tx.send_blocking(UiEvent::ProcessExited(code));
-        });
+impl Drop for SecretService {
+    fn drop(&mut self) {
+        let _ = self.call(&self.session.clone(), SECRET_SESSION_IFACE, "Close", None);
     }
 }
 
-fn stop_command_blocking(state: Rc<RefCell<AppState>>) {
-    let child = state.borrow_mut().child.take();
-    if let Some(mut child) = child {
-        terminate_child(&mut child, Duration::from_secs(2));
-        let _ = child.wait();
-    }
+fn call_secret_service(
+    connection: &gio::DBusConnection,
+    path: &str,
+    interface: &str,
+    method: &str,
+    parameters: Option<glib::Variant>,
+) -> Result<glib::Variant, String> {
+    connection
+        .call_sync(
+            Some(SECRET_SERVICE_NAME),
+            path,
+            interface,
+            method,
+            parameters.as_ref(),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|err| format!("secret service: {method} failed: {err}"))
 }
 
-fn terminate_child(child: &mut Child, timeout: Duration) {
-    if let Ok(Some(_)) = child.try_wait() {
-        return;
+fn validate_secret_name(raw: &str) -> Result<String, String> {
+    let name = raw.trim();
+    if name.is_empty() {
+        return Err("secret name cannot be empty".to_string());
     }
-    let pid = child.id();
-    unsafe {
-        libc::kill(pid as i32, libc::SIGTERM);
+    if !name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+    {
+        return Err(format!(
+            "invalid secret name '{name}': use letters, numbers, '-' or '_'"
+        ));
     }
+    Ok(name.to_string())
+}
 
-    let start = Instant::now();
-    loop {
-        match child.try_wait() {
-            Ok(Some(_)) => return,
-            Ok(None) => {}
-            Err(_) => break,
+fn run_secret_command(
+    profile: &str,
+    action: SecretAction,
+    name: Option<&str>,
+) -> Result<(), String> {
+    existing_profile_config(profile)?;
+    let name = name.unwrap_or_default();
+    let service = SecretService::connect()?;
+    match action {
+        SecretAction::Set => {
+            let value = read_secret_from_stdin(&format!("Secret {name} for {profile}: "))?;
+            service.store(profile, name, &value)?;
+            println!("Secret stored: {profile}/{name}");
         }
-        if start.elapsed() > timeout {
-            break;
+        SecretAction::Delete => {
+            if service.delete(profile, name)? {
+                println!("Secret deleted: {profile}/{name}");
+            } else {
+                println!("No stored secret: {profile}/{name}");
+            }
         }
-        thread::sleep(Duration::from_millis(50));
     }
-
-    let _ = child.kill();
+    Ok(())
 }
 
-fn spawn_reader<R: std::io::Read + Send + 'static>(reader: R, ui_tx: Sender<UiEvent>) {
-    thread::spawn(move || {
-        let buf = BufReader::new(reader);
-        for line in buf.lines() {
-            match line {
-                Ok(line) => {
-                    let _ = ui_tx.send_blocking(UiEvent::AppendLog(line));
-                }
-                Err(err) => {
-                    let _ =
-                        ui_tx.send_blocking(UiEvent::AppendLog(format!("log read error: {err}")));
-                    break;
-                }
+/// Reads one line from stdin, turning off terminal echo when stdin is a terminal.
+fn read_secret_from_stdin(prompt: &str) -> Result<Zeroizing<String>, String> {
+    let interactive = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    let mut saved: Option<libc::termios> = None;
+    if interactive {
+        eprint!("{prompt}");
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } == 0 {
+            let mut silent = termios;
+            silent.c_lflag &= !libc::ECHO;
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &silent) } == 0 {
+                saved = Some(termios);
             }
         }
-    });
+    }
+
+    let mut line = Zeroizing::new(String::new());
+    let result = io::stdin().read_line(&mut line);
+    if let Some(termios) = saved {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+        }
+        eprintln!();
+    }
+    result.map_err(|err| format!("failed to read secret: {err}"))?;
+
+    let value = line.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        return Err("secret cannot be empty".to_string());
+    }
+    Ok(Zeroizing::new(value.to_string()))
 }
 
 fn is_sudo_command(args: &[String]) -> bool {
@@ -2000,23 +8905,213 @@ fn is_sudo_command(args: &[String]) -> bool {
     })
 }
 
-fn ensure_sudo_stdin_flag(args: &mut Vec<String>) {
+/// Adds `flag` (`-S` or `-A`) after `sudo` unless a password source is already given.
+fn ensure_sudo_flag(args: &mut Vec<String>, flag: &str) {
     if args
         .iter()
-        .any(|arg| arg == "-S" || arg == "--stdin" || arg == "--askpass")
+        .any(|arg| matches!(arg.as_str(), "-S" | "--stdin" | "-A" | "--askpass"))
     {
         return;
     }
 
     if args.len() == 1 {
-        args.push("-S".to_string());
+        args.push(flag.to_string());
         return;
     }
 
-    args.insert(1, "-S".to_string());
+    args.insert(1, flag.to_string());
+}
+
+/// Removes any `-p`/`--prompt` from sudo's options. Returns whether one was found.
+fn strip_sudo_prompt(args: &mut Vec<String>) -> bool {
+    let mut removed = false;
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" || !arg.starts_with('-') {
+            break;
+        }
+        if arg == "-p" || arg == "--prompt" {
+            args.drain(index..(index + 2).min(args.len()));
+            removed = true;
+        } else if arg.starts_with("--prompt=") || (arg.starts_with("-p") && !arg.starts_with("--"))
+        {
+            args.remove(index);
+            removed = true;
+        } else if matches!(
+            arg.as_str(),
+            "-C" | "-D"
+                | "-g"
+                | "-R"
+                | "-r"
+                | "-T"
+                | "-t"
+                | "-U"
+                | "-u"
+                | "--close-from"
+                | "--chdir"
+                | "--group"
+                | "--host"
+                | "--chroot"
+                | "--role"
+                | "--command-timeout"
+                | "--type"
+                | "--other-user"
+                | "--user"
+        ) {
+            index += 2;
+        } else {
+            index += 1;
+        }
+    }
+    removed
+}
+
+/// Rewrites `sudo [-u USER] COMMAND...` as `pkexec [--user USER] COMMAND...`.
+fn sudo_to_pkexec(args: &[String]) -> Result<Vec<String>, String> {
+    let mut converted = vec!["pkexec".to_string()];
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        match arg.as_str() {
+            "--" => {
+                index += 1;
+                break;
+            }
+            "-u" | "--user" => {
+                let user = args
+                    .get(index + 1)
+                    .ok_or_else(|| format!("sudo option {arg} is missing a user"))?;
+                converted.extend(["--user".to_string(), user.clone()]);
+                index += 2;
+            }
+            "-S" | "--stdin" | "-A" | "--askpass" => index += 1,
+            flag if flag.starts_with("--user=") => {
+                converted.extend(["--user".to_string(), flag["--user=".len()..].to_string()]);
+                index += 1;
+            }
+            flag if flag.starts_with("-u") => {
+                converted.extend(["--user".to_string(), flag[2..].to_string()]);
+                index += 1;
+            }
+            flag if flag.starts_with('-') => {
+                return Err(format!(
+                    "sudo option {flag} has no pkexec equivalent; remove it or change elevation"
+                ));
+            }
+            _ => break,
+        }
+    }
+    if index >= args.len() {
+        return Err("missing command after sudo".to_string());
+    }
+    converted.extend(args[index..].iter().cloned());
+    Ok(converted)
+}
+
+/// Writes the script `SUDO_ASKPASS` points at. sudo runs it without
+/// arguments of its own besides the prompt, so it adds the helper flag.
+fn write_askpass_wrapper() -> Result<PathBuf, String> {
+    let executable =
+        env::current_exe().map_err(|err| format!("unable to resolve executable path: {err}"))?;
+    let dir = ProjectDirs::from("com", APP_NAME, APP_NAME)
+        .map(|proj| {
+            proj.runtime_dir()
+                .unwrap_or_else(|| proj.data_local_dir())
+                .to_path_buf()
+        })
+        .ok_or_else(|| "unable to resolve runtime directory".to_string())?;
+    let path = dir.join(ASKPASS_WRAPPER_NAME);
+    let script = format!(
+        "#!/bin/sh\nexec {} {ASKPASS_ARG} \"$@\"\n",
+        shell_words::quote(&executable.to_string_lossy())
+    );
+    if fs::read_to_string(&path).is_ok_and(|existing| existing == script) {
+        return Ok(path);
+    }
+    fs::create_dir_all(&dir).map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+    write_file_atomically(&path, script.as_bytes())
+        .and_then(|_| fs::set_permissions(&path, fs::Permissions::from_mode(0o700)))
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+/// Entry point when sudo runs givetray as its askpass helper: show the password
+/// dialog with sudo's prompt and print the password for sudo to read.
+fn run_askpass_helper() -> ! {
+    if gtk::init().is_err() {
+        eprintln!("{APP_NAME}: unable to initialize GTK for the password prompt");
+        process::exit(1);
+    }
+    let prompt = env::args()
+        .nth(2)
+        .map(|prompt| prompt.trim().to_string())
+        .filter(|prompt| !prompt.is_empty())
+        .unwrap_or_else(|| SUDO_PROMPT.to_string());
+    let Some(password) = prompt_sudo_password(&prompt) else {
+        process::exit(1);
+    };
+    let mut stdout = io::stdout();
+    let written = stdout
+        .write_all(password.as_bytes())
+        .and_then(|_| stdout.write_all(b"\n"))
+        .and_then(|_| stdout.flush());
+    process::exit(if written.is_ok() { 0 } else { 1 });
+}
+
+fn prompt_command_values(
+    title: &str,
+    defaults: &[(String, String)],
+    hidden: bool,
+) -> Option<BTreeMap<String, String>> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(title),
+        None::<&gtk::Window>,
+        gtk::DialogFlags::MODAL,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Start", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+
+    let grid = gtk::Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(8);
+    let mut entries = Vec::new();
+    for (row, (name, value)) in defaults.iter().enumerate() {
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::Start);
+        let entry = gtk::Entry::new();
+        entry.set_text(value);
+        entry.set_visibility(!hidden);
+        entry.set_hexpand(true);
+        entry.set_activates_default(true);
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(&entry, 1, row as i32, 1, 1);
+        entries.push((name.clone(), entry));
+    }
+    content.pack_start(&grid, false, false, 0);
+
+    dialog.show_all();
+    if let Some((_, entry)) = entries.first() {
+        entry.grab_focus();
+    }
+
+    let response = dialog.run();
+    let values = (response == gtk::ResponseType::Accept).then(|| {
+        entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.text().to_string()))
+            .collect()
+    });
+
+    dialog.close();
+    values
 }
 
-fn prompt_sudo_password() -> Option<Zeroizing<String>> {
+fn prompt_sudo_password(message: &str) -> Option<Zeroizing<String>> {
     let dialog = gtk::Dialog::with_buttons(
         Some("Sudo Password"),
         None::<&gtk::Window>,
@@ -2031,7 +9126,7 @@ fn prompt_sudo_password() -> Option<Zeroizing<String>> {
     let content = dialog.content_area();
     content.set_spacing(8);
 
-    let description = gtk::Label::new(Some("Enter sudo password to start this command:"));
+    let description = gtk::Label::new(Some(message));
     description.set_halign(gtk::Align::Start);
     description.set_xalign(0.0);
     content.pack_start(&description, false, false, 0);
@@ -2060,3 +9155,760 @@ fn prompt_sudo_password() -> Option<Zeroizing<String>> {
     dialog.close();
     password
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn expand(template: &str, prompted: &[(&str, &str)]) -> Result<Vec<String>, String> {
+        let vars = BTreeMap::from([("rate".to_string(), "--bit-rate 8M".to_string())]);
+        let prompted = prompted
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        expand_command_template(template, "demo", &vars, &prompted, &BTreeMap::new())
+            .map(|args| args.to_vec())
+    }
+
+    #[test]
+    fn template_vars_may_hold_several_arguments() {
+        assert_eq!(
+            expand("scrcpy ${rate} '${rate}'", &[]).unwrap(),
+            ["scrcpy", "--bit-rate", "8M", "--bit-rate 8M"]
+        );
+    }
+
+    #[test]
+    fn template_prompts_stay_one_argument() {
+        let value = r#"it's "a" $b \c"#;
+        assert_eq!(
+            expand(
+                "run ${prompt:x} \"-n=${prompt:x}\" '${prompt:x}'",
+                &[("x", value)]
+            )
+            .unwrap(),
+            ["run", value, &format!("-n={value}"), value]
+        );
+        assert_eq!(
+            expand("run ${prompt:x}", &[("x", "")]).unwrap(),
+            ["run", ""]
+        );
+    }
+
+    #[test]
+    fn template_rejects_unset_environment_variables() {
+        let err = expand("run ${env:GIVETRAY_TEST_UNSET}", &[]).unwrap_err();
+        assert_eq!(err, "unset variable ${env:GIVETRAY_TEST_UNSET}");
+    }
+
+    fn tar_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for (name, data) in entries {
+            tar_append(&mut archive, name, data, 1_700_000_000);
+        }
+        archive.resize(archive.len() + 2 * TAR_BLOCK_SIZE, 0);
+        archive
+    }
+
+    #[test]
+    fn tar_entries_round_trip() {
+        let block = vec![7u8; TAR_BLOCK_SIZE];
+        let archive = tar_archive(&[
+            (BUNDLE_MANIFEST_NAME, b"format = 1\n"),
+            (BUNDLE_ICON_NAME, &block),
+            ("empty", b""),
+        ]);
+        assert_eq!(archive.len() % TAR_BLOCK_SIZE, 0);
+        assert_eq!(
+            read_tar_entries(&archive).unwrap(),
+            [
+                (BUNDLE_MANIFEST_NAME.to_string(), b"format = 1\n".to_vec()),
+                (BUNDLE_ICON_NAME.to_string(), block),
+                ("empty".to_string(), Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tar_octal_fields_round_trip() {
+        let mut field = [0u8; 12];
+        write_tar_octal(&mut field, 0o1234567);
+        assert_eq!(&field, b"00001234567\0");
+        assert_eq!(parse_tar_octal(&field), Ok(0o1234567));
+        assert_eq!(parse_tar_octal(b" 644 \0"), Ok(0o644));
+        assert_eq!(parse_tar_octal(b"\0\0\0"), Ok(0));
+        assert!(parse_tar_octal(b"9\0").is_err());
+    }
+
+    #[test]
+    fn tar_rejects_truncated_archives() {
+        let archive = tar_archive(&[("name", b"data")]);
+        assert_eq!(
+            read_tar_entries(&archive[..300]).unwrap_err(),
+            "archive is truncated"
+        );
+        assert_eq!(
+            read_tar_entries(&archive[..2 * TAR_BLOCK_SIZE]).unwrap_err(),
+            "archive is truncated"
+        );
+    }
+
+    #[test]
+    fn tar_rejects_sizes_past_the_end() {
+        let mut archive = Vec::new();
+        tar_append(&mut archive, "name", &[1u8; 8 * TAR_BLOCK_SIZE], 0);
+        archive.truncate(2 * TAR_BLOCK_SIZE);
+        archive.resize(archive.len() + 2 * TAR_BLOCK_SIZE, 0);
+        assert_eq!(
+            read_tar_entries(&archive).unwrap_err(),
+            "archive is truncated at name"
+        );
+    }
+
+    #[test]
+    fn tar_rejects_bad_checksums() {
+        let mut archive = tar_archive(&[("name", b"data")]);
+        archive[0] = b'N';
+        assert_eq!(
+            read_tar_entries(&archive).unwrap_err(),
+            "corrupt archive header"
+        );
+    }
+
+    #[test]
+    fn import_rejects_paths_outside_the_bundle() {
+        let archive = tar_archive(&[("../profile.toml", b"command = \"true\"\n")]);
+        assert_eq!(read_tar_entries(&archive).unwrap()[0].0, "../profile.toml");
+        let bundle = env::temp_dir().join(format!("givetray-test-{}.tar", process::id()));
+        fs::write(&bundle, &archive).unwrap();
+        let result = import_profile(&bundle, Some("imported"));
+        fs::remove_file(&bundle).unwrap();
+        assert_eq!(
+            result.unwrap_err(),
+            "unexpected file in bundle: ../profile.toml"
+        );
+    }
+
+    #[test]
+    fn import_warns_about_machine_paths() {
+        let mut config = Config {
+            command: "/opt/tool/run --log=/var/log/tool.log relative".to_string(),
+            icon_path: Some("/usr/share/icons/tool.png".to_string()),
+            ..Config::default()
+        };
+        config.hooks.pre_start = Some("~/bin/prepare".to_string());
+        config.hooks.post_exit = Some("/usr/bin/cleanup".to_string());
+        let warnings = imported_path_warnings(&config, "demo");
+        assert_eq!(warnings.len(), 4, "{warnings:?}");
+        assert!(warnings[0].starts_with("command uses the absolute path /opt/tool/run"));
+        assert!(warnings[1].starts_with("command uses the absolute path --log=/var/log/tool.log"));
+        assert!(warnings[2].starts_with("hooks.post_exit uses the absolute path /usr/bin/cleanup"));
+        assert!(warnings[3].starts_with("icon_path /usr/share/icons/tool.png"));
+    }
+
+    #[test]
+    fn saved_profiles_leave_out_defaults_and_inherited_values() {
+        let config = Config {
+            autostart: true,
+            ..Config::default()
+        };
+        let inherited = toml::Table::from_iter([(
+            "backend".to_string(),
+            toml::Value::String("systemd".to_string()),
+        )]);
+        let effective = config_defaults_with(inherited).unwrap();
+
+        let mut generated = toml::Table::try_from(&config).unwrap();
+        strip_inherited_keys(&mut generated, &effective, None);
+        let mut keys = generated.keys().map(String::as_str).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, ["autostart", "backend", "command", "version"]);
+
+        let existing = toml::Table::from_iter([("restart_on_change".to_string(), false.into())]);
+        let mut generated = toml::Table::try_from(&config).unwrap();
+        strip_inherited_keys(&mut generated, &effective, Some(&existing));
+        assert!(generated.contains_key("restart_on_change"));
+        assert!(!generated.contains_key("log_to_file"));
+    }
+
+    #[test]
+    fn unknown_keys_include_nested_tables() {
+        let config = parse_config(
+            "command = \"true\"\nbogus = 1\n[hooks]\npre_strat = \"x\"\n[log_sinks]\nsyslg = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            unknown_config_keys(&config),
+            ["bogus", "hooks.pre_strat", "log_sinks.syslg"]
+        );
+    }
+
+    #[test]
+    fn control_action_names_match_serde() {
+        #[derive(Serialize, Deserialize)]
+        struct Actions {
+            actions: Vec<ControlAction>,
+        }
+        for (action, name) in ControlAction::NAMES {
+            assert_eq!(ControlAction::parse(name), Some(action));
+            assert_eq!(action.as_str(), name);
+        }
+        let all = Actions {
+            actions: ControlAction::NAMES.map(|(action, _)| action).to_vec(),
+        };
+        let text = toml::to_string(&all).unwrap();
+        assert_eq!(
+            text,
+            "actions = [\"start\", \"stop\", \"restart\", \"logs\", \"status\"]\n"
+        );
+        assert_eq!(
+            toml::from_str::<Actions>(&text).unwrap().actions,
+            all.actions
+        );
+        let err = toml::from_str::<Actions>("actions = [\"Start\"]")
+            .err()
+            .unwrap();
+        assert!(err.message().contains("unknown action 'Start'"), "{err}");
+    }
+
+    #[test]
+    fn desktop_action_groups_are_validated() {
+        let settings = DesktopSettings {
+            actions: Some(ControlAction::NAMES.map(|(action, _)| action).to_vec()),
+            ..DesktopSettings::default()
+        };
+        for autostart in [false, true] {
+            let entry = desktop_entry(
+                Path::new("/opt/my app/tray"),
+                Path::new("/icons/tray.png"),
+                "work 100%",
+                autostart,
+                &settings,
+            );
+            assert_eq!(validate_desktop_entry(&entry), Vec::<String>::new());
+            assert!(entry.contains("\n[Desktop Action restart]\nName=Restart\n"));
+            assert!(!entry.contains("[Desktop Action status]"));
+        }
+
+        let entry = "[Desktop Entry]\nType=Application\nName=Tray\nExec=tray\n\
+                     Actions=start;stop;\n\n\
+                     [Desktop Action start]\nExec=tray ctl \"$start\"\n\n\
+                     [Desktop Action stop]\nName=Stop\n";
+        assert_eq!(
+            validate_desktop_entry(entry),
+            vec![
+                "[Desktop Action start] is missing 'Name'".to_string(),
+                "invalid Exec in [Desktop Action start]: unescaped '$' inside quotes".to_string(),
+                "[Desktop Action stop] is missing 'Exec'".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn sudo_prompt_options_are_removed() {
+        let args = |line: &str| shell_words::split(line).unwrap();
+        let mut command = args("sudo -u root -p 'Password:' --prompt=x -pfoo -S cmd -p arg");
+        assert!(strip_sudo_prompt(&mut command));
+        assert_eq!(command, args("sudo -u root -S cmd -p arg"));
+
+        let mut command = args("sudo -u -p cmd");
+        assert!(!strip_sudo_prompt(&mut command));
+        assert_eq!(command, args("sudo -u -p cmd"));
+    }
+
+    #[test]
+    fn log_encodings_must_be_ascii_compatible() {
+        assert_eq!(validate_log_encoding("utf-8"), Ok(()));
+        assert_eq!(validate_log_encoding("ISO-8859-1"), Ok(()));
+        assert_eq!(validate_log_encoding("CP1252"), Ok(()));
+        for encoding in ["UTF-16", "UTF-16LE", "UTF-32"] {
+            let err = validate_log_encoding(encoding).unwrap_err();
+            assert!(err.starts_with(&format!("unsupported encoding {encoding}")));
+        }
+        assert!(validate_log_encoding("no-such-encoding").is_err());
+    }
+
+    #[test]
+    fn ansi_lines_split_into_styled_runs() {
+        let red = AnsiStyle {
+            foreground: Some(ANSI_PALETTE[1]),
+            bold: false,
+        };
+        let bold_red = AnsiStyle { bold: true, ..red };
+        assert_eq!(
+            parse_ansi_line("a\x1b[31mb\x1b[1mc\x1b[0md\x1b]0;title\x07e\x1b[2Kf"),
+            [
+                ("a".to_string(), AnsiStyle::default()),
+                ("b".to_string(), red),
+                ("c".to_string(), bold_red),
+                ("def".to_string(), AnsiStyle::default()),
+            ]
+        );
+        assert_eq!(parse_ansi_line("\x1b[1m\x1b[m"), []);
+    }
+
+    #[test]
+    fn sgr_extended_colors() {
+        let mut style = AnsiStyle::default();
+        apply_sgr(&mut style, "38;5;196");
+        assert_eq!(style.foreground, Some((255, 0, 0)));
+        apply_sgr(&mut style, "1;38;2;10;300;30");
+        assert_eq!(style.foreground, Some((10, 255, 30)));
+        assert!(style.bold);
+        // A background colour consumes its arguments without touching the foreground.
+        apply_sgr(&mut style, "48;5;21;22");
+        assert_eq!(style.foreground, Some((10, 255, 30)));
+        assert!(!style.bold);
+        apply_sgr(&mut style, "38;5");
+        assert_eq!(style.foreground, None);
+        apply_sgr(&mut style, "92");
+        assert_eq!(style.foreground, Some(ANSI_PALETTE[10]));
+        apply_sgr(&mut style, "39");
+        assert_eq!(style, AnsiStyle::default());
+    }
+
+    #[test]
+    fn ansi_256_palette() {
+        assert_eq!(ansi_256_color(9), ANSI_PALETTE[9]);
+        assert_eq!(ansi_256_color(16), (0, 0, 0));
+        assert_eq!(ansi_256_color(231), (255, 255, 255));
+        assert_eq!(ansi_256_color(67), (95, 135, 175));
+        assert_eq!(ansi_256_color(232), (8, 8, 8));
+        assert_eq!(ansi_256_color(255), (238, 238, 238));
+    }
+
+    fn log_segments(output: &[u8]) -> Vec<String> {
+        let mut splitter = LogSplitter::new(output, None);
+        let mut segments = Vec::new();
+        while let Some(segment) = splitter.next_segment().unwrap() {
+            segments.push(match segment {
+                LogSegment::Line(text) => format!("line {text}"),
+                LogSegment::Progress(text) => format!("progress {text}"),
+                LogSegment::ProgressEnd(text) => format!("end {text}"),
+            });
+        }
+        segments
+    }
+
+    #[test]
+    fn log_splitter_reports_progress() {
+        assert_eq!(
+            log_segments(b"start\n10%\r50%\r100%\r\ndone\r\nlast"),
+            [
+                "line start",
+                "progress 10%",
+                "progress 50%",
+                "progress 100%",
+                "end 100%",
+                // A CRLF line is drawn and then kept, like a one-step progress.
+                "progress done",
+                "end done",
+                "line last",
+            ]
+        );
+        // A redraw that ends with a newline instead of `\r\n` closes the sequence with its own text.
+        assert_eq!(
+            log_segments(b"\r1/2\r2/2\nok\n"),
+            ["progress 1/2", "end 2/2", "line ok"]
+        );
+        // Output that stops mid-progress still closes the sequence.
+        assert_eq!(log_segments(b"50%\r"), ["progress 50%", "end 50%"]);
+    }
+
+    #[test]
+    fn log_segments_are_truncated() {
+        let mut reader = BufReader::with_capacity(4, &b"abcdefghij\rxy\n"[..]);
+        let mut buf = Vec::new();
+        assert_eq!(
+            read_log_segment(&mut reader, &mut buf, 6).unwrap(),
+            (LineEnd::CarriageReturn, 4)
+        );
+        assert_eq!(buf, b"abcdef");
+        buf.clear();
+        assert_eq!(
+            read_log_segment(&mut reader, &mut buf, 6).unwrap(),
+            (LineEnd::Newline, 0)
+        );
+        assert_eq!(buf, b"xy");
+        buf.clear();
+        assert_eq!(
+            read_log_segment(&mut reader, &mut buf, 6).unwrap(),
+            (LineEnd::Eof, 0)
+        );
+
+        let long = vec![b'a'; MAX_LOG_LINE_BYTES + 10];
+        assert_eq!(
+            log_segments(&long),
+            [format!(
+                "line {} [10 more bytes truncated]",
+                "a".repeat(MAX_LOG_LINE_BYTES)
+            )]
+        );
+    }
+
+    #[test]
+    fn journald_records_use_the_native_protocol() {
+        assert_eq!(
+            journald_record("scrcpy", "demo", LogStream::Stderr, "failed"),
+            b"MESSAGE=failed\nPRIORITY=3\nSYSLOG_IDENTIFIER=scrcpy\n\
+              GIVETRAY_PROFILE=demo\nGIVETRAY_STREAM=stderr\n"
+        );
+
+        let record = journald_record("scrcpy", "demo", LogStream::Stdout, "a\nb");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\nPRIORITY=6\n");
+        assert!(record.starts_with(&expected), "{record:?}");
+    }
+
+    #[test]
+    fn syslog_records_follow_rfc5424() {
+        let record = syslog_record("my app\u{e9}", LogStream::Tray, "command started");
+        let fields = record.splitn(8, ' ').collect::<Vec<_>>();
+        assert_eq!(fields[0], "<13>1");
+        assert_eq!(fields[3], "my_app_");
+        assert_eq!(fields[4], process::id().to_string());
+        assert_eq!(fields[5], APP_NAME);
+        assert_eq!(fields[6], "-");
+        assert_eq!(fields[7], "command started");
+
+        let record = syslog_record(&"x".repeat(60), LogStream::Stderr, "oops");
+        assert!(record.starts_with("<11>1 "));
+        assert!(record.contains(&format!(" {} ", "x".repeat(48))));
+    }
+
+    #[test]
+    fn json_strings_escape_control_characters() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("say \"hi\"\\\n\r\t"), r#""say \"hi\"\\\n\r\t""#);
+        assert_eq!(json_string("\x00\x1b[1m\x1f"), r#""\u0000\u001b[1m\u001f""#);
+        assert_eq!(json_string("\u{7f}é\u{2028}"), "\"\u{7f}é\u{2028}\"");
+
+        // TOML basic strings share JSON's escapes, so they make a handy parser.
+        let value = "a\"b\\c\nd\u{1}e\u{fffd}";
+        let table: toml::Table = toml::from_str(&format!("v = {}", json_string(value))).unwrap();
+        assert_eq!(table["v"].as_str(), Some(value));
+    }
+
+    const STAND_IN_SECRET_SERVICE: &str = r#"<node>
+      <interface name="org.freedesktop.Secret.Service">
+        <method name="OpenSession">
+          <arg type="s" direction="in"/><arg type="v" direction="in"/>
+          <arg type="v" direction="out"/><arg type="o" direction="out"/>
+        </method>
+        <method name="SearchItems">
+          <arg type="a{ss}" direction="in"/>
+          <arg type="ao" direction="out"/><arg type="ao" direction="out"/>
+        </method>
+        <method name="Unlock">
+          <arg type="ao" direction="in"/>
+          <arg type="ao" direction="out"/><arg type="o" direction="out"/>
+        </method>
+        <method name="ReadAlias">
+          <arg type="s" direction="in"/><arg type="o" direction="out"/>
+        </method>
+      </interface>
+      <interface name="org.freedesktop.Secret.Collection">
+        <method name="CreateItem">
+          <arg type="a{sv}" direction="in"/><arg type="(oayays)" direction="in"/>
+          <arg type="b" direction="in"/>
+          <arg type="o" direction="out"/><arg type="o" direction="out"/>
+        </method>
+      </interface>
+      <interface name="org.freedesktop.Secret.Item">
+        <method name="GetSecret">
+          <arg type="o" direction="in"/><arg type="(oayays)" direction="out"/>
+        </method>
+        <method name="Delete"><arg type="o" direction="out"/></method>
+      </interface>
+      <interface name="org.freedesktop.Secret.Session">
+        <method name="Close"/>
+      </interface>
+      <interface name="org.freedesktop.Secret.Prompt">
+        <method name="Prompt"><arg type="s" direction="in"/></method>
+        <signal name="Completed"><arg type="b"/><arg type="v"/></signal>
+      </interface>
+    </node>"#;
+
+    const STAND_IN_COLLECTION: &str = "/org/freedesktop/secrets/collection/login";
+    const STAND_IN_SESSION: &str = "/org/freedesktop/secrets/session/1";
+    const STAND_IN_PROMPT: &str = "/org/freedesktop/secrets/prompt/1";
+
+    /// A private message bus, stopped again on drop.
+    struct TestBus {
+        daemon: Child,
+        config: PathBuf,
+        address: String,
+    }
+
+    impl TestBus {
+        fn start() -> Option<Self> {
+            let config = env::temp_dir().join(format!("givetray-test-bus-{}.conf", process::id()));
+            fs::write(
+                &config,
+                "<busconfig><type>session</type><listen>unix:tmpdir=/tmp</listen>\
+                 <policy context=\"default\"><allow send_destination=\"*\"/>\
+                 <allow receive_sender=\"*\"/>\
+                 <allow own=\"*\"/></policy></busconfig>",
+            )
+            .ok()?;
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some(Self {
+                daemon,
+                config,
+                address: address.trim().to_string(),
+            })
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = fs::remove_file(&self.config);
+        }
+    }
+
+    #[derive(Default)]
+    struct StandInKeyring {
+        items: Vec<(u32, HashMap<String, String>, Vec<u8>)>,
+        next_id: u32,
+        reported_locked: bool,
+    }
+
+    fn object_path(path: &str) -> glib::variant::ObjectPath {
+        glib::variant::ObjectPath::try_from(path).unwrap()
+    }
+
+    fn item_path(id: u32) -> glib::variant::ObjectPath {
+        object_path(&format!("{STAND_IN_COLLECTION}/{id}"))
+    }
+
+    fn item_id(path: &str) -> u32 {
+        path.rsplit('/').next().unwrap().parse().unwrap()
+    }
+
+    fn no_paths() -> Vec<glib::variant::ObjectPath> {
+        Vec::new()
+    }
+
+    /// Answers the Secret Service calls `SecretService` makes, keeping items
+    /// in memory. The first search that finds something reports it as locked
+    /// so the unlock prompt is exercised too.
+    fn stand_in_method_call(
+        keyring: &Mutex<StandInKeyring>,
+        connection: &gio::DBusConnection,
+        path: &str,
+        method: &str,
+        parameters: &glib::Variant,
+        invocation: gio::DBusMethodInvocation,
+    ) {
+        let mut keyring = keyring.lock().unwrap();
+        let reply = match method {
+            "OpenSession" => Some(
+                (
+                    glib::Variant::from_variant(&"".to_variant()),
+                    object_path(STAND_IN_SESSION),
+                )
+                    .to_variant(),
+            ),
+            "ReadAlias" => Some((object_path(STAND_IN_COLLECTION),).to_variant()),
+            "SearchItems" => {
+                let (attributes,) = parameters.get::<(HashMap<String, String>,)>().unwrap();
+                let found: Vec<_> = keyring
+                    .items
+                    .iter()
+                    .filter(|item| item.1 == attributes)
+                    .map(|item| item_path(item.0))
+                    .collect();
+                if found.is_empty() || keyring.reported_locked {
+                    Some((found, no_paths()).to_variant())
+                } else {
+                    keyring.reported_locked = true;
+                    Some((no_paths(), found).to_variant())
+                }
+            }
+            "Unlock" => Some((no_paths(), object_path(STAND_IN_PROMPT)).to_variant()),
+            "Prompt" => {
+                invocation.return_value(None);
+                let result = glib::Variant::from_variant(&no_paths().to_variant());
+                connection
+                    .emit_signal(
+                        None,
+                        path,
+                        SECRET_PROMPT_IFACE,
+                        "Completed",
+                        Some(&(false, result).to_variant()),
+                    )
+                    .unwrap();
+                return;
+            }
+            "CreateItem" => {
+                let properties = parameters
+                    .child_value(0)
+                    .get::<HashMap<String, glib::Variant>>()
+                    .unwrap();
+                let attributes = properties["org.freedesktop.Secret.Item.Attributes"]
+                    .get::<HashMap<String, String>>()
+                    .unwrap();
+                let (_, _, value, _) = parameters
+                    .child_value(1)
+                    .get::<(glib::variant::ObjectPath, Vec<u8>, Vec<u8>, String)>()
+                    .unwrap();
+                let replace = parameters.child_value(2).get::<bool>().unwrap();
+                let existing = keyring
+                    .items
+                    .iter_mut()
+                    .find(|item| replace && item.1 == attributes);
+                let id = match existing {
+                    Some(item) => {
+                        item.2 = value;
+                        item.0
+                    }
+                    None => {
+                        keyring.next_id += 1;
+                        let id = keyring.next_id;
+                        keyring.items.push((id, attributes, value));
+                        id
+                    }
+                };
+                Some((item_path(id), object_path("/")).to_variant())
+            }
+            "GetSecret" => {
+                let id = item_id(path);
+                let item = keyring.items.iter().find(|item| item.0 == id).unwrap();
+                let secret = (
+                    object_path(STAND_IN_SESSION),
+                    Vec::<u8>::new(),
+                    item.2.clone(),
+                    "text/plain".to_string(),
+                );
+                Some((secret,).to_variant())
+            }
+            "Delete" => {
+                let id = item_id(path);
+                keyring.items.retain(|item| item.0 != id);
+                Some((object_path("/"),).to_variant())
+            }
+            _ => None,
+        };
+        invocation.return_value(reply.as_ref());
+    }
+
+    /// Serves the stand-in on `address` from its own thread and context until
+    /// `stop` is set. Returns once the well-known name is owned.
+    fn spawn_stand_in_secret_service(
+        address: String,
+        stop: Arc<AtomicBool>,
+    ) -> thread::JoinHandle<()> {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let context = MainContext::new();
+            context
+                .with_thread_default(|| {
+                    let connection = gio::DBusConnection::for_address_sync(
+                        &address,
+                        gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                            | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                        None,
+                        gio::Cancellable::NONE,
+                    )
+                    .unwrap();
+                    let node = gio::DBusNodeInfo::for_xml(STAND_IN_SECRET_SERVICE).unwrap();
+                    let keyring = Arc::new(Mutex::new(StandInKeyring::default()));
+                    let objects = [
+                        (SECRET_SERVICE_PATH.to_string(), SECRET_SERVICE_IFACE),
+                        (STAND_IN_COLLECTION.to_string(), SECRET_COLLECTION_IFACE),
+                        (STAND_IN_SESSION.to_string(), SECRET_SESSION_IFACE),
+                        (STAND_IN_PROMPT.to_string(), SECRET_PROMPT_IFACE),
+                    ]
+                    .into_iter()
+                    .chain(
+                        (1..5).map(|id| (format!("{STAND_IN_COLLECTION}/{id}"), SECRET_ITEM_IFACE)),
+                    );
+                    for (path, interface) in objects {
+                        let keyring = keyring.clone();
+                        connection
+                            .register_object(
+                                &path,
+                                &node.lookup_interface(interface).unwrap(),
+                                move |connection, _, path, _, method, parameters, invocation| {
+                                    stand_in_method_call(
+                                        &keyring,
+                                        &connection,
+                                        path,
+                                        method,
+                                        &parameters,
+                                        invocation,
+                                    )
+                                },
+                                |_, _, _, _, _| "".to_variant(),
+                                |_, _, _, _, _, _| false,
+                            )
+                            .unwrap();
+                    }
+                    let _owner = gio::bus_own_name_on_connection(
+                        &connection,
+                        SECRET_SERVICE_NAME,
+                        gio::BusNameOwnerFlags::NONE,
+                        move |_, _| {
+                            let _ = ready_tx.send(());
+                        },
+                        |_, _| {},
+                    );
+                    while !stop.load(Ordering::SeqCst) {
+                        context.iteration(false);
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                })
+                .unwrap();
+        });
+        ready_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        handle
+    }
+
+    #[test]
+    fn secret_service_stores_looks_up_and_deletes() {
+        let Some(bus) = TestBus::start() else {
+            eprintln!("skipping: dbus-daemon is not available");
+            return;
+        };
+        env::set_var(SECRET_SERVICE_BUS_ENV, &bus.address);
+        let address = bus.address.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server = spawn_stand_in_secret_service(address, stop.clone());
+
+        let lookup = |service: &SecretService, name: &str| {
+            service
+                .lookup("demo", name)
+                .unwrap()
+                .map(|value| value.to_string())
+        };
+        let service = SecretService::connect().unwrap();
+        assert_eq!(lookup(&service, "sudo"), None);
+        service.store("demo", "sudo", "hunter2").unwrap();
+        service.store("demo", "token", "abc").unwrap();
+        assert_eq!(lookup(&service, "sudo").as_deref(), Some("hunter2"));
+        service.store("demo", "sudo", "hunter3").unwrap();
+        assert_eq!(lookup(&service, "sudo").as_deref(), Some("hunter3"));
+        assert_eq!(lookup(&service, "token").as_deref(), Some("abc"));
+        assert_eq!(service.delete("demo", "sudo"), Ok(true));
+        assert_eq!(service.delete("demo", "sudo"), Ok(false));
+        assert_eq!(lookup(&service, "sudo"), None);
+        assert_eq!(lookup(&service, "token").as_deref(), Some("abc"));
+        drop(service);
+
+        stop.store(true, Ordering::SeqCst);
+        server.join().unwrap();
+    }
+}